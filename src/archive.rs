@@ -0,0 +1,118 @@
+//! Packing a run's manifest and results (and optionally its sources)
+//! into a `.tar.zst`, and unpacking one into another outdir, so runs
+//! from other machines can be compared locally.
+
+use anyhow::{Result, bail};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::{manifest, results};
+
+pub fn archive(run_dir: &Path, with_sources: bool,
+               output: Option<PathBuf>) -> Result<PathBuf> {
+    let label = run_label(run_dir)?;
+    let output = match output {
+        Some(output) => output,
+        None => run_dir.with_file_name(format!("{}.tar.zst", label)),
+    };
+
+    let mut files = vec![manifest::MANIFEST_FILE.to_string()];
+    if run_dir.join(results::RESULTS_FILE).exists() {
+        files.push(results::RESULTS_FILE.to_string());
+    }
+    if with_sources {
+        let mut sources = vec![];
+        for entry in fs::read_dir(run_dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".rs") {
+                sources.push(name);
+            }
+        }
+        sources.sort();
+        files.extend(sources);
+    }
+
+    let out = zstd::Encoder::new(File::create(&output)?, 0)?;
+    let mut tar = tar::Builder::new(out);
+    for file in &files {
+        tar.append_path_with_name(run_dir.join(file),
+                                  Path::new(&label).join(file))?;
+    }
+    tar.into_inner()?.finish()?;
+
+    println!("archived {} files from {} to {}",
+             files.len(), run_dir.display(), output.display());
+
+    Ok(output)
+}
+
+/// Unpacks an archive as a new run in outdir, returning its directory
+pub fn import(outdir: &Path, archive: &Path,
+              run_name: Option<String>) -> Result<PathBuf> {
+    let label = match run_name {
+        Some(name) => name,
+        None => archived_label(archive)?,
+    };
+    let run_dir = outdir.join(&label);
+    if run_dir.exists() {
+        bail!("run {} already exists in {}; pass --run-name to import \
+               it under another name", label, outdir.display());
+    }
+    // Unpacked beside the run first, so a bad archive leaves nothing
+    // that looks like one
+    let partial = outdir.join(format!(".{}.importing", label));
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    fs::create_dir_all(&partial)?;
+    if let Err(e) = unpack(archive, &partial) {
+        fs::remove_dir_all(&partial)?;
+        return Err(e);
+    }
+    fs::rename(&partial, &run_dir)?;
+
+    println!("imported {} as run {}", archive.display(), label);
+
+    Ok(run_dir)
+}
+
+fn unpack(archive: &Path, dir: &Path) -> Result<()> {
+    let mut tar = tar::Archive::new(zstd::Decoder::new(File::open(archive)?)?);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        // Only flat files inside the one run directory are expected
+        let name = match path.file_name() {
+            Some(name) if path.components().count() == 2 => name.to_owned(),
+            _ => bail!("unexpected path {} in {}",
+                       path.display(), archive.display()),
+        };
+        entry.unpack(dir.join(name))?;
+    }
+
+    if !dir.join(manifest::MANIFEST_FILE).exists() {
+        bail!("{} has no manifest", archive.display());
+    }
+
+    Ok(())
+}
+
+fn run_label(run_dir: &Path) -> Result<String> {
+    match run_dir.file_name() {
+        Some(name) => Ok(name.to_string_lossy().into_owned()),
+        None => bail!("{} is not a run directory", run_dir.display()),
+    }
+}
+
+fn archived_label(archive: &Path) -> Result<String> {
+    let mut tar = tar::Archive::new(zstd::Decoder::new(File::open(archive)?)?);
+    let first = match tar.entries()?.next() {
+        Some(entry) => entry?.path()?.into_owned(),
+        None => bail!("{} is empty", archive.display()),
+    };
+
+    match first.components().next() {
+        Some(label) => Ok(label.as_os_str().to_string_lossy().into_owned()),
+        None => bail!("{} has no run directory", archive.display()),
+    }
+}
@@ -0,0 +1,123 @@
+//! Checking a `--vary-args` case's assembly for the calls its loop is
+//! meant to make. Every generated method black-boxes its receiver, and
+//! black_box survives inlining as an empty inline asm block, so each
+//! call left in main's loop shows up either as a call instruction or as
+//! one of those blocks. Fewer of them than the loop has call sites means
+//! the optimizer hoisted some out of the loop or removed them, and the
+//! case's run time measures less than it claims to.
+
+use anyhow::{Result, anyhow, bail};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The calls and inline asm blocks in main's loop, at least one per
+/// call site and one per value the loop rebinds through black_box each
+/// pass
+pub fn check(asm: &Path, sites: usize, values: usize) -> Result<usize> {
+    let text = fs::read_to_string(asm)?;
+    let found = count_in_loop(&text)
+        .ok_or_else(|| anyhow!("can't find main in {}", asm.display()))?;
+    let expected = sites + values;
+    if found < expected {
+        bail!("main's loop in {} has {} calls and black_box blocks for {} \
+               call sites rebinding {} values, so some were hoisted out of it \
+               or eliminated",
+              asm.display(), found, sites, values);
+    }
+
+    Ok(found)
+}
+
+/// None if there's no main. Both manglings name it `...4main`. The
+/// loop is the longest stretch a branch in main jumps back over, which
+/// takes in any loops nested in it; calls before or after it don't
+/// count.
+fn count_in_loop(text: &str) -> Option<usize> {
+    let mut lines = text.lines()
+        .skip_while(|line| !is_main_label(line))
+        .skip(1)
+        .peekable();
+    lines.peek()?;
+    let body: Vec<&str> = lines
+        .take_while(|line| !line.starts_with(".Lfunc_end"))
+        .map(str::trim)
+        .collect();
+
+    let mut labels = HashMap::new();
+    let mut longest = 0..0;
+    for (i, line) in body.iter().enumerate() {
+        if let Some(label) = line.strip_suffix(':') {
+            labels.insert(label, i);
+        } else if let Some(&start) = branch_target(line).and_then(|t| labels.get(t)) {
+            if i - start > longest.len() {
+                longest = start..i;
+            }
+        }
+    }
+
+    Some(body[longest]
+         .iter()
+         .filter(|insn| is_call(insn) || **insn == "#APP" || **insn == "//APP")
+         .count())
+}
+
+fn is_main_label(line: &str) -> bool {
+    let name = match line.strip_suffix(':') {
+        Some(name) if !name.starts_with('.') && !name.starts_with(char::is_whitespace) => name,
+        _ => return false,
+    };
+    // v0 ends the path at main; legacy mangling adds a hash
+    name.ends_with("4main")
+        || name.split_once("4main17h").is_some_and(|(_, hash)| hash.len() == 17)
+}
+
+fn is_call(insn: &str) -> bool {
+    let op = insn.split_whitespace().next().unwrap_or("");
+    matches!(op, "call" | "callq" | "bl" | "blr")
+        || (matches!(op, "jmp" | "jmpq") && insn.contains('*'))
+}
+
+/// The label a direct branch jumps to, on x86 or AArch64
+fn branch_target(insn: &str) -> Option<&str> {
+    let op = insn.split_whitespace().next()?;
+    let branch = (op.starts_with('j') && !insn.contains('*'))
+        || op == "b" || op.starts_with("b.")
+        || matches!(op, "cbz" | "cbnz" | "tbz" | "tbnz");
+    if !branch {
+        return None;
+    }
+    insn.rsplit(|c: char| c == ',' || c.is_whitespace()).next()
+}
+
+#[cfg(test)]
+mod test {
+    use super::count_in_loop;
+
+    #[test]
+    fn counts_only_the_loops_calls_and_blocks() {
+        let asm = "\
+_RNvCs1_4case5other:
+\tcallq\tfoo
+.Lfunc_end0:
+_RNvCs1_4case4main:
+\tcallq\thoisted
+.LBB4_1:
+\tmovq\t%rcx, -8(%rsp)
+\t#APP
+\t#NO_APP
+\tcallq\t*(%rax)
+\tcmpq\t$100000, %rdx
+\tjne\t.LBB4_1
+\tcallq\tafter
+\tjmp\t.LBB4_2
+.LBB4_2:
+\tretq
+.Lfunc_end1:
+main:
+\tcallq\tstd_main
+";
+        assert_eq!(count_in_loop(asm), Some(2));
+        assert_eq!(count_in_loop("main:\n\tretq\n"), None);
+    }
+}
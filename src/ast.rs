@@ -0,0 +1,142 @@
+//! The generated programs' items as syntax trees. The generators build
+//! them as tokens with `quote!`, so each is valid Rust as it's built,
+//! and they're printed with prettyplease. Transforms apply to the
+//! trees rather than the text, like the attributes --no-inline adds to
+//! each fn. main is still written by the `Code` builder, and takes the
+//! fragments it shares with the items, like a call site's dispatch, as
+//! prettyplease prints them.
+
+use anyhow::{Result, anyhow};
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::quote;
+use syn::{Attribute, File, ImplItem, Item, LitInt, ReturnType, Type};
+
+/// The items in `tokens`, or why they aren't Rust
+pub fn items(tokens: TokenStream) -> Result<Vec<Item>> {
+    let file: File = syn::parse2(tokens)?;
+    Ok(file.items)
+}
+
+/// A number as the generators write it, with no suffix
+pub fn num(n: u32) -> Literal {
+    Literal::u32_unsuffixed(n)
+}
+
+/// A number with its type after an underscore, like `3_u32`
+pub fn suffixed(n: u64, ty: &str) -> LitInt {
+    LitInt::new(&format!("{}_{}", n, ty), Span::call_site())
+}
+
+/// A number in hex padded to 16 digits, like `0x00000000000000ff`
+pub fn hex(n: u64) -> LitInt {
+    LitInt::new(&format!("{:#018x}", n), Span::call_site())
+}
+
+/// A type as main's lines write it
+pub fn type_text(ty: &Type) -> String {
+    let alias = render(vec![syn::parse_quote!(type T = #ty;)]);
+    alias.trim_end()
+        .strip_prefix("type T = ").and_then(|s| s.strip_suffix(';'))
+        .expect("a type alias prints as one")
+        .to_string()
+}
+
+/// What a fn returns as main's lines write it after its parameters,
+/// like ` -> u8`
+pub fn ret_text(ret: &ReturnType) -> String {
+    match ret {
+        ReturnType::Default => String::new(),
+        ReturnType::Type(_, ty) => format!(" -> {}", type_text(ty)),
+    }
+}
+
+/// Statements as main's lines write them, at no indentation of their
+/// own, or why they aren't Rust
+pub fn stmts_text(stmts: TokenStream) -> Result<String> {
+    let f = render(items(quote!(fn f() { #stmts }))?);
+    let body = f.strip_prefix("fn f() {\n").and_then(|s| s.strip_suffix("}\n"))
+        .ok_or_else(|| anyhow!("statements `{}` don't print as a fn body", stmts))?;
+
+    Ok(body.lines()
+        .map(|line| line.strip_prefix("    ").unwrap_or(line))
+        .map(|line| format!("{}\n", line))
+        .collect())
+}
+
+/// Adds `attr` to each fn among the items, and each method of their
+/// impls
+pub fn inject_attr(items: &mut [Item], attr: &Attribute) {
+    for item in items {
+        match item {
+            Item::Fn(f) => f.attrs.push(attr.clone()),
+            Item::Impl(imp) => {
+                for item in &mut imp.items {
+                    if let ImplItem::Fn(f) = item {
+                        f.attrs.push(attr.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The items as source, one after another
+pub fn render(items: Vec<Item>) -> String {
+    prettyplease::unparse(&File { shebang: None, attrs: vec![], items })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{inject_attr, items, render, stmts_text, type_text};
+    use quote::quote;
+    use syn::parse_quote;
+
+    #[test]
+    fn injects_into_fns_and_methods() {
+        let call = quote!(v.do_io_m(););
+        let mut built = items(quote! {
+            trait Io { fn do_io_m(&self); }
+            struct T0(u8);
+            impl Io for T0 { fn do_io_m(&self) {} }
+            fn do_io_f0<T: Io>(v: &T) where T: Sized { #call }
+        }).unwrap();
+        inject_attr(&mut built, &parse_quote!(#[inline(never)]));
+        assert_eq!(render(built), "\
+trait Io {
+    fn do_io_m(&self);
+}
+struct T0(u8);
+impl Io for T0 {
+    #[inline(never)]
+    fn do_io_m(&self) {}
+}
+#[inline(never)]
+fn do_io_f0<T: Io>(v: &T)
+where
+    T: Sized,
+{
+    v.do_io_m();
+}
+");
+        assert!(items(quote!(fn)).is_err());
+    }
+
+    #[test]
+    fn prints_fragments_for_main() {
+        assert_eq!(type_text(&parse_quote!(dyn Io<Out = u8>)), "dyn Io<Out = u8>");
+        assert_eq!(stmts_text(quote! {
+            match tag {
+                0 => { T0(0_u8).do_io_m(); }
+                _ => unreachable!(),
+            }
+        }).unwrap(), "\
+match tag {
+    0 => {
+        T0(0_u8).do_io_m();
+    }
+    _ => unreachable!(),
+}
+");
+    }
+}
@@ -0,0 +1,218 @@
+//! Parsing of sweep axis specs.
+//!
+//! An axis is a comma-separated list of terms, where each term is
+//! either a single value (`8`) or an inclusive range with an optional
+//! step (`1..64`, `1..64:4`). A step written as `xN` advances
+//! multiplicatively, so `1..64:x2` is `1,2,4,...,64`. Terms combine,
+//! as in `--types 0..32:8,64`.
+//!
+//! The end of a range is always included even when the step doesn't
+//! land on it, so `1..10:4` is `1,5,9,10`. A range may leave out its
+//! start (`..64:8`), in which case it starts at the axis minimum set
+//! with `with_min`, or zero.
+
+use anyhow::{Error, Result, anyhow, bail};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Axis {
+    terms: Vec<Term>,
+    min: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Term {
+    start: Option<u32>,
+    end: u32,
+    step: Step,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Linear(u32),
+    Geometric(u32),
+}
+
+impl Axis {
+    pub fn with_min(mut self, min: u32) -> Axis {
+        self.min = min;
+        self
+    }
+
+    pub fn values(&self) -> Vec<u32> {
+        let mut values = vec![];
+
+        for term in &self.terms {
+            let start = term.start.unwrap_or(self.min);
+            if start > term.end {
+                continue;
+            }
+            match term.step {
+                Step::Linear(step) => {
+                    values.extend((start..=term.end).step_by(step as usize));
+                }
+                Step::Geometric(factor) => {
+                    values.extend(geometric(start, term.end, factor));
+                }
+            }
+            if values.last() != Some(&term.end) {
+                values.push(term.end);
+            }
+        }
+
+        values.sort();
+        values.dedup();
+        values
+    }
+
+    pub fn contains(&self, v: u32) -> bool {
+        self.values().contains(&v)
+    }
+}
+
+impl FromStr for Axis {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Axis> {
+        let mut terms = vec![];
+
+        for term in s.split(',') {
+            let term = parse_term(term.trim())
+                .map_err(|e| anyhow!("bad axis term '{}': {}", term, e))?;
+            terms.push(term);
+        }
+
+        Ok(Axis { terms, min: 0 })
+    }
+}
+
+fn parse_term(term: &str) -> Result<Term> {
+    if term.is_empty() {
+        bail!("empty term");
+    }
+
+    let (range, step) = match term.find(':') {
+        Some(i) => (&term[..i], Some(&term[i + 1..])),
+        None => (term, None),
+    };
+
+    let (start, end) = match range.find("..") {
+        Some(i) => {
+            let start = &range[..i];
+            let end = range[i + 2..].trim_start_matches('=');
+            let start = if start.is_empty() {
+                None
+            } else {
+                Some(start.parse::<u32>()?)
+            };
+            (start, end.parse::<u32>()?)
+        }
+        None => {
+            if step.is_some() {
+                bail!("a step needs a range");
+            }
+            let v = range.parse::<u32>()?;
+            (Some(v), v)
+        }
+    };
+
+    if start.is_some_and(|start| start > end) {
+        bail!("range start is greater than its end");
+    }
+
+    let step = match step {
+        Some(step) if step.starts_with('x') => {
+            let factor = step[1..].parse::<u32>()?;
+            if factor < 2 {
+                bail!("geometric factor must be at least 2");
+            }
+            Step::Geometric(factor)
+        }
+        Some(step) => {
+            let step = step.parse::<u32>()?;
+            if step == 0 {
+                bail!("step must be greater than zero");
+            }
+            Step::Linear(step)
+        }
+        None => Step::Linear(1),
+    };
+
+    Ok(Term { start, end, step })
+}
+
+fn geometric(start: u32, end: u32, factor: u32) -> Vec<u32> {
+    let mut values = vec![];
+    let mut v = start;
+
+    // Zero never grows, so a geometric axis from zero continues from one
+    if v == 0 {
+        values.push(0);
+        v = 1;
+    }
+
+    while v <= end {
+        values.push(v);
+        v = match v.checked_mul(factor) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod test {
+    use super::Axis;
+
+    fn values(s: &str) -> Vec<u32> {
+        s.parse::<Axis>().unwrap().values()
+    }
+
+    #[test]
+    fn single_values_and_lists() {
+        assert_eq!(values("8"), [8]);
+        assert_eq!(values("16,1,4,4"), [1, 4, 16]);
+    }
+
+    #[test]
+    fn ranges_are_inclusive() {
+        assert_eq!(values("0..3"), [0, 1, 2, 3]);
+        assert_eq!(values("2..=4"), [2, 3, 4]);
+        assert_eq!(values("5..5"), [5]);
+    }
+
+    #[test]
+    fn linear_steps_include_the_end() {
+        assert_eq!(values("0..8:4"), [0, 4, 8]);
+        assert_eq!(values("1..10:4"), [1, 5, 9, 10]);
+    }
+
+    #[test]
+    fn geometric_steps_include_the_end() {
+        assert_eq!(values("1..64:x2"), [1, 2, 4, 8, 16, 32, 64]);
+        assert_eq!(values("0..10:x3"), [0, 1, 3, 9, 10]);
+    }
+
+    #[test]
+    fn open_starts_use_the_minimum() {
+        let axis = "..10:4".parse::<Axis>().unwrap();
+        assert_eq!(axis.values(), [0, 4, 8, 10]);
+        assert_eq!(axis.clone().with_min(3).values(), [3, 7, 10]);
+        assert_eq!(axis.with_min(12).values(), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn min_does_not_move_explicit_starts() {
+        let axis = "1..4,..2".parse::<Axis>().unwrap().with_min(2);
+        assert_eq!(axis.values(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn bad_specs() {
+        for spec in &["", "a", "1..", "4..2", "1..8:0", "1..8:x1", "3:2", "1,,2"] {
+            assert!(spec.parse::<Axis>().is_err(), "{}", spec);
+        }
+    }
+}
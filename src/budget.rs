@@ -0,0 +1,83 @@
+//! Sweeps under a `--time-budget`. They take any cases the user puts
+//! first, then the rest smallest first, so whatever the budget allows
+//! covers the cheap end of the grid, and stop starting cases once it's
+//! spent. What they covered is drawn as a map of the grid.
+
+use std::collections::BTreeSet;
+
+use crate::estimate::{duration, units};
+use crate::manifest::CaseEntry;
+use crate::recommend::Millis;
+use crate::replay::CaseId;
+
+/// Puts the cases in the order a budgeted sweep takes them
+pub fn order(cases: &mut [CaseEntry], priority: &[CaseId]) {
+    cases.sort_by_key(|c| {
+        let rank = priority.iter()
+            .position(|p| (p.num_types, p.num_fns) == (c.num_types, c.num_fns));
+        (rank.unwrap_or(usize::MAX), units(c.num_types, c.num_fns), c.num_types, c.num_fns)
+    });
+}
+
+/// Which of the sweep's cases were finished within the budget, with
+/// types down and fns across
+pub fn report(cases: &[CaseEntry], finished: &[(u32, u32)], Millis(budget): Millis) {
+    let types: BTreeSet<u32> = cases.iter().map(|c| c.num_types).collect();
+    let fns: BTreeSet<u32> = cases.iter().map(|c| c.num_fns).collect();
+    let swept: BTreeSet<(u32, u32)> = cases.iter().map(|c| (c.num_types, c.num_fns)).collect();
+
+    println!();
+    println!("the {} time budget covered {} of {} cases:",
+             duration(budget as f64), finished.len(), cases.len());
+    let width = fns.iter().map(|f| f.to_string().len()).max().unwrap_or(1) + 1;
+    print!("{:>10}", "types\\fns");
+    for f in &fns {
+        print!("{:>width$}", f, width = width);
+    }
+    println!();
+    for &t in &types {
+        print!("{:>10}", t);
+        for &f in &fns {
+            let cell = if finished.contains(&(t, f)) {
+                "#"
+            } else if swept.contains(&(t, f)) {
+                "."
+            } else {
+                ""
+            };
+            print!("{:>width$}", cell, width = width);
+        }
+        println!();
+    }
+    if finished.len() < cases.len() {
+        println!("rerun the phase with --resume to continue");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::order;
+    use crate::manifest::{CaseEntry, CaseStatus};
+    use crate::replay::CaseId;
+    use crate::GenOpts;
+    use clap::Parser;
+
+    #[test]
+    fn priority_then_smallest() {
+        #[derive(Parser)]
+        struct Opts {
+            #[command(flatten)]
+            opts: GenOpts,
+        }
+        let entry = |num_types, num_fns| CaseEntry {
+            num_types, num_fns,
+            gen_opts: Opts::parse_from(["gen"]).opts,
+            status: CaseStatus::Generated,
+            command: None,
+        };
+        let mut cases = vec![entry(1, 8), entry(8, 1), entry(2, 2), entry(16, 16)];
+        order(&mut cases, &[CaseId { num_types: 16, num_fns: 16 }]);
+        let ordered: Vec<_> = cases.iter().map(|c| (c.num_types, c.num_fns)).collect();
+        assert_eq!(ordered, [(16, 16), (2, 2), (1, 8), (8, 1)]);
+    }
+}
@@ -0,0 +1,79 @@
+//! Sweeping `-Ccodegen-units` for one stored case. How rustc splits a
+//! crate into codegen units decides which trait methods can be inlined
+//! into their callers and how many copies of vtables and generic fns
+//! get emitted, so the static and dynamic variants can respond to it
+//! differently. Each setting is built into its own run directory beside
+//! the original, like a replay.
+
+use anyhow::{Result, anyhow, bail};
+use std::path::Path;
+
+use crate::replay::{self, CaseId};
+use crate::results::{self, CaseResults};
+use crate::{manifest, CaseConfig, Dispatch, Lang, Runner};
+
+pub fn cgu_sweep(outdir: &Path, id: CaseId, units: &[u32]) -> Result<()> {
+    let stored_config = CaseConfig {
+        outdir: outdir.to_owned(),
+        num_types: id.num_types,
+        num_fns: id.num_fns,
+    };
+    let case = manifest::find_case(&stored_config)?;
+    if case.gen_opts.lang != Lang::Rust {
+        bail!("cgu-sweep is for rustc; case {} types / {} fns is C++",
+              id.num_types, id.num_fns);
+    }
+    let stored = results::find_case(&stored_config)?
+        .filter(|s| s.compile.is_some())
+        .ok_or_else(|| anyhow!("case {} types / {} fns hasn't been compiled",
+                               id.num_types, id.num_fns))?;
+    let compile = stored.compile.as_ref().expect("filtered on compile results");
+    let (runner, repeat) = match stored.run {
+        Some(ref run) => (replay::stored_runner(run)?, replay::stored_repeat(run)),
+        None => (Runner::Local { mem_limit: None, cold_cache: false }, 1),
+    };
+
+    let label = outdir.file_name().expect("run dirs have names").to_string_lossy();
+    let mut swept = vec![];
+    for &n in units {
+        let dir = outdir.with_file_name(format!("{}-cgu{}-{:04}-{:04}", label, n,
+                                                id.num_types, id.num_fns));
+        crate::fresh_derived_run(&dir, outdir)?;
+        let config = CaseConfig {
+            outdir: dir,
+            num_types: id.num_types,
+            num_fns: id.num_fns,
+        };
+        let mut opts = compile.opts.clone();
+        opts.codegen_units = Some(n);
+
+        println!("codegen units: {}", n);
+        crate::gen_one_case(config.clone(), case.gen_opts.clone())?;
+        crate::compile_one_case(config.clone(), opts)?;
+        crate::run_one_case(config.clone(), &runner, repeat, 0)?;
+        let results = results::find_case(&config)?
+            .ok_or_else(|| anyhow!("codegen units {} recorded no results", n))?;
+        swept.push((n, results));
+    }
+
+    println!();
+    print_table(&swept);
+
+    Ok(())
+}
+
+fn print_table(swept: &[(u32, CaseResults)]) {
+    println!("{:<8}{:<10}{:>12}{:>12}{:>16}", "cgus", "variant",
+             "run-time-ms", "bin-size", "compile-time-ms");
+    for (n, case) in swept {
+        for dispatch in case.variants() {
+            let compile = case.compile.as_ref().and_then(|c| c.variant(dispatch));
+            let run = case.run.as_ref().and_then(|r| r.variant(dispatch));
+            let cell = |v: Option<u64>| v.map_or("-".to_string(), |v| v.to_string());
+            println!("{:<8}{:<10}{:>12}{:>12}{:>16}", n, dispatch.to_string(),
+                     cell(run.map(|r| r.run_time_ms)),
+                     cell(compile.map(|c| c.bin_size)),
+                     cell(compile.map(|c| c.compile_time_ms)));
+        }
+    }
+}
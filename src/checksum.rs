@@ -0,0 +1,51 @@
+//! Checking that a `--checksum` case's programs all made the same
+//! calls. Each type's method adds a number of its own to a sum the
+//! program prints at exit, so programs that call different methods, or
+//! the same ones a different number of times, print different sums and
+//! their run times aren't comparable.
+
+use anyhow::{Result, anyhow, bail};
+use std::path::Path;
+
+use crate::Dispatch;
+
+/// What the programs print before their sum
+pub static PREFIX: &str = "checksum: ";
+
+/// The sum every variant's program printed, from the one launch of
+/// each whose output was kept
+pub fn verify(sums: &[(Dispatch, u64)]) -> Result<u64> {
+    let (first, sum) = sums[0];
+    if let Some((other, other_sum)) = sums.iter().find(|(_, s)| *s != sum) {
+        bail!("the {} program's checksum is {:016x} but the {} one's is {:016x}, \
+               so they don't make the same calls",
+              first, sum, other, other_sum);
+    }
+
+    Ok(sum)
+}
+
+/// The sum in a program's captured stdout
+pub fn read(bin: &Path, stdout: &str) -> Result<u64> {
+    parse(stdout).ok_or_else(|| anyhow!("{} didn't print a checksum", bin.display()))
+}
+
+fn parse(out: &str) -> Option<u64> {
+    out.lines()
+        .rev()
+        .find_map(|line| line.strip_prefix(PREFIX))
+        .and_then(|sum| u64::from_str_radix(sum.trim(), 16).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn parses_the_last_checksum() {
+        assert_eq!(parse("checksum: 00000000000000ff\n"), Some(0xff));
+        assert_eq!(parse("checksum: 1\nother\nchecksum: 2\n"), Some(2));
+        assert_eq!(parse("checksum: zz\n"), None);
+        assert_eq!(parse(""), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Command lines recorded with the results, written so they can be
+//! pasted into a shell to reproduce a measurement.
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// Environment that changes what rustc accepts or which toolchain runs
+static TOOL_ENV: &[&str] = &["RUSTC_BOOTSTRAP", "RUSTUP_TOOLCHAIN"];
+
+/// The command line this process was started with
+pub fn invocation() -> String {
+    let args: Vec<_> = env::args_os().collect();
+    in_current_dir(&join(&args))
+}
+
+/// A compiler or linker command as run by `run_tool`. In a container
+/// it runs in the run directory, which is mounted as the working
+/// directory.
+pub fn tool(program: &str, dir: &Path, args: &[impl AsRef<OsStr>],
+            container: Option<&str>) -> String {
+    let mut line = String::new();
+    for assignment in tool_env() {
+        line.push_str(&assignment);
+        line.push(' ');
+    }
+    line.push_str(&command(program, args));
+
+    match container {
+        Some(image) => format!("cd {} && {}  # in {}",
+                               quote(dir.as_os_str()), line, image),
+        None => in_current_dir(&line),
+    }
+}
+
+/// The toolchain environment this process has, as `VAR=value`
+pub fn tool_env() -> Vec<String> {
+    TOOL_ENV.iter()
+        .filter_map(|var| {
+            let value = env::var(var).ok()?;
+            Some(format!("{}={}", var, quote(value.as_ref())))
+        })
+        .collect()
+}
+
+/// A command with its arguments quoted for a shell
+pub fn command(program: &str, args: &[impl AsRef<OsStr>]) -> String {
+    format!("{} {}", quote(program.as_ref()), join(args))
+}
+
+fn in_current_dir(line: &str) -> String {
+    match env::current_dir() {
+        Ok(dir) => format!("cd {} && {}", quote(dir.as_os_str()), line),
+        Err(_) => line.to_string(),
+    }
+}
+
+fn join(args: &[impl AsRef<OsStr>]) -> String {
+    args.iter()
+        .map(|a| quote(a.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let plain = !arg.is_empty() && arg.chars().all(|c| {
+        c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c)
+    });
+
+    if plain {
+        arg.into_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
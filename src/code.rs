@@ -0,0 +1,159 @@
+//! Building the generated programs' source. Lines are written at the
+//! current depth of nesting, and blocks open and close around what's
+//! written inside them, so the generators nest matches, loops and
+//! closures without padding each line by hand. The fixed headers are
+//! written through `fmt::Write`, as they are, and the items built by
+//! `ast` as prettyplease prints them.
+
+use std::fmt::{self, Display};
+
+/// Spaces per level of nesting
+const INDENT: &str = "    ";
+
+#[derive(Default)]
+pub struct Code {
+    out: String,
+    depth: usize,
+}
+
+impl Code {
+    pub fn new() -> Code {
+        Code::default()
+    }
+
+    pub fn line(&mut self, line: impl Display) {
+        self.pad();
+        fmt::Write::write_fmt(&mut self.out, format_args!("{}\n", line))
+            .expect("writing to a String");
+    }
+
+    pub fn blank(&mut self) {
+        self.out.push('\n');
+    }
+
+    /// Writes `text`'s lines at this depth, keeping their own
+    /// indentation within it
+    pub fn lines(&mut self, text: &str) {
+        for line in text.lines() {
+            if line.is_empty() {
+                self.blank();
+            } else {
+                self.line(line);
+            }
+        }
+    }
+
+    /// Starts a block, like `head {`, whose contents go a level in. A
+    /// block in a call's arguments starts like `head({`, and a bare one
+    /// as just `{`.
+    pub fn open(&mut self, head: impl Display) {
+        let head = head.to_string();
+        if head.is_empty() || head.ends_with('(') {
+            self.line(format_args!("{}{{", head));
+        } else {
+            self.line(format_args!("{} {{", head));
+        }
+        self.depth += 1;
+    }
+
+    pub fn close(&mut self) {
+        self.close_with("");
+    }
+
+    /// Ends a block with `tail` after its brace, like `});`
+    pub fn close_with(&mut self, tail: &str) {
+        self.depth -= 1;
+        self.line(format_args!("}}{}", tail));
+    }
+
+    /// Ends a block and starts the next in the same chain, like
+    /// `} else {`
+    pub fn reopen(&mut self, head: impl Display) {
+        self.depth -= 1;
+        self.open(format_args!("}} {}", head));
+    }
+
+    /// Writes lines a level in, outside of any block, as for the items
+    /// of an array
+    pub fn indented(&mut self, body: impl FnOnce(&mut Code)) {
+        self.depth += 1;
+        body(self);
+        self.depth -= 1;
+    }
+
+    pub fn block(&mut self, head: impl Display, body: impl FnOnce(&mut Code)) {
+        self.open(head);
+        body(self);
+        self.close();
+    }
+
+    pub fn fn_item(&mut self, attrs: &str, sig: impl Display, body: impl FnOnce(&mut Code)) {
+        if !attrs.is_empty() {
+            self.line(attrs);
+        }
+        self.block(format_args!("fn {}", sig), body);
+    }
+
+    pub fn impl_block(&mut self, trait_: &str, ty: impl Display, body: impl FnOnce(&mut Code)) {
+        self.block(format_args!("impl {} for {}", trait_, ty), body);
+    }
+
+    pub fn for_loop(&mut self, pat: &str, iter: impl Display, body: impl FnOnce(&mut Code)) {
+        self.block(format_args!("for {} in {}", pat, iter), body);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn pad(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+}
+
+impl fmt::Write for Code {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.out.push_str(s);
+        Ok(())
+    }
+}
+
+/// A call, like `callee(a, b)`
+pub fn call(callee: impl Display, args: &[&dyn Display]) -> String {
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    format!("{}({})", callee, args.join(", "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Code;
+
+    #[test]
+    fn nests_blocks() {
+        let mut code = Code::new();
+        code.fn_item("#[inline(never)]", "f(tag: u32)", |c| {
+            c.open("if tag == 0");
+            c.lines("a();\nb();");
+            c.reopen("else");
+            c.for_loop("_", "0..2", |c| c.lines("x();\n\n    y();"));
+            c.close();
+        });
+        assert_eq!(code.finish(), "\
+#[inline(never)]
+fn f(tag: u32) {
+    if tag == 0 {
+        a();
+        b();
+    } else {
+        for _ in 0..2 {
+            x();
+
+                y();
+        }
+    }
+}
+");
+    }
+}
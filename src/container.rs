@@ -0,0 +1,141 @@
+//! Running rustc and case binaries inside a Docker or Podman image, so
+//! results can be reproduced with a pinned toolchain and libc no matter
+//! what the host has installed. The run directory is mounted at the
+//! same path inside the container, and commands are timed inside it so
+//! container startup isn't measured.
+
+use anyhow::{Result, anyhow, bail};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub struct Container {
+    engine: &'static str,
+    image: String,
+}
+
+// Prints the time taken by "$0" "$@" in nanoseconds, keeping the
+// command's own output off stdout
+static TIMED_SCRIPT: &str = "\
+start=$(date +%s%N) && \"$0\" \"$@\" >&2 && end=$(date +%s%N) && \
+echo $((end - start))";
+
+// Prints the time taken by a case binary "$0" in nanoseconds, throwing
+// away its output
+static CASE_SCRIPT: &str = "\
+start=$(date +%s%N) && \"$0\" >/dev/null && end=$(date +%s%N) && \
+echo $((end - start))";
+
+// The same, but printing the binary's output after the time
+static CAPTURED_CASE_SCRIPT: &str = "\
+start=$(date +%s%N) && out=$(\"$0\") && end=$(date +%s%N) && \
+echo $((end - start)) && printf \"%s\\n\" \"$out\"";
+
+/// The script that times a case binary, printing its output after the
+/// time if `capture`
+pub fn case_script(capture: bool) -> &'static str {
+    if capture { CAPTURED_CASE_SCRIPT } else { CASE_SCRIPT }
+}
+
+/// Splits what a case script printed into the time and, if `capture`,
+/// the binary's own output
+pub fn parse_case_output(out: &str, capture: bool) -> Option<(Duration, Option<String>)> {
+    let (time, rest) = out.split_once('\n').unwrap_or((out, ""));
+    let nanos = time.trim().parse::<u64>().ok()?;
+
+    Some((Duration::from_nanos(nanos), Some(rest.to_string()).filter(|_| capture)))
+}
+
+impl Container {
+    pub fn new(image: &str) -> Result<Container> {
+        Ok(Container {
+            engine: engine()?,
+            image: image.to_string(),
+        })
+    }
+
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    /// Runs `program` in `dir` inside the container and returns how
+    /// long it took
+    pub fn time(&self, dir: &Path, program: impl AsRef<OsStr>,
+                args: &[OsString]) -> Result<Duration> {
+        let out = self.run(dir, TIMED_SCRIPT, program.as_ref(), args)?;
+        let nanos = out.trim().parse::<u64>()
+            .map_err(|_| anyhow!("bad timing from container {}: {:?}",
+                                 self.image, out))?;
+
+        Ok(Duration::from_nanos(nanos))
+    }
+
+    /// Runs a case binary inside the container and returns how long it
+    /// took, and its output if `capture`
+    pub fn time_case(&self, bin: &Path, capture: bool) -> Result<(Duration, Option<String>)> {
+        let name = bin.file_name().expect("bin paths have names");
+        let dir = bin.parent().expect("bin paths have parents");
+        let program = Path::new(".").join(name);
+        let out = self.run(dir, case_script(capture), program.as_os_str(), &[])?;
+
+        parse_case_output(&out, capture)
+            .ok_or_else(|| anyhow!("bad timing from container {}: {:?}", self.image, out))
+    }
+
+    /// Runs a script in `dir` inside the container with the program and
+    /// its arguments as its own, returning what it printed
+    fn run(&self, dir: &Path, script: &str, program: &OsStr,
+           args: &[OsString]) -> Result<String> {
+        let dir = fs::canonicalize(dir)?;
+        let mut mount = dir.clone().into_os_string();
+        mount.push(":");
+        mount.push(&dir);
+
+        let mut cmd = Command::new(self.engine);
+        cmd.args(["run", "--rm", "-v"]).arg(mount).arg("-w").arg(&dir);
+        // Podman maps root to the invoking user already; docker would
+        // leave root-owned files in the run directory
+        if self.engine == "docker" {
+            let meta = fs::metadata(&dir)?;
+            cmd.arg("--user").arg(format!("{}:{}", meta.uid(), meta.gid()));
+        }
+        let output = cmd
+            .arg(&self.image)
+            .args(["sh", "-c", script])
+            .arg(program)
+            .args(args)
+            .stderr(Stdio::inherit())
+            .output()?;
+
+        if !output.status.success() {
+            bail!("command failed in container {}", self.image);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Prefers podman, which doesn't need a daemon, falling back to docker
+pub fn engine() -> Result<&'static str> {
+    static ENGINE: OnceLock<Option<&'static str>> = OnceLock::new();
+
+    let engine = ENGINE.get_or_init(|| {
+        ["podman", "docker"].iter().copied().find(|engine| {
+            Command::new(engine)
+                .arg("--version")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|s| s.success())
+        })
+    });
+
+    match *engine {
+        Some(engine) => Ok(engine),
+        None => bail!("--container needs podman or docker installed"),
+    }
+}
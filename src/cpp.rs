@@ -0,0 +1,282 @@
+//! Generation and compilation of the C++ counterparts of each case,
+//! with templates standing in for generics and virtual functions for
+//! trait objects. The programs have the same shape as the Rust ones so
+//! the two languages can be compared case for case.
+
+use anyhow::{Result, anyhow, bail};
+use std::ffi::OsString;
+use std::fs;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+
+use crate::code::Code;
+use crate::{hash, run_tool, tool_command, tool_paths, Backend, BuildTime,
+            CaseConfig, CompileOpts, GenOpts, Linking, Lto, RelocModel, test_loops};
+
+static HEADER: &str = "
+#include <cstdint>
+
+template <typename T>
+void black_box(T const& v) { asm volatile(\"\" : : \"r\"(&v) : \"memory\"); }
+
+struct Io { virtual void do_io_m() const = 0; };
+";
+
+// Types are final so that calls on them in the static program are
+// direct, as they are for Rust generics
+macro_rules! type_template { () => { "
+struct T{num} final : Io {{
+    {types} v;
+    {inlining}void do_io_m() const override {{
+        black_box(this);
+        if ({no_dedup}) {{
+            black_box({num});
+        }}
+    }}
+}};
+"
+}}
+
+macro_rules! fn_static_template { () => { "
+template <typename T>
+{inlining}void do_io_f{num}(T const& v) {{
+    v.do_io_m();
+    if ({no_dedup}) {{
+        black_box({num});
+    }}
+}}
+"
+}}
+
+macro_rules! fn_dynamic_template { () => { "
+{inlining}void do_io_f{num}(Io const& v) {{
+    v.do_io_m();
+    if ({no_dedup}) {{
+        black_box({num});
+    }}
+}}
+"
+}}
+
+pub fn gen_static(config: &CaseConfig, path: &Path, opts: GenOpts) -> Result<()> {
+    gen_case(config, path, false, opts)
+}
+
+pub fn gen_dynamic(config: &CaseConfig, path: &Path, opts: GenOpts) -> Result<()> {
+    gen_case(config, path, true, opts)
+}
+
+fn gen_case(config: &CaseConfig, path: &Path,
+            dynamic: bool, opts: GenOpts) -> Result<()> {
+    assert!(path.extension().expect("") == "cpp");
+    let dir = path.parent().expect("directory");
+    fs::create_dir_all(dir)?;
+    let mut file = Code::new();
+    let inlining = inline_str(&opts);
+
+    file.line(format_args!("// types = {}, fns = {}", config.num_types, config.num_fns));
+    file.line(hash::header_line(&hash::case_hash(config, &opts)?));
+
+    writeln!(file, "{}", HEADER)?;
+
+    for type_num in 0..config.num_types {
+        writeln!(file, type_template!(),
+                 num = type_num, types = "uint8_t",
+                 inlining = inlining,
+                 no_dedup = opts.no_dedup)?;
+    }
+
+    for fn_num in 0..config.num_fns {
+        if dynamic {
+            writeln!(file, fn_dynamic_template!(),
+                     num = fn_num, inlining = inlining,
+                     no_dedup = opts.no_dedup)?;
+        } else {
+            writeln!(file, fn_static_template!(),
+                     num = fn_num, inlining = inlining,
+                     no_dedup = opts.no_dedup)?;
+        }
+    }
+
+    // Methods are kept by the vtables, which the types' constructors
+    // reference
+    if opts.keep_symbols {
+        for fn_num in 0..config.num_fns {
+            if dynamic {
+                file.line(format_args!("__attribute__((used)) static void \
+                                        (*const keep_f{num})(Io const&) = &do_io_f{num};",
+                                       num = fn_num));
+                continue;
+            }
+            for type_num in 0..config.num_types {
+                file.line(format_args!("__attribute__((used)) static void \
+                                        (*const keep_f{f}_t{t})(T{t} const&) \
+                                        = &do_io_f{f}<T{t}>;",
+                                       f = fn_num, t = type_num));
+            }
+        }
+    }
+
+    file.blank();
+    file.open("int main()");
+
+    for type_num in 0..config.num_types {
+        file.line(format_args!("static T{num} V{num};", num = type_num));
+    }
+    file.blank();
+
+    file.block(format_args!("for (long i = 0; i < {}; i++)", test_loops(&opts)), |c| {
+        if config.num_types == 0 {
+            c.line("black_box(0);");
+        } else if config.num_fns == 0 {
+            for type_num in 0..config.num_types {
+                if dynamic {
+                    c.line(format_args!("static_cast<Io const&>(V{}).do_io_m();", type_num));
+                } else {
+                    c.line(format_args!("V{}.do_io_m();", type_num));
+                }
+            }
+        } else if !opts.predictable {
+            for fn_num in 0..config.num_fns {
+                for type_num in 0..config.num_types {
+                    c.line(format_args!("do_io_f{}(V{});", fn_num, type_num));
+                }
+                c.blank();
+            }
+        } else {
+            for type_num in 0..config.num_types {
+                for fn_num in 0..config.num_fns {
+                    c.line(format_args!("do_io_f{}(V{});", fn_num, type_num));
+                }
+                c.blank();
+            }
+        }
+    });
+    file.close();
+
+    fs::write(path, file.finish())?;
+
+    Ok(())
+}
+
+fn inline_str(opts: &GenOpts) -> &'static str {
+    if opts.no_inline {
+        "__attribute__((noinline)) "
+    } else {
+        ""
+    }
+}
+
+pub fn run_cxx_bin(src: &Path, out: &Path, opts: &CompileOpts) -> Result<BuildTime> {
+    run_cxx(src, out, false, opts)
+}
+
+pub fn run_cxx_asm(src: &Path, out: &Path, opts: &CompileOpts) -> Result<BuildTime> {
+    run_cxx(src, out, true, opts)
+}
+
+fn run_cxx(src: &Path, out: &Path, asm: bool, opts: &CompileOpts) -> Result<BuildTime> {
+    let dir = src.parent().expect("src paths have parents");
+    let (src, out) = tool_paths(src, out, opts);
+
+    if !opts.split_link || asm {
+        let args = cxx_args(&src, &out, asm, opts)?;
+        let total = run_tool(&opts.cxx, dir, &args, opts)?;
+        let commands = vec![tool_command(&opts.cxx, dir, &args, opts)];
+        return Ok(BuildTime { total, link: None, commands });
+    }
+
+    let obj = out.with_extension("o");
+    let mut args = cxx_args(&src, &obj, false, opts)?;
+    args.push("-c".into());
+    let codegen = run_tool(&opts.cxx, dir, &args, opts)?;
+
+    let mut link_args = cxx_args(&obj, &out, false, opts)?;
+    // Not a C++ source any more
+    link_args.retain(|a| a != "-std=c++17");
+    let link = run_tool(&opts.cxx, dir, &link_args, opts)?;
+    fs::remove_file(dir.join(obj.file_name().expect("obj paths have names")))?;
+
+    Ok(BuildTime {
+        total: codegen + link,
+        link: Some(link),
+        commands: vec![tool_command(&opts.cxx, dir, &args, opts),
+                       tool_command(&opts.cxx, dir, &link_args, opts)],
+    })
+}
+
+pub(crate) fn cxx_args(src: &Path, out: &Path, asm: bool, opts: &CompileOpts) -> Result<Vec<OsString>> {
+    if opts.codegen_backend != Backend::Llvm {
+        bail!("--codegen-backend {} only applies to Rust cases",
+              opts.codegen_backend);
+    }
+    if opts.incremental {
+        bail!("--incremental only applies to Rust cases");
+    }
+    if !opts.target_feature.is_empty() {
+        bail!("--target-feature only applies to Rust cases");
+    }
+    if opts.mitigations || opts.compare_mitigations {
+        bail!("--mitigations only applies to Rust cases");
+    }
+    if opts.linking == Some(Linking::PreferDynamic) {
+        bail!("--linking prefer-dynamic only applies to Rust cases; C++'s \
+               standard library is already shared");
+    }
+
+    let mut args: Vec<OsString> = vec![
+        "-std=c++17".into(),
+        src.into(),
+        "-o".into(),
+        out.into(),
+        format!("-O{}", opts.opt_level).into(),
+    ];
+    if asm {
+        args.push("-S".into());
+    }
+    match opts.lto {
+        Some(Lto::Thin) => args.push("-flto=thin".into()),
+        Some(Lto::Fat) => args.push("-flto".into()),
+        Some(Lto::Off) => args.push("-fno-lto".into()),
+        None => { }
+    }
+    if let Some(ref target) = opts.target {
+        // Only clang takes a target; gcc is built for one. A compiler
+        // in --container can't be asked here, so it's left to fail.
+        if opts.container.is_none() && !is_clang(&opts.cxx)? {
+            bail!("--target {} with --lang cpp needs clang as --cxx; {} isn't clang, \
+                   and gcc only builds for the target it was configured for",
+                  target, opts.cxx);
+        }
+        args.push(format!("--target={}", target).into());
+    }
+    if let Some(ref cpu) = opts.target_cpu {
+        args.push(format!("-march={}", cpu).into());
+    }
+    match opts.relocation_model() {
+        Some(RelocModel::Pic) => args.extend(["-fPIE".into(), "-pie".into()]),
+        Some(RelocModel::Static) => args.extend(["-fno-pie".into(), "-no-pie".into()]),
+        None => { }
+    }
+    if opts.linking == Some(Linking::Static) {
+        args.push("-static".into());
+    }
+    if opts.deterministic {
+        args.push(crate::remap_path_prefix("-ffile-prefix-map=", src));
+        // Seeds the names of LTO's and anonymous namespaces' symbols
+        args.push("-frandom-seed=dispatch-test".into());
+    }
+
+    Ok(args)
+}
+
+/// Whether the compiler says it's clang when asked its version
+fn is_clang(cxx: &str) -> Result<bool> {
+    let output = Command::new(cxx)
+        .arg("--version")
+        .output()
+        .map_err(|e| anyhow!("can't run {}: {}", cxx, e))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).contains("clang"))
+}
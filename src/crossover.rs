@@ -0,0 +1,155 @@
+//! Finding where a variant overtakes the baseline one along an axis of
+//! a sweep, interpolating between the measured grid points. A run
+//! compiled as a matrix is searched once for each combination.
+
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::report::{CaseAxis, Metric};
+use crate::results::{self, RESULTS_FILE};
+use crate::{matrix, AxisFilter, Dispatch};
+
+pub struct CrossoverConfig {
+    pub metric: Metric,
+    /// The axis to walk, once for each value of the other
+    pub along: CaseAxis,
+    /// The variant the others are compared with
+    pub baseline: Dispatch,
+}
+
+pub fn crossover(outdir: &Path, filter: &AxisFilter, config: CrossoverConfig) -> Result<()> {
+    let (along, baseline) = (config.along, config.baseline);
+    let across = along.other();
+
+    // For each combination, variant and value of the other axis,
+    // (position, variant - baseline)
+    type Key = (usize, Dispatch, u32);
+    let runs = matrix::runs(outdir)?;
+    let mut lines: BTreeMap<Key, Vec<(u32, f64)>> = BTreeMap::new();
+    for (combination, (_, dir)) in runs.iter().enumerate() {
+        if !dir.join(RESULTS_FILE).exists() {
+            continue;
+        }
+        for case in results::load(dir)? {
+            if !filter.matches(case.num_types, case.num_fns) {
+                continue;
+            }
+            let base = match config.metric.value(&case, baseline) {
+                Some(base) => base,
+                None => continue,
+            };
+            for variant in case.variants().into_iter().filter(|&v| v != baseline) {
+                if let Some(value) = config.metric.value(&case, variant) {
+                    lines.entry((combination, variant, across.of(&case))).or_default()
+                        .push((along.of(&case), value as f64 - base as f64));
+                }
+            }
+        }
+    }
+    if lines.is_empty() {
+        bail!("no cases in {} have {} measured for {} and another variant",
+              outdir.display(), config.metric, baseline);
+    }
+
+    let mut heading = None;
+    for ((combination, variant, value), mut points) in lines {
+        if heading.map(|h: (usize, Dispatch)| h.0) != Some(combination) {
+            if let Some(ref flags) = runs[combination].0 {
+                println!("flags: {}", flags);
+            }
+        }
+        if heading != Some((combination, variant)) {
+            println!("where {} and {} {} cross, along {}",
+                     variant, baseline, config.metric, along);
+            heading = Some((combination, variant));
+        }
+        points.sort_by_key(|p| p.0);
+        let crossings = crossings(&points);
+        let at = format!("{} {}", value, across);
+
+        if points.len() < 2 {
+            println!("{:>12}: only one case", at);
+        } else if crossings.is_empty() {
+            let (first, last) = (points[0].0, points[points.len() - 1].0);
+            let state = if points.iter().all(|p| p.1 == 0.0) {
+                "the variants tie".to_string()
+            } else if points.iter().all(|p| p.1 >= 0.0) {
+                format!("{} is never below {}", variant, baseline)
+            } else {
+                format!("{} is never above {}", variant, baseline)
+            };
+            println!("{:>12}: no crossover; {} from {} to {} {}",
+                     at, state, first, last, along);
+        } else {
+            for c in crossings {
+                println!("{:>12}: {} {} {} at {:.1} {} (between {} and {})",
+                         at, variant, if c.rising { "overtakes" } else { "falls below" },
+                         baseline, c.at, along, c.from, c.to);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Crossing {
+    at: f64,
+    from: u32,
+    to: u32,
+    /// Whether the variant goes from below the baseline to above it
+    rising: bool,
+}
+
+/// Where the difference changes sign between neighbouring points,
+/// interpolated linearly, or where it passes through zero on the way,
+/// at the first point it's zero. Ties that go back to the side they
+/// came from aren't crossings.
+fn crossings(points: &[(u32, f64)]) -> Vec<Crossing> {
+    let mut crossings = vec![];
+    let mut last: Option<(u32, f64)> = None;
+    let mut tie: Option<u32> = None;
+
+    for &(x, diff) in points {
+        if diff == 0.0 {
+            tie = tie.or(Some(x));
+            continue;
+        }
+        if let Some((x0, d0)) = last {
+            if (d0 < 0.0) != (diff < 0.0) {
+                let at = match tie {
+                    Some(tie) => tie as f64,
+                    None => x0 as f64 + (x - x0) as f64 * d0 / (d0 - diff),
+                };
+                crossings.push(Crossing { at, from: x0, to: x, rising: diff > 0.0 });
+            }
+        }
+        last = Some((x, diff));
+        tie = None;
+    }
+
+    crossings
+}
+
+#[cfg(test)]
+mod test {
+    use super::crossings;
+
+    #[test]
+    fn interpolates_between_points() {
+        let found = crossings(&[(1, -4.0), (2, -2.0), (4, 2.0), (8, -6.0)]);
+        let found: Vec<_> = found.iter().map(|c| (c.at, c.rising)).collect();
+        assert_eq!(found, [(3.0, true), (5.0, false)]);
+    }
+
+    #[test]
+    fn crosses_at_an_exact_tie() {
+        assert!(crossings(&[(1, 0.0), (2, 0.0)]).is_empty());
+        assert!(crossings(&[(1, -1.0), (2, 0.0), (3, -1.0)]).is_empty());
+        let found = crossings(&[(1, -1.0), (2, 0.0), (3, 5.0)]);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].at, found[0].from, found[0].to), (2.0, 1, 3));
+        let found = crossings(&[(1, 1.0), (2, 0.0), (4, 0.0), (8, -1.0)]);
+        assert_eq!((found[0].at, found[0].rising), (2.0, false));
+    }
+}
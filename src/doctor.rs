@@ -0,0 +1,156 @@
+//! Checking up front that the tools a sweep needs are installed and
+//! work, so a missing one is reported with what to do about it instead
+//! of failing partway through.
+
+use anyhow::{Result, anyhow, bail};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::{container, pgo, symbols};
+
+/// Whether a failed check stops every sweep or only the options that
+/// need the tool
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Need {
+    Required,
+    /// Names the options that need it
+    For(&'static str),
+}
+
+pub fn doctor(outdir: &Path, cxx: &str) -> Result<()> {
+    let mut failed = 0;
+    let mut missing = 0;
+    let mut check = |name: &str, need: Need, result: Result<String>| {
+        match (result, need) {
+            (Ok(found), _) => println!("ok       {:<10} {}", name, found),
+            (Err(e), Need::Required) => {
+                println!("FAIL     {:<10} {}", name, e);
+                failed += 1;
+            }
+            (Err(e), Need::For(options)) => {
+                println!("missing  {:<10} {} (needed for {})", name, e, options);
+                missing += 1;
+            }
+        }
+    };
+
+    check("rustc", Need::Required, version("rustc"));
+    check("-Z flags", Need::For("--mono-items, --split-link, --mitigations \
+                                 and --codegen-backend cranelift"),
+          unstable_flags());
+    check("outdir", Need::Required, writable(outdir));
+    check("nm", Need::Required, symbols_work(outdir));
+    check("rustfmt", Need::For("--rustfmt"), version("rustfmt"));
+    check("profdata", Need::For("--pgo"),
+          pgo::llvm_profdata().and_then(|path| {
+              version(&path.to_string_lossy())?;
+              Ok(path.display().to_string())
+          }));
+    check("c++", Need::For("--lang cpp"), version(cxx));
+    check("container", Need::For("--container"),
+          container::engine().map(|e| format!("using {}", e)));
+    check("ssh", Need::For("--remote"), version_flag("ssh", "-V"));
+    check("bwrap", Need::For("--sandbox"), version("bwrap"));
+    check("qemu", Need::For("--runner qemu"),
+          version(&format!("qemu-{}", std::env::consts::ARCH)));
+
+    println!();
+    if failed > 0 {
+        bail!("{} required checks failed; sweeps will not work until they pass",
+              failed);
+    }
+    if missing > 0 {
+        println!("ready, but {} optional tools are missing", missing);
+    } else {
+        println!("ready");
+    }
+
+    Ok(())
+}
+
+fn version(program: &str) -> Result<String> {
+    version_flag(program, "--version")
+}
+
+/// The first line a program prints when asked its version
+fn version_flag(program: &str, flag: &str) -> Result<String> {
+    let output = Command::new(program)
+        .arg(flag)
+        .output()
+        .map_err(|e| anyhow!("can't run {}: {}", program, e))?;
+    if !output.status.success() {
+        bail!("{} {} failed", program, flag);
+    }
+
+    // ssh prints its version to stderr
+    let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+    Ok(String::from_utf8_lossy(&text).lines().next().unwrap_or_default().to_string())
+}
+
+fn unstable_flags() -> Result<String> {
+    let output = Command::new("rustc")
+        .args(["-Zunstable-options", "--version"])
+        .output()?;
+    if !output.status.success() {
+        bail!("rustc only accepts -Z flags on nightly; use a nightly \
+               toolchain or set RUSTC_BOOTSTRAP=1");
+    }
+
+    Ok("accepted".to_string())
+}
+
+fn writable(outdir: &Path) -> Result<String> {
+    fs::create_dir_all(outdir)
+        .map_err(|e| anyhow!("can't create {}: {}", outdir.display(), e))?;
+    let probe = outdir.join(".doctor-probe");
+    fs::write(&probe, "")
+        .map_err(|e| anyhow!("can't write to {}: {}", outdir.display(), e))?;
+    fs::remove_file(&probe)?;
+
+    Ok(outdir.display().to_string())
+}
+
+/// Builds a small program with a generated method name and checks nm
+/// finds it
+fn symbols_work(outdir: &Path) -> Result<String> {
+    let dir = outdir.join(".doctor");
+    fs::create_dir_all(&dir)
+        .map_err(|e| anyhow!("can't build a test program in {}: {}", dir.display(), e))?;
+    let result = (|| {
+        let src = dir.join("probe.rs");
+        let bin = dir.join("probe.bin");
+        fs::write(&src, PROBE)?;
+        let status = Command::new("rustc")
+            .arg(&src).arg("-o").arg(&bin)
+            .status()?;
+        if !status.success() {
+            bail!("rustc couldn't build a test program");
+        }
+        let counts = symbols::count(&bin)
+            .map_err(|e| anyhow!("{}; install binutils", e))?;
+        if counts.methods == 0 {
+            bail!("nm didn't list the test program's symbols");
+        }
+
+        version("nm")
+    })();
+    fs::remove_dir_all(&dir)?;
+
+    result
+}
+
+static PROBE: &str = "\
+struct T0;
+
+impl T0 {
+    #[inline(never)]
+    fn do_io_m(&self) {
+        std::hint::black_box(self);
+    }
+}
+
+fn main() {
+    T0.do_io_m();
+}
+";
@@ -0,0 +1,89 @@
+//! The kinds of failure a script wrapping the tool may want to tell
+//! apart, each with its own exit status. They're attached as context to
+//! the underlying error, which says what failed and how.
+
+use anyhow::Error;
+use std::fmt;
+
+use crate::memlimit::MemLimitExceeded;
+use crate::{interrupt, CaseConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Writing or formatting a case's sources
+    Generation,
+    /// Building a case's binaries
+    Compilation,
+    /// Running a case's binaries
+    Execution,
+    /// Reading symbols and mono items out of the builds
+    Analysis,
+}
+
+impl Phase {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Phase::Generation => 3,
+            Phase::Compilation => 4,
+            Phase::Execution => 5,
+            Phase::Analysis => 6,
+        }
+    }
+}
+
+/// Which phase of which case failed
+#[derive(Debug)]
+pub struct Failure {
+    pub phase: Phase,
+    pub num_types: u32,
+    pub num_fns: u32,
+}
+
+impl Failure {
+    pub fn new(phase: Phase, config: &CaseConfig) -> Failure {
+        Failure {
+            phase,
+            num_types: config.num_types,
+            num_fns: config.num_fns,
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Generation => "generation",
+            Phase::Compilation => "compilation",
+            Phase::Execution => "execution",
+            Phase::Analysis => "analysis",
+        })
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of case {} types / {} fns failed",
+               self.phase, self.num_types, self.num_fns)
+    }
+}
+
+/// Interrupted sweeps exit like a process killed by SIGINT
+static INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static MEM_LIMIT_EXIT_CODE: i32 = 7;
+
+/// The exit status for an error: going over --mem-limit's, the failed
+/// phase's, or 1 for anything else, like a missing run
+pub fn exit_code(e: &Error) -> i32 {
+    if interrupt::interrupted() {
+        return INTERRUPTED_EXIT_CODE;
+    }
+
+    if e.downcast_ref::<MemLimitExceeded>().is_some() {
+        return MEM_LIMIT_EXIT_CODE;
+    }
+    // Finds the Failure among any layers of context
+    e.downcast_ref::<Failure>()
+        .map_or(1, |f| f.phase.exit_code())
+}
@@ -0,0 +1,209 @@
+//! Predicting what a sweep's grid will cost before launching it. A few
+//! of its cases are generated and compiled as calibration, in a run of
+//! their own that's removed afterwards, and lines fitted through their
+//! times and disk usage are extrapolated over the whole grid.
+//!
+//! Cases are measured in units: each type's method and each copy of a
+//! generic fn the static program may make, so `types * (fns + 1)`.
+
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::axis::Axis;
+use crate::{CaseConfig, CompileOpts, GenOpts};
+
+/// The run, under the outdir, that calibration cases are built in
+static CALIBRATION_RUN: &str = "estimate-calibration";
+
+pub struct EstimateConfig {
+    pub outdir: PathBuf,
+    pub types: Axis,
+    pub fns: Axis,
+    pub calibrate: u32,
+    pub gen_opts: GenOpts,
+    pub compile_opts: CompileOpts,
+}
+
+struct Sample {
+    num_types: u32,
+    num_fns: u32,
+    time_ms: f64,
+    bytes: f64,
+}
+
+pub fn estimate(config: EstimateConfig) -> Result<()> {
+    let mut cases = vec![];
+    for num_types in config.types.values() {
+        for num_fns in config.fns.values() {
+            cases.push((num_types, num_fns));
+        }
+    }
+    if cases.is_empty() {
+        bail!("the grid has no cases");
+    }
+    cases.sort_by_key(|&(t, f)| (units(t, f), t, f));
+
+    let picks = calibration_cases(&cases, config.calibrate as usize);
+    println!("calibrating on {} of the grid's {} cases", picks.len(), cases.len());
+    let dir = config.outdir.join(CALIBRATION_RUN);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    let samples = calibrate(&dir, &picks, &config);
+    fs::remove_dir_all(&dir)?;
+    let samples = samples?;
+
+    let time = Fit::new(samples.iter().map(|s| (units(s.num_types, s.num_fns), s.time_ms)));
+    let disk = Fit::new(samples.iter().map(|s| (units(s.num_types, s.num_fns), s.bytes)));
+
+    println!();
+    println!("{:<12}{:>8}{:>12}{:>10}", "case", "units", "time", "disk");
+    for s in &samples {
+        println!("{:<12}{:>8}{:>12}{:>10}", format!("{}-{}", s.num_types, s.num_fns),
+                 units(s.num_types, s.num_fns), duration(s.time_ms), size(s.bytes));
+    }
+    println!();
+    println!("fitted: {} + {} per unit to generate and compile, \
+              {} + {} per unit on disk",
+             duration(time.fixed), duration(time.per_unit),
+             size(disk.fixed), size(disk.per_unit));
+
+    let total_ms: f64 = cases.iter().map(|&(t, f)| time.at(units(t, f))).sum();
+    let total_bytes: f64 = cases.iter().map(|&(t, f)| disk.at(units(t, f))).sum();
+    let &(largest_types, largest_fns) = cases.last().expect("the grid isn't empty");
+    let largest = units(largest_types, largest_fns);
+    println!("plan: {} cases, about {} generating and compiling one at a time, \
+              and {} on disk",
+             cases.len(), duration(total_ms), size(total_bytes));
+    println!("largest: {}-{} at about {} and {}",
+             largest_types, largest_fns, duration(time.at(largest)), size(disk.at(largest)));
+    let &(calibrated_types, calibrated_fns) = picks.last().expect("there's a pick");
+    if largest > units(calibrated_types, calibrated_fns) * 4 {
+        println!("The largest cases are well beyond the calibration ones, \
+                  so their estimates are rough.");
+    }
+
+    Ok(())
+}
+
+fn calibrate(dir: &Path, picks: &[(u32, u32)], config: &EstimateConfig) -> Result<Vec<Sample>> {
+    let mut samples = vec![];
+    for &(num_types, num_fns) in picks {
+        let case = CaseConfig {
+            outdir: dir.to_owned(),
+            num_types, num_fns,
+        };
+        let before = dir_size(dir)?;
+        let start = Instant::now();
+        crate::gen_one_case(case.clone(), config.gen_opts.clone())?;
+        crate::compile_one_case(case, config.compile_opts.clone())?;
+        samples.push(Sample {
+            num_types, num_fns,
+            time_ms: start.elapsed().as_millis() as f64,
+            bytes: dir_size(dir)?.saturating_sub(before) as f64,
+        });
+    }
+    Ok(samples)
+}
+
+/// How big a case is, for ordering and extrapolating
+pub fn units(num_types: u32, num_fns: u32) -> u64 {
+    num_types as u64 * (num_fns as u64 + 1)
+}
+
+/// `n` cases spread over the smaller half of the sorted grid, so
+/// calibrating costs a fraction of the sweep
+fn calibration_cases(sorted: &[(u32, u32)], n: usize) -> Vec<(u32, u32)> {
+    let last = (sorted.len() - 1) / 2;
+    let mut picks: Vec<_> = (0..n.max(1))
+        .map(|i| sorted[if n > 1 { i * last / (n - 1) } else { last }])
+        .collect();
+    picks.dedup();
+    picks
+}
+
+/// A least-squares line through the samples, never sloping down
+struct Fit {
+    fixed: f64,
+    per_unit: f64,
+}
+
+impl Fit {
+    fn new(points: impl Iterator<Item = (u64, f64)>) -> Fit {
+        let points: Vec<(f64, f64)> = points.map(|(x, y)| (x as f64, y)).collect();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let var_x: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        let per_unit = if var_x == 0.0 {
+            // One size of case tells nothing about growth
+            0.0
+        } else {
+            let cov: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+            (cov / var_x).max(0.0)
+        };
+        Fit {
+            fixed: (mean_y - per_unit * mean_x).max(0.0),
+            per_unit,
+        }
+    }
+
+    fn at(&self, units: u64) -> f64 {
+        self.fixed + self.per_unit * units as f64
+    }
+}
+
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        total += if meta.is_dir() { dir_size(&entry.path())? } else { meta.len() };
+    }
+    Ok(total)
+}
+
+/// A number of milliseconds, at a sensible scale
+pub fn duration(ms: f64) -> String {
+    let secs = (ms / 1000.0).round() as u64;
+    if ms < 10.0 {
+        format!("{:.1}ms", ms)
+    } else if ms < 1000.0 {
+        format!("{:.0}ms", ms)
+    } else if secs < 60 {
+        format!("{:.1}s", ms / 1000.0)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+fn size(bytes: f64) -> String {
+    let scales = [("G", 1u64 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    match scales.iter().find(|&&(_, scale)| bytes >= scale as f64) {
+        Some((suffix, scale)) => format!("{:.1}{}", bytes / *scale as f64, suffix),
+        None => format!("{:.0}", bytes),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Fit, calibration_cases};
+
+    #[test]
+    fn fits_and_picks() {
+        let fit = Fit::new(vec![(1, 110.0), (3, 130.0), (5, 150.0)].into_iter());
+        assert!((fit.fixed - 100.0).abs() < 1e-9);
+        assert!((fit.per_unit - 10.0).abs() < 1e-9);
+        assert_eq!(Fit::new(vec![(2, 50.0)].into_iter()).at(100), 50.0);
+
+        let grid: Vec<_> = (0..9).map(|n| (n, 0)).collect();
+        assert_eq!(calibration_cases(&grid, 3), [(0, 0), (2, 0), (4, 0)]);
+        assert_eq!(calibration_cases(&grid[..1], 3), [(0, 0)]);
+        assert_eq!(calibration_cases(&grid, 1), [(4, 0)]);
+    }
+}
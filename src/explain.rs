@@ -0,0 +1,456 @@
+//! Prose explanations of generated cases: the comments written into
+//! sources by `--annotate`, and the `explain` subcommand.
+
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+use crate::code::Code;
+use crate::skew::Skew;
+use crate::{manifest, BlackBox, BranchOrder, CallShape, CaseConfig, DynamicVia, ErrorPath, GenOpts,
+            StaticVia, test_loops};
+
+pub static HEADER_NOTE: &str = "\
+This program is one half of a comparison between static dispatch
+(generics, resolved at compile time) and dynamic dispatch (trait
+objects, resolved at run time through a vtable). The other half is
+generated from the same parameters and differs only in how the fns
+below take their argument.";
+
+pub static CONCRETE_HEADER_NOTE: &str = "\
+This program is the baseline for a comparison between static dispatch
+(generics) and dynamic dispatch (trait objects). It makes the same
+method calls as the two programs compared, but straight from the call
+sites, with no fns, generics or trait objects, so it's a lower bound
+for both.";
+
+pub static TYPES_NOTE: &str = "\
+Each T type stands in for a distinct concrete type implementing the
+trait. black_box hides values from the optimizer so it can't see that
+the method does nothing and delete the calls we are trying to measure.";
+
+pub static STATIC_FNS_NOTE: &str = "\
+These fns are generic over T: Io. rustc makes a separate copy of each
+fn for every type it is called with (monomorphization), so each call to
+do_io_m is a direct call the optimizer can inline. The cost is more
+code: up to one copy per fn per type.";
+
+pub static CONCRETE_CALLS_NOTE: &str = "\
+There are no fns to go through: each call site in the loop calls the
+method on a value of its type, a direct call the optimizer can inline,
+as cheap as a call to the method gets.";
+
+pub static CONST_GENERIC_FNS_NOTE: &str = "\
+Under --const-generic these fns take no argument and are generic over
+a const K: u32 instead, matching on K to pick the type's value. rustc
+makes a copy of each fn for every K it is called with, and in each
+copy the match folds to a direct call of one type's method.";
+
+pub static DYNAMIC_FNS_NOTE: &str = "\
+These fns take &dyn Io. There is exactly one copy of each fn, and every
+call to do_io_m loads the method's address from the value's vtable and
+calls through it, which the optimizer usually can't inline.";
+
+pub static JUMP_TABLE_FNS_NOTE: &str = "\
+Under --dynamic-via jump-table these fns take an integer tag instead
+of a trait object and match on it, each arm calling one type's method
+directly. The tag is only known at run time, so the match becomes a
+jump table or a chain of compares rather than a vtable load.";
+
+pub static BRANCH_TREE_FNS_NOTE: &str = "\
+Under --dynamic-via branch-tree these fns take an integer tag and
+compare it with each type's in an if/else-if chain, calling the first
+type that matches. Each compare is a branch the CPU can predict, where
+a vtable call is an indirect jump it has to predict the target of.";
+
+pub static CLOSURE_FNS_NOTE: &str = "\
+Under --dynamic-via closure these fns take &dyn Fn(u32), a boxed
+closure per type that calls its type's method, and call it with their
+own number as the event: callbacks registered with an event loop. The
+call goes through the closure's vtable, and the method call inside it
+is direct.";
+
+pub static STATIC_ENUM_FNS_NOTE: &str = "\
+Under --static-via enum these fns take a Val, an enum with a variant
+per type, and match on it to call the type's method directly: an
+event loop whose handlers are a closed set known up front. Unlike
+generics, there is one copy of each fn.";
+
+pub static STATIC_SYSTEMS_FNS_NOTE: &str = "\
+Under --components these fns are systems: each runs over one type's
+array of components, generic over the type, so each is copied per
+type and every call in its loop is direct.";
+
+pub static DYNAMIC_SYSTEMS_FNS_NOTE: &str = "\
+Under --components these fns are systems over a slice of boxed trait
+objects, the way components are often stored when a system has to
+handle any kind: each call in the loop follows a box and then a
+vtable.";
+
+pub static FN_TABLE_FNS_NOTE: &str = "\
+Under --dynamic-via fn-table these fns take an integer tag and index a
+table of fn pointers with it, an entry per type calling its method
+directly: the dispatch table of a bytecode interpreter. The call
+through the entry is indirect, like a vtable's, without the load of
+a vtable pointer from the object.";
+
+pub static STATIC_STATES_NOTE: &str = "\
+Under --state-machine the types are states, each a variant of the
+enum State, and step matches on the current one, does its work through
+the fns and returns the next by value: the closed-world way to write a
+state machine, with every transition visible to the compiler.";
+
+pub static DYNAMIC_STATES_NOTE: &str = "\
+Under --state-machine the types are states behind Box<dyn State>, and
+each state's next consumes its box, does its work through the fns and
+boxes the next: the state pattern, paying a vtable call and an
+allocation per transition.";
+
+pub static STATIC_VISITOR_FNS_NOTE: &str = "\
+Under --visitor these fns take a Val, an enum with a variant per type,
+and match on it to call the type's method directly: the closed-world
+way to visit a fixed set of types.";
+
+pub static DYNAMIC_VISITOR_FNS_NOTE: &str = "\
+Under --visitor these fns take &dyn Io and try to downcast it to each
+type in turn through Any, calling the method on the first that fits:
+the open-world visitor pattern common in AST-heavy code. Each failed
+attempt is a type id comparison.";
+
+pub static STATIC_HOLDER_NOTE: &str = "\
+Under --holder the fns take a Holder that stores the value in a field.
+The holder is generic over the value's type, so each fn is still
+copied per type and the call through the field is direct.";
+
+pub static DYNAMIC_HOLDER_NOTE: &str = "\
+Under --holder the fns take a Holder that stores the value as a
+Box<dyn Io> field, the \"strategy object in a struct\" pattern. Each
+call loads the box's vtable pointer from the field first.";
+
+pub static STATIC_STRUCTS_NOTE: &str = "\
+Under --structs each S struct is generic over T: Io, so rustc makes a
+copy of the struct's method for every type it holds, the way generic
+containers and adapters multiply in real code.";
+
+pub static DYNAMIC_STRUCTS_NOTE: &str = "\
+Under --structs each S struct holds a &dyn Io, so there is one copy
+of each struct's method and its call to do_io_m goes through the
+vtable.";
+
+pub static ASSOC_TYPE_NOTE: &str = "\
+Under --assoc-type the method returns the trait's associated type. A
+trait object has to name it, so the dynamic program uses
+dyn Io<Out = u8>, which every type here satisfies.";
+
+pub static GENERIC_METHOD_NOTE: &str = "\
+Under --generic-method the trait has a method generic over U. It is
+bounded by `where Self: Sized` so the trait can still be made into an
+object, which also means it can't be called through &dyn Io: only the
+static program's fns call it, making a copy per fn per type.";
+
+pub static OBJECT_SAFETY_NOTE: &str = "\
+Under --object-safety-stress the trait also has a method returning
+Self, and each method trait objects can't have is paired with an
+object-safe stand-in: do_io_any takes its argument as &dyn Any and
+dup_boxed returns its copy in a Box. The static program calls the
+generic methods; the dynamic program has to call the stand-ins,
+paying for the erasure and an allocation per copy.";
+
+pub static SPECIALIZATION_NOTE: &str = "\
+Under --specialization every type gets the trait from this blanket
+impl, whose method is a default that each T type's own impl
+specializes. It needs the nightly min_specialization feature, and
+tracks how well rustc resolves and optimizes specialized calls.";
+
+pub static TRAIT_DEPTH_NOTE: &str = "\
+Under --trait-depth a chain of traits derives from Io, each implemented
+for every type implementing the last, and the fns take the most derived.
+Its trait objects' vtables embed Io's, so the method is still called
+through one. Under --upcast the dynamic fns first coerce their argument
+to &dyn Io, which swaps in the vtable for Io.";
+
+pub fn main_note(opts: &GenOpts) -> String {
+    let order = if opts.predictable {
+        "grouped by type, so consecutive calls usually see the same type"
+    } else {
+        "grouped by fn, so consecutive calls alternate between types"
+    };
+
+    let skew = match opts.skew {
+        Skew::Uniform => "",
+        Skew::Zipf => "\nUnder --skew zipf type k is called in proportion to 1/(k+1).",
+        Skew::Single => "\nUnder --skew single every call is to T0.",
+    };
+
+    let threads = if opts.threads > 0 {
+        format!("\nUnder --threads each of {} threads runs the whole loop.", opts.threads)
+    } else if opts.tasks > 0 {
+        format!("\nUnder --tasks the passes are split between {} tasks, polled in turn.",
+                opts.tasks)
+    } else {
+        String::new()
+    };
+
+    let states = if opts.state_machine {
+        "\nUnder --state-machine each call site is a transition, cycling through the states."
+    } else {
+        ""
+    };
+
+    let shape = match opts.call_shape {
+        CallShape::InlineLoop => "",
+        CallShape::Outlined => "\nUnder --call-shape outlined the loop calls a fn per call site.",
+        CallShape::IndirectThroughArray => "\nUnder --call-shape indirect-through-array the loop \
+                                            calls a fn per call site\nthrough an array of fn \
+                                            pointers.",
+    };
+
+    let vary = if opts.vary_args {
+        "\nUnder --vary-args each pass rebinds the values through black_box first."
+    } else {
+        ""
+    };
+
+    let components = if opts.components > 0 {
+        format!("\nUnder --components each call runs over {} components.", opts.components)
+    } else {
+        String::new()
+    };
+
+    format!("\
+The measured loop. It repeats every call site {} times; the calls are
+{}.{}{}{}{}{}{}
+The static and dynamic programs make exactly the same calls.",
+            test_loops(opts), order, skew, threads, components, states, shape, vary)
+}
+
+pub fn write_note(code: &mut Code, note: &str) {
+    code.blank();
+    for line in note.lines() {
+        code.line(format_args!("// {}", line));
+    }
+}
+
+pub fn explain(outdir: &Path, num_types: u32, num_fns: u32) -> Result<()> {
+    let config = CaseConfig {
+        outdir: outdir.to_owned(),
+        num_types, num_fns,
+    };
+    let case = manifest::find_case(&config)?;
+    let opts = &case.gen_opts;
+    let calls = num_types * num_fns.max(1);
+
+    println!("Case {} types / {} fns in {}, currently {:?}.",
+             num_types, num_fns, outdir.display(), case.status);
+    println!();
+    println!("Both programs define {} types implementing the trait Io and \
+              {} fns that call its one method.", num_types, num_fns);
+    if num_types == 0 {
+        println!("With no types there is nothing to call, so the loop is \
+                  empty and the case measures only loop overhead.");
+    } else if num_fns == 0 {
+        println!("With no fns, the loop calls each type's method directly: \
+                  statically on the concrete type, and dynamically through \
+                  a &dyn Io. This isolates the cost of the method call \
+                  itself.");
+    } else {
+        println!("The static program makes the fns generic, so rustc may \
+                  generate up to {} copies of them, one per fn per type. \
+                  The dynamic program takes &dyn Io, so there are only {} \
+                  and every method call goes through a vtable.",
+                 calls, num_fns);
+    }
+    println!();
+    println!("Each pass of the loop makes {} calls, repeated {} times, {}.",
+             calls, test_loops(opts),
+             if opts.predictable { "grouped by type" } else { "grouped by fn" });
+    if opts.no_inline {
+        println!("Generated fns and methods are marked #[inline(never)], so \
+                  the static program can't inline them either.");
+    }
+    if opts.holder {
+        println!("The fns take a Holder struct with the value in a field: \
+                  a generic T in the static program and a Box<dyn Io> in \
+                  the dynamic one.");
+    }
+    if opts.structs > 0 {
+        println!("Each type's method is also called through {} structs, \
+                  generic over T: Io in the static program, where each is \
+                  instantiated for all {} types, and holding a &dyn Io in \
+                  the dynamic one.", opts.structs, num_types);
+    }
+    if opts.assoc_type {
+        println!("The trait's method returns an associated type, so the \
+                  dynamic program's trait objects are dyn Io<Out = u8>.");
+    }
+    if opts.generic_method {
+        println!("The trait has a generic method with `where Self: Sized`. \
+                  The static program's fns call it, instantiating it once \
+                  more per fn per type; the dynamic program can't call it \
+                  through &dyn Io and calls do_io_m instead.");
+    }
+    if opts.object_safety_stress {
+        println!("The trait is at the edge of object safety. The static \
+                  program's fns call a generic method and copy their value \
+                  with a method returning Self; the dynamic program works \
+                  around both, passing the argument as &dyn Any and copying \
+                  into a Box<dyn Io>.");
+    }
+    if opts.const_generic {
+        println!("The static program selects the type with a const generic \
+                  parameter and a match on it rather than a type parameter; \
+                  each fn is still copied per type.");
+    }
+    if opts.specialization {
+        println!("The trait has a blanket default impl that each type's impl \
+                  specializes, using the nightly min_specialization \
+                  feature.");
+    }
+    if opts.dynamic_via == DynamicVia::JumpTable {
+        println!("The dynamic program passes an integer tag instead of \
+                  &dyn Io, and each fn matches on it to pick the type, \
+                  which rustc can compile to a jump table.");
+    }
+    if opts.dynamic_via == DynamicVia::FnTable {
+        println!("The dynamic program passes an integer tag instead of \
+                  &dyn Io, and each fn calls through a table of fn \
+                  pointers indexed by it, an entry per type.");
+    }
+    if opts.dynamic_via == DynamicVia::Closure {
+        println!("The dynamic program passes each value as a boxed closure, \
+                  &dyn Fn(u32), that calls its type's method, and each fn \
+                  calls it with the fn's number as the event.");
+    }
+    if opts.static_via == StaticVia::Enum {
+        println!("The static program passes an enum with a variant per type \
+                  instead of a type parameter, and each fn matches on it, so \
+                  there's one copy of each fn.");
+    }
+    if opts.dynamic_via == DynamicVia::BranchTree {
+        println!("The dynamic program passes an integer tag instead of \
+                  &dyn Io, and each fn compares it with the types' in an \
+                  if/else-if chain, {}.",
+                 match opts.branch_order {
+                     BranchOrder::LikelyFirst => "most often called type first",
+                     BranchOrder::Uniform => "each fn starting at a different type",
+                 });
+    }
+    match opts.skew {
+        Skew::Uniform => {}
+        Skew::Zipf => println!("The call sites are skewed toward the first types, \
+                                type k appearing in proportion to 1/(k+1), so \
+                                some types may never be called."),
+        Skew::Single => println!("Every call site uses T0, so the other types are \
+                                  generated but never called."),
+    }
+    if opts.site_types > 0 && opts.site_types < num_types {
+        println!("Each fn and struct is called with only {} of the types, \
+                  so the method call inside it sees {} {}, however many \
+                  types there are.", opts.site_types, opts.site_types,
+                 if opts.site_types == 1 { "type (monomorphic)" } else { "types (polymorphic)" });
+    }
+    if opts.trait_depth > 0 {
+        println!("The fns take Sub{}, the end of a chain of {} traits derived \
+                  from Io{}.", opts.trait_depth, opts.trait_depth,
+                 if opts.upcast {
+                     ", and the dynamic fns upcast their &dyn argument to &dyn Io \
+                      before calling"
+                 } else {
+                     ""
+                 });
+    }
+    if opts.visitor {
+        println!("The fns visit their argument: the static program matches \
+                  on an enum of the types, and the dynamic program downcasts \
+                  &dyn Io to each type in turn.");
+    }
+    if opts.threads > 0 {
+        println!("The loop runs on {} threads at once. Each has its own \
+                  clones of the values, shared through Arc<Tn> in the static \
+                  program and Arc<dyn Io + Send + Sync> in the dynamic one.",
+                 opts.threads);
+    }
+    if opts.tasks > 0 {
+        println!("The loop is split between {} tasks on a minimal executor \
+                  that polls each in turn, every poll making one pass. The \
+                  static program polls each task's concrete future; the \
+                  dynamic one polls them as Pin<Box<dyn Future>>.",
+                 opts.tasks);
+    }
+    match opts.call_shape {
+        CallShape::InlineLoop => {}
+        CallShape::Outlined => println!("Each call site is outlined into an \
+                                         #[inline(never)] fn of its own, which \
+                                         the loop calls in turn."),
+        CallShape::IndirectThroughArray => println!("Each call site is outlined into \
+                                                     an #[inline(never)] fn of its \
+                                                     own, and the loop calls them \
+                                                     through an array of fn pointers \
+                                                     hidden from the optimizer."),
+    }
+    if opts.vary_args {
+        println!("Each pass starts by passing every value through black_box \
+                  with the pass number, so the optimizer can't prove the \
+                  receivers loop-invariant and hoist the calls out. \
+                  compile-one-case checks they're still in the loop in \
+                  main's assembly.");
+    }
+    match opts.blackbox {
+        BlackBox::Std => {}
+        BlackBox::Asm => println!("black_box is an empty inline asm block the \
+                                   value's address escapes into, rather than \
+                                   std's."),
+        BlackBox::Volatile => println!("black_box is a volatile read of the \
+                                        value, rather than std's."),
+        BlackBox::None => println!("black_box does nothing, so the optimizer \
+                                    is free to remove any call it can prove \
+                                    does nothing too."),
+    }
+    if opts.checksum {
+        println!("Each method adds a number of its type's own to a sum \
+                  the programs print at exit, and run-one-case checks \
+                  that both printed the same one, so they made the same \
+                  calls.");
+    }
+    if opts.concrete {
+        println!("A third, concrete program makes the same method calls \
+                  straight from the call sites, with no fns, generics or \
+                  trait objects, as a lower bound for both. `report \
+                  --baseline concrete` gives each program's ratios to it.");
+    }
+    if opts.state_machine {
+        println!("The types are the states of a state machine, advanced \
+                  through them in turn, once per state each pass, each \
+                  transition calling the fns on its state: the static \
+                  program steps an enum with a match, and the dynamic one a \
+                  Box<dyn State> that boxes the next state.");
+    }
+    if opts.components > 0 {
+        println!("Each type has an array of {} components that the fns run \
+                  over, calling the method on each, as systems do in an \
+                  entity-component system: a &[Tn] in the static program \
+                  and a &[Box<dyn Io>] in the dynamic one.", opts.components);
+    }
+    if let Some(path) = opts.errors {
+        println!("The method and fns return Result, propagating with ?: the \
+                  static program's error is an enum of each type's error, \
+                  and the dynamic program's a Box<dyn Error>. {}",
+                 match path {
+                     ErrorPath::Rare => "The method never actually fails, so \
+                                         only the Result plumbing is measured.",
+                     ErrorPath::Hot => "Every call fails, so each builds, \
+                                        propagates and drops an error.",
+                 });
+    }
+    if opts.cold_start {
+        println!("The loop makes a single pass, and each run launches the \
+                  programs many times, so the case measures process startup \
+                  and first calls rather than steady-state dispatch.");
+    }
+    if opts.no_dedup {
+        println!("Every generated body has distinct code, so the compiler \
+                  and linker can't merge identical copies.");
+    }
+
+    Ok(())
+}
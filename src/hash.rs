@@ -0,0 +1,56 @@
+//! A hash of everything that determines a generated case's source,
+//! stamped into each file's header. The compile and run phases check it
+//! against the manifest so stale files written by other generators or
+//! with other options aren't silently measured against fresh ones.
+
+use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::{CaseConfig, GenOpts};
+
+static HASH_PREFIX: &str = "// dispatch-test-hash: ";
+
+/// Bumped by every change to what the generators write for the same
+/// case and options. The golden tests fail on output that changed
+/// without it.
+pub const GENERATOR_VERSION: u32 = 2;
+
+pub fn case_hash(config: &CaseConfig, opts: &GenOpts) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("generator {}", GENERATOR_VERSION));
+    hasher.update(format!(" {} {} ", config.num_types, config.num_fns));
+    hasher.update(serde_json::to_vec(opts)?);
+
+    let digest = hasher.finalize();
+    Ok(digest[..8].iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+pub fn header_line(hash: &str) -> String {
+    format!("{}{}", HASH_PREFIX, hash)
+}
+
+pub fn verify(config: &CaseConfig, opts: &GenOpts, src: &Path) -> Result<()> {
+    let expected = case_hash(config, opts)?;
+    let text = fs::read_to_string(src)?;
+    let found = text.lines()
+        .take_while(|l| l.starts_with("//") || l.is_empty())
+        .find_map(|l| l.strip_prefix(HASH_PREFIX));
+
+    match found {
+        Some(found) if found.trim() == expected => Ok(()),
+        Some(_) => bail!("{} was generated by a different generator or with \
+                          different options than the manifest records; \
+                          regenerate it", src.display()),
+        None => bail!("{} has no dispatch-test hash; regenerate it",
+                      src.display()),
+    }
+}
+
+/// The hex SHA-256 of a built file, recorded so a comparison between runs
+/// can tell when "the same" case produced a different binary
+pub fn file_digest(path: &Path) -> Result<String> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
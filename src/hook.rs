@@ -0,0 +1,60 @@
+//! The `--on-complete` hook, run when a phase finishes or fails so a
+//! long sweep can send a notification without a wrapper script.
+
+use anyhow::Result;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::manifest::{self, CaseStatus};
+use crate::results;
+
+/// Runs `hook` with `sh -c`. The results path and a one-line summary
+/// are passed as `$1` and `$2`, and in the environment along with the
+/// subcommand, the run directory and whether it succeeded.
+pub fn on_complete(hook: &str, subcommand: &str, run_dir: &Path,
+                   elapsed: Duration, result: &Result<()>) {
+    let status = if result.is_ok() { "success" } else { "failure" };
+    let summary = summarize(subcommand, run_dir, elapsed, result);
+    let results_path = run_dir.join(results::RESULTS_FILE);
+
+    let outcome = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .arg("dispatch-test-hook")
+        .arg(&results_path)
+        .arg(&summary)
+        .env("DISPATCH_TEST_STATUS", status)
+        .env("DISPATCH_TEST_SUBCOMMAND", subcommand)
+        .env("DISPATCH_TEST_RUN_DIR", run_dir)
+        .env("DISPATCH_TEST_RESULTS", &results_path)
+        .env("DISPATCH_TEST_SUMMARY", &summary)
+        .status();
+
+    // The phase's own outcome is what matters; a broken hook only warns
+    match outcome {
+        Ok(s) if s.success() => { }
+        Ok(s) => println!("warning: --on-complete hook failed ({})", s),
+        Err(e) => println!("warning: running --on-complete hook: {}", e),
+    }
+}
+
+fn summarize(subcommand: &str, run_dir: &Path,
+             elapsed: Duration, result: &Result<()>) -> String {
+    let secs = elapsed.as_secs();
+    let elapsed = format!("{}m{:02}s", secs / 60, secs % 60);
+    let mut summary = match result {
+        Ok(()) => format!("{} finished in {}", subcommand, elapsed),
+        Err(e) => format!("{} failed after {}: {}", subcommand, elapsed, e),
+    };
+
+    if let Ok(cases) = manifest::load(run_dir) {
+        let at_least = |status| cases.iter().filter(|c| c.status >= status).count();
+        summary.push_str(&format!(" ({} cases: {} compiled, {} ran)",
+                                  cases.len(),
+                                  at_least(CaseStatus::Compiled),
+                                  at_least(CaseStatus::Ran)));
+    }
+
+    summary
+}
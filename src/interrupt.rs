@@ -0,0 +1,46 @@
+//! Stopping a sweep cleanly on Ctrl-C. Each case's results are written
+//! as it finishes, so the sweep stops between cases and says where
+//! `--resume` will pick up. A case cut short is left unrecorded, so
+//! resuming redoes it. A second Ctrl-C exits at once.
+
+use anyhow::{Error, anyhow};
+use std::sync::Once;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::CaseConfig;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+static MESSAGE: &[u8] = b"\ninterrupted; stopping after the current case \
+                          (Ctrl-C again to stop now)\n";
+
+extern "C" fn on_sigint(_: libc::c_int) {
+    // Only async-signal-safe calls from here
+    unsafe {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::raise(libc::SIGINT);
+        } else {
+            libc::write(libc::STDERR_FILENO, MESSAGE.as_ptr().cast(), MESSAGE.len());
+        }
+    }
+}
+
+/// Installs the handler, for the rest of the process
+pub fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        libc::signal(libc::SIGINT, on_sigint as extern "C" fn(libc::c_int)
+                     as libc::sighandler_t);
+    });
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// The error a sweep stops with, naming the first case not done
+pub fn stopped(done: usize, total: usize, next: &CaseConfig, hint: &str) -> Error {
+    anyhow!("interrupted after {} of {} cases, before {} types / {} fns; {}",
+            done, total, next.num_types, next.num_fns, hint)
+}
@@ -0,0 +1,196 @@
+//! A Markdown report of one case's most lopsided measurement, for
+//! filing against rust-lang/rust: the anomaly in a sentence, the
+//! numbers behind it, how other runs of the same case compare, the
+//! reproduction script and the toolchain. It's a draft to check over,
+//! not something to file unread.
+
+use anyhow::{Result, anyhow, bail};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+
+use crate::replay::CaseId;
+use crate::report::{self, Metric};
+use crate::results::{self, CaseResults};
+use crate::{manifest, repro, summary, CaseConfig, Dispatch, Lang};
+
+/// Other runs' ratios at most this far from 1, as a fraction of this
+/// run's distance, are reported as not showing the anomaly
+static CONTRAST: f64 = 0.5;
+
+/// A metric's dynamic/static ratio in one run
+struct Anomaly {
+    metric: Metric,
+    ratio: f64,
+}
+
+impl Anomaly {
+    /// The metric this case's variants differ on most
+    fn of(case: &CaseResults) -> Option<Anomaly> {
+        report::METRICS.iter()
+            .filter_map(|&metric| Some(Anomaly { metric, ratio: ratio(metric, case)? }))
+            .max_by(|a, b| a.ratio.ln().abs().total_cmp(&b.ratio.ln().abs()))
+    }
+
+    fn describe(&self) -> String {
+        let (more, less) = match self.metric {
+            Metric::RunTime => ("slower", "faster"),
+            Metric::CompileTime => ("slower to compile", "faster to compile"),
+            Metric::BinSize => ("larger", "smaller"),
+        };
+        if self.ratio >= 1.0 {
+            format!("dynamic dispatch is {:.1}× {} than static dispatch", self.ratio, more)
+        } else {
+            format!("dynamic dispatch is {:.1}× {} than static dispatch",
+                    1.0 / self.ratio, less)
+        }
+    }
+}
+
+fn ratio(metric: Metric, case: &CaseResults) -> Option<f64> {
+    let (s, d) = metric.values(case)?;
+    if s == 0 || d == 0 {
+        return None;
+    }
+    Some(d as f64 / s as f64)
+}
+
+fn flags(case: &CaseResults) -> Vec<String> {
+    case.compile.as_ref().map_or(vec![], |c| summary::compile_flags(&c.opts))
+}
+
+/// The flags in `a` that aren't in `b`, or all of `a`'s if none differ
+fn differing(a: &[String], b: &[String]) -> String {
+    let only: Vec<_> = a.iter().filter(|f| !b.contains(f)).cloned().collect();
+    if only.is_empty() { a.join(" ") } else { only.join(" ") }
+}
+
+pub fn report_issue(outdir: &Path, id: CaseId) -> Result<()> {
+    let config = CaseConfig {
+        outdir: outdir.to_owned(),
+        num_types: id.num_types,
+        num_fns: id.num_fns,
+    };
+    if manifest::find_case(&config)?.gen_opts.lang != Lang::Rust {
+        bail!("report-issue is for rustc; case {} types / {} fns is C++",
+              id.num_types, id.num_fns);
+    }
+    let case = results::find_case(&config)?
+        .ok_or_else(|| anyhow!("case {} types / {} fns hasn't been measured",
+                               id.num_types, id.num_fns))?;
+    let anomaly = Anomaly::of(&case)
+        .ok_or_else(|| anyhow!("case {} types / {} fns has no measurements to report",
+                               id.num_types, id.num_fns))?;
+    let this_flags = flags(&case);
+    let this_run = outdir.file_name().expect("run dirs have names").to_string_lossy();
+    let root = outdir.parent().expect("run dirs have parents");
+
+    // The same case as measured by the outdir's other runs
+    let others: Vec<_> = report::every_run(root, id)?.into_iter()
+        .filter(|(run, _)| *run != this_run)
+        .filter_map(|(run, case)| {
+            let case = case?;
+            Some((run, ratio(anomaly.metric, &case)?, flags(&case)))
+        })
+        .collect();
+    let contrast = others.iter()
+        .filter(|(_, r, _)| r.ln().abs() <= anomaly.ratio.ln().abs() * CONTRAST)
+        .min_by(|a, b| a.1.ln().abs().total_cmp(&b.1.ln().abs()));
+
+    let mut summary = anomaly.describe();
+    match contrast {
+        Some((_, r, other_flags)) => {
+            // In the same direction as the anomaly's
+            let r = if anomaly.ratio >= 1.0 { *r } else { 1.0 / r };
+            write!(summary, " with {}, but {:.1}× with {}",
+                   differing(&this_flags, other_flags), r,
+                   differing(other_flags, &this_flags))?;
+        }
+        None => write!(summary, " with {}", this_flags.join(" "))?,
+    }
+
+    let mut out = String::new();
+    writeln!(out, "<!-- Drafted by dispatch-test {}; check it over before filing -->",
+             env!("CARGO_PKG_VERSION"))?;
+    writeln!(out)?;
+    writeln!(out, "# {}", capitalized(&anomaly.describe()))?;
+    writeln!(out)?;
+    writeln!(out, "A generated program calling {} fns on {} types, once through \
+                   generics and once through `&dyn` trait objects: {}.",
+             id.num_fns, id.num_types, summary)?;
+
+    writeln!(out)?;
+    writeln!(out, "## Measurements")?;
+    writeln!(out)?;
+    writeln!(out, "| | static | dynamic | dynamic/static |")?;
+    writeln!(out, "|---|---:|---:|---:|")?;
+    for (label, metric) in [("run time (ms)", Metric::RunTime),
+                            ("compile time (ms)", Metric::CompileTime),
+                            ("binary size (bytes)", Metric::BinSize)] {
+        if let Some((s, d)) = metric.values(&case) {
+            let ratio = match metric.interval(&case, Dispatch::Dynamic, Dispatch::Static) {
+                Some(interval) => format!("{} (95% CI)", interval),
+                None => ratio(metric, &case).map_or("-".to_string(),
+                                                    |r| format!("{:.2}", r)),
+            };
+            writeln!(out, "| {} | {} | {} | {} |", label, s, d, ratio)?;
+        }
+    }
+    if !others.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "The same case in other runs, by dynamic/static {}:",
+                 anomaly.metric)?;
+        writeln!(out)?;
+        writeln!(out, "| run | flags | dynamic/static |")?;
+        writeln!(out, "|---|---|---:|")?;
+        writeln!(out, "| {} (this one) | `{}` | {:.2} |",
+                 this_run, this_flags.join(" "), anomaly.ratio)?;
+        for (run, r, other_flags) in &others {
+            writeln!(out, "| {} | `{}` | {:.2} |", run, other_flags.join(" "), r)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "## Reproduction")?;
+    writeln!(out)?;
+    writeln!(out, "This script writes both programs, builds them with the flags \
+                   measured and times a run of each:")?;
+    writeln!(out)?;
+    writeln!(out, "<details><summary>repro.sh</summary>")?;
+    writeln!(out)?;
+    writeln!(out, "```sh")?;
+    out.push_str(&repro::render(&config)?);
+    writeln!(out, "```")?;
+    writeln!(out)?;
+    writeln!(out, "</details>")?;
+
+    writeln!(out)?;
+    writeln!(out, "## Meta")?;
+    writeln!(out)?;
+    writeln!(out, "`rustc --version --verbose` (when this report was drafted):")?;
+    writeln!(out, "```")?;
+    writeln!(out, "{}", toolchain()?.trim_end())?;
+    writeln!(out, "```")?;
+
+    print!("{}", out);
+
+    Ok(())
+}
+
+fn toolchain() -> Result<String> {
+    let output = Command::new("rustc").arg("-vV").output()
+        .map_err(|e| anyhow!("can't run rustc: {}", e))?;
+    if !output.status.success() {
+        bail!("rustc -vV failed with {}", output.status);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn capitalized(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
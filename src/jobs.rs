@@ -0,0 +1,52 @@
+//! Bounding total parallelism when compiling cases in parallel. Each
+//! case compile holds a jobserver token, and rustc is handed the same
+//! jobserver so its codegen threads draw from the same pool instead of
+//! oversubscribing the machine. Under `make -jN` make's jobserver is
+//! used.
+
+use anyhow::{Result, anyhow};
+use jobserver::Client;
+use std::process::Command;
+use std::sync::OnceLock;
+
+static CLIENT: OnceLock<(Client, Option<usize>)> = OnceLock::new();
+
+/// Sets up the pool for `jobs` parallel jobs, returning how many
+/// tokens it has, or `None` if they come from make
+pub fn init(jobs: usize) -> Result<Option<usize>> {
+    // Unsafe because the descriptors named in MAKEFLAGS may have been
+    // reused; the jobserver crate checks they're still pipes
+    let (client, limit) = match unsafe { Client::from_env() } {
+        Some(client) => {
+            println!("using make's jobserver");
+            (client, None)
+        }
+        None => (Client::new(jobs)?, Some(jobs)),
+    };
+    let _ = CLIENT.set((client, limit));
+
+    Ok(limit)
+}
+
+/// The bound on parallelism recorded with results, if jobs are limited
+pub fn limit() -> Option<usize> {
+    CLIENT.get().and_then(|&(_, limit)| limit)
+}
+
+/// Runs `f` while holding a token, if there's a pool
+pub fn with_token<T>(f: impl FnOnce() -> T) -> Result<T> {
+    let _token = match CLIENT.get() {
+        Some((client, _)) => Some(client.acquire()
+            .map_err(|e| anyhow!("acquiring jobserver token: {}", e))?),
+        None => None,
+    };
+
+    Ok(f())
+}
+
+/// Lets a compiler take part in the pool
+pub fn configure(cmd: &mut Command) {
+    if let Some((client, _)) = CLIENT.get() {
+        client.configure(cmd);
+    }
+}
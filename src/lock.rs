@@ -0,0 +1,50 @@
+//! An advisory lock held on a run directory for the whole invocation,
+//! so two instances writing the same case files fail fast instead of
+//! silently clobbering each other.
+
+use anyhow::{Result, bail};
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+static LOCK_FILE: &str = "dispatch-test.lock";
+
+pub struct OutdirLock {
+    // Released when the file is closed
+    _file: File,
+}
+
+pub fn lock_outdir(dir: &Path, wait: bool) -> Result<OutdirLock> {
+    let path = dir.join(LOCK_FILE);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+
+    match file.try_lock_exclusive() {
+        Ok(()) => { }
+        Err(ref e) if e.kind() == fs2::lock_contended_error().kind() => {
+            let holder = fs::read_to_string(&path).unwrap_or_default();
+            let holder = holder.trim();
+            let holder = if holder.is_empty() { "unknown" } else { holder };
+            if !wait {
+                bail!("{} is in use by another dispatch-test (pid {}); \
+                       pass --wait-lock to wait for it",
+                      dir.display(), holder);
+            }
+            println!("waiting for lock on {} held by pid {}",
+                     dir.display(), holder);
+            file.lock_exclusive()?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    file.flush()?;
+
+    Ok(OutdirLock { _file: file })
+}
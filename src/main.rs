@@ -2,160 +2,1039 @@
 
 #[macro_use]
 extern crate serde_derive;
-#[macro_use]
-extern crate structopt;
 
 use std::time::{Instant, Duration};
-use std::process::{Command, ExitStatus};
-use structopt::StructOpt;
+use std::process::{Command, ExitStatus, Stdio};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap::error::ErrorKind;
+use std::collections::BTreeMap;
 use std::env;
+use std::ffi::OsString;
+use std::fmt;
 use std::path::{PathBuf, Path};
 use std::fs::{self, File};
 use std::iter;
 use std::io::Write;
-use anyhow::{Result, bail};
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use anyhow::{Context, Result, bail};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse_quote;
+
+mod archive;
+mod asmcheck;
+mod ast;
+mod axis;
+mod budget;
+mod cgu;
+mod checksum;
+mod cmdline;
+mod code;
+mod container;
+mod cpp;
+mod crossover;
+mod doctor;
+mod error;
+mod estimate;
+mod explain;
+mod hash;
+mod hook;
+mod interrupt;
+mod issue;
+mod jobs;
+mod lock;
+mod manifest;
+mod matrix;
+mod memlimit;
+mod metrics;
+mod mono;
+mod pagecache;
+mod pgo;
+mod preset;
+mod qemu;
+mod recommend;
+mod refine;
+mod remote;
+mod replay;
+mod repro;
+mod report;
+mod sensitivity;
+mod skew;
+mod results;
+mod retry;
+mod sandbox;
+mod stats;
+mod stream;
+mod summary;
+mod symbols;
+mod trend;
+mod variance;
+mod variant_opts;
+mod watch;
 
-#[derive(Debug, StructOpt)]
+use axis::Axis;
+use code::Code;
+use error::{Failure, Phase};
+use manifest::CaseStatus;
+
+#[derive(Debug, Parser)]
+#[command(version, about, after_help = "EXIT STATUS:
+    1    any other error, like a missing run
+    2    invalid arguments
+    3    generating a case failed
+    4    compiling a case failed
+    5    running a case failed
+    6    reading symbols or mono items from a build failed
+    7    a case went over --mem-limit
+    130  a sweep was interrupted")]
 struct Options {
-    #[structopt(subcommand)]
+    #[command(subcommand)]
     cmd: Cmd,
-    #[structopt(flatten)]
+    #[command(flatten)]
     global: GlobalOptions,
 }
 
-#[derive(Debug, StructOpt)]
+#[derive(Debug, Subcommand)]
 enum Cmd {
+    /// Generate the static and dynamic sources for one case
+    #[command(after_help = "EXAMPLES:
+    dispatch-test gen-one-case 8 4
+    dispatch-test --run-name inlining gen-one-case 8 4 --no-inline
+    dispatch-test --run-name cpp gen-one-case 8 4 --lang cpp
+    dispatch-test --run-name holder gen-one-case 8 4 --holder")]
     GenOneCase {
         num_types: u32,
         num_fns: u32,
-        #[structopt(long)]
-        no_inline: bool,
-        #[structopt(long)]
-        no_dedup: bool,
-        #[structopt(long)]
-        predictable: bool,
+        #[command(flatten)]
+        opts: GenOpts,
     },
+    /// Compile one generated case, reporting compile time, binary
+    /// size and symbol counts
+    #[command(after_help = "EXAMPLES:
+    dispatch-test compile-one-case 8 4 --opt-level 3
+    dispatch-test compile-one-case 8 4 --measure-compile --repeat 10
+    dispatch-test --run-name inlining compile-one-case 8 4 --asm
+    dispatch-test compile-one-case 8 4 --opt-level 3 --static-opts \"--lto fat\"
+    dispatch-test compile-one-case 8 4 --opt-level 3 --print-repro")]
     CompileOneCase {
         num_types: u32,
         num_fns: u32,
-        #[structopt(long)]
-        asm: bool,
-        #[structopt(long, default_value = "0")]
-        opt_level: u32,
+        #[command(flatten)]
+        opts: CompileOpts,
+        /// Also print the script that reproduces the case, which is
+        /// written beside it as repro-TYPES-FNS.sh
+        #[arg(long)]
+        print_repro: bool,
     },
+    /// Run one compiled case, reporting run time
+    #[command(after_help = "EXAMPLES:
+    dispatch-test run-one-case 8 4
+    dispatch-test run-one-case 8 4 --remote bench@quietbox
+    dispatch-test run-one-case 8 4 --container rust:1.75")]
     RunOneCase {
         num_types: u32,
         num_fns: u32,
+        #[command(flatten)]
+        runner: RunnerOpts,
     },
+    /// Generate every case in a grid of types and fns
+    #[command(after_help = "EXAMPLES:
+    dispatch-test gen-all-cases --types 1..64:x2 --fns 1..16:4
+    dispatch-test gen-all-cases --types ..100:10 --min-types 1 --fns 1,10
+    dispatch-test gen-all-cases --types 1..1000 --fns 1..100 --sample 50 --seed 3")]
     GenAllCases {
-        num_types: u32,
-        num_fns: u32,
-        step_types: u32,
-        step_fns: u32,
-        #[structopt(long)]
-        no_inline: bool,
-        #[structopt(long)]
-        no_dedup: bool,
-        #[structopt(long)]
-        predictable: bool,
+        #[command(flatten)]
+        axes: SweepAxes,
+        /// Generate only this many of the grid's cases, picked at
+        /// random, for grids too big to sweep whole. Fit the ratios'
+        /// trends over them with `report --trend`
+        #[arg(long, value_name = "N")]
+        sample: Option<usize>,
+        /// The seed --sample picks with, so a sample can be picked again
+        #[arg(long, default_value_t = 0, requires = "sample")]
+        seed: u64,
+        #[command(flatten)]
+        opts: GenOpts,
     },
+    /// Compile the cases recorded in the outdir's manifest,
+    /// optionally filtered to the given ranges
+    #[command(after_help = "EXAMPLES:
+    dispatch-test compile-all-cases --opt-level 3
+    dispatch-test compile-all-cases --types 1..8 --resume
+    dispatch-test compile-all-cases --container rust:1.75
+    dispatch-test compile-all-cases --target aarch64-unknown-linux-gnu
+    dispatch-test compile-all-cases --opt-level 3 --compare-mitigations
+    dispatch-test compile-all-cases --jobs 8
+    dispatch-test compile-all-cases --time-budget 1h --priority 64-16
+    dispatch-test compile-all-cases --opt-level 0,3 --lto off,fat --codegen-units 1,16")]
     CompileAllCases {
+        #[command(flatten)]
+        filter: AxisFilter,
+        #[command(flatten)]
+        budget: SweepBudget,
+        #[command(flatten)]
+        opts: CompileOpts,
+        /// Skip cases that a previous sweep already compiled
+        #[arg(long)]
+        resume: bool,
+        /// Compile cases in parallel, sharing this many jobserver tokens
+        /// with rustc's codegen threads. Under make, make's are used
+        #[arg(long, short, conflicts_with = "measure_compile")]
+        jobs: Option<usize>,
+        /// Every combination of the values listed for --opt-level, --lto
+        /// and --codegen-units, each compiled in a run of its own beside
+        /// this one. `run-all-cases` and `report` then cover every
+        /// combination
+        #[arg(skip)]
+        matrix: Option<matrix::Matrix>,
+    },
+    /// Run the cases recorded in the outdir's manifest,
+    /// optionally filtered to the given ranges
+    #[command(after_help = "EXAMPLES:
+    dispatch-test run-all-cases
+    dispatch-test --run-name inlining run-all-cases --fns 4 --resume
+    dispatch-test run-all-cases --remote bench@quietbox
+    dispatch-test run-all-cases --runner qemu
+    dispatch-test run-all-cases --sandbox
+    dispatch-test run-all-cases --refine 8
+    dispatch-test run-all-cases --time-budget 20m")]
+    RunAllCases {
+        #[command(flatten)]
+        filter: AxisFilter,
+        #[command(flatten)]
+        budget: SweepBudget,
+        /// Skip cases that a previous sweep already ran
+        #[arg(long)]
+        resume: bool,
+        /// Then add up to this many cases, one at a time, halfway
+        /// between the neighbouring cases whose dynamic/static run time
+        /// ratios differ most, favouring where the variants cross
+        #[arg(long, value_name = "N")]
+        refine: Option<u32>,
+        #[command(flatten)]
+        runner: RunnerOpts,
+    },
+    /// Predict a sweep's compile time and disk usage from a few
+    /// calibration cases, compiled in a run that's then removed
+    #[command(after_help = "EXAMPLES:
+    dispatch-test estimate --types 1..256:x2 --fns 1..64:x2
+    dispatch-test estimate --types 1..64:x2 --fns 16 --opt-level 3 --calibrate 5")]
+    Estimate {
+        #[command(flatten)]
+        axes: SweepAxes,
+        /// How many cases to calibrate on, from the smaller half of the grid
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        calibrate: u32,
+        #[command(flatten)]
+        gen_opts: GenOpts,
+        #[command(flatten)]
+        compile_opts: CompileOpts,
+    },
+    /// Describe a generated case in prose
+    #[command(after_help = "EXAMPLES:
+    dispatch-test explain 8 4
+    dispatch-test --run-name inlining explain 8 4")]
+    Explain {
         num_types: u32,
         num_fns: u32,
-        step_types: u32,
-        step_fns: u32,
-        #[structopt(long)]
-        asm: bool,
-        #[structopt(long, default_value = "0")]
-        opt_level: u32,
     },
-    RunAllCases {
+    /// Regenerate, compile and run one case whenever watched files
+    /// change, including the dispatch-test executable itself
+    #[command(after_help = "EXAMPLES:
+    dispatch-test watch 4 2 --opt-level 3
+    dispatch-test watch 4 2 --no-inline --path experiment.toml")]
+    Watch {
         num_types: u32,
         num_fns: u32,
-        step_types: u32,
-        step_fns: u32,
+        /// Also watch this file or directory
+        #[arg(long)]
+        path: Vec<PathBuf>,
+        #[command(flatten)]
+        gen_opts: GenOpts,
+        #[command(flatten)]
+        compile_opts: CompileOpts,
+    },
+    /// Generate, compile and run a built-in experiment in a new run
+    #[command(after_help = "EXAMPLES:
+    dispatch-test preset vtable-vs-generic
+    dispatch-test preset vtable-vs-enum
+    dispatch-test --run-name sizes preset size-embedded
+    dispatch-test preset compile-time-scaling --show
+    dispatch-test --run-name libc preset musl-vs-glibc
+    dispatch-test preset serde-style --show")]
+    Preset {
+        #[arg(value_enum)]
+        preset: preset::Preset,
+        /// Print the commands the preset runs instead of running them
+        #[arg(long)]
+        show: bool,
+    },
+    /// Generate, compile and run one stored case again with the options
+    /// it was measured with, and compare the fresh numbers
+    #[command(after_help = "EXAMPLES:
+    dispatch-test replay 8-4
+    dispatch-test --run-name inlining replay 16-2")]
+    Replay {
+        /// The case, as TYPES-FNS
+        case: replay::CaseId,
+    },
+    /// Draft a Markdown issue for rust-lang/rust about one case's most
+    /// lopsided measurement, with its reproduction and toolchain
+    #[command(after_help = "EXAMPLES:
+    dispatch-test report-issue 8-4 > issue.md
+    dispatch-test --run-name o3 report-issue 16-2")]
+    ReportIssue {
+        /// The case, as TYPES-FNS
+        case: replay::CaseId,
+    },
+    /// Build and run one stored case again at several -Ccodegen-units
+    /// settings, each in its own run, and tabulate each variant's
+    /// numbers against them
+    #[command(after_help = "EXAMPLES:
+    dispatch-test cgu-sweep 8-4
+    dispatch-test --run-name o3 cgu-sweep 16-2 --units 1,16")]
+    CguSweep {
+        /// The case, as TYPES-FNS
+        case: replay::CaseId,
+        /// The -Ccodegen-units settings to build with
+        #[arg(long, value_name = "N,...", value_delimiter = ',',
+              default_values_t = [1, 4, 16, 256])]
+        units: Vec<u32>,
+    },
+    /// Tabulate each variant's ratios to a baseline over the run's cases
+    #[command(after_help = "EXAMPLES:
+    dispatch-test report
+    dispatch-test report --geomean
+    dispatch-test report --by types --fns 4
+    dispatch-test report --pareto 8-4
+    dispatch-test report --trend
+    dispatch-test report --baseline dynamic
+    dispatch-test report --baseline concrete")]
+    Report {
+        #[command(flatten)]
+        filter: AxisFilter,
+        /// Roll cases up along this axis, taking the geometric mean of
+        /// each ratio over the other axis
+        #[arg(long, value_enum)]
+        by: Option<report::CaseAxis>,
+        /// Finish with the geometric mean of each ratio over all cases
+        #[arg(long)]
+        geomean: bool,
+        /// Instead list every run's builds of this case, as TYPES-FNS,
+        /// by binary size and run time, marking the Pareto frontier
+        #[arg(long, value_name = "CASE", conflicts_with_all = ["by", "geomean"])]
+        pareto: Option<replay::CaseId>,
+        /// Instead fit how each ratio grows with the types and fns, as
+        /// for a sweep of a `--sample` of the grid
+        #[arg(long, conflicts_with_all = ["by", "geomean", "pareto"])]
+        trend: bool,
+        /// The variant the other variants' ratios are taken against,
+        /// concrete for cases generated with --concrete
+        #[arg(long, value_enum, default_value_t = Dispatch::Static)]
+        baseline: Dispatch,
+    },
+    /// Find where each variant overtakes the baseline one along an axis
+    /// of the sweep
+    #[command(after_help = "EXAMPLES:
+    dispatch-test crossover
+    dispatch-test crossover --metric bin-size --along fns
+    dispatch-test crossover --fns 1,4,16
+    dispatch-test crossover --baseline concrete")]
+    Crossover {
+        #[command(flatten)]
+        filter: AxisFilter,
+        #[arg(long, value_enum, default_value_t = report::Metric::RunTime)]
+        metric: report::Metric,
+        /// The axis to search along, separately for each value of the
+        /// other
+        #[arg(long, value_enum, default_value_t = report::CaseAxis::Types)]
+        along: report::CaseAxis,
+        /// The variant the others are compared with, concrete for cases
+        /// generated with --concrete
+        #[arg(long, value_enum, default_value_t = Dispatch::Static)]
+        baseline: Dispatch,
     },
+    /// Find the fastest build of a case, across every run, that fits a
+    /// budget for binary size and compile time
+    #[command(after_help = "EXAMPLES:
+    dispatch-test recommend 8-4 --max-size 500K --max-compile 10s
+    dispatch-test recommend 64-16 --max-size 2MB")]
+    Recommend {
+        /// The case, as TYPES-FNS
+        case: replay::CaseId,
+        /// The largest acceptable binary, like 500K or 2MB
+        #[arg(long, value_name = "SIZE")]
+        max_size: Option<recommend::Bytes>,
+        /// The longest acceptable compile, like 10s or 1500ms
+        #[arg(long, value_name = "DURATION")]
+        max_compile: Option<recommend::Millis>,
+    },
+    /// Rank which parameters most strongly drive the gap between the
+    /// variants, over one or more runs
+    #[command(after_help = "EXAMPLES:
+    dispatch-test sensitivity
+    dispatch-test sensitivity o0 o2 o3")]
+    Sensitivity {
+        /// Names of the runs under outdir [default: --run-name or the
+        /// latest run]
+        runs: Vec<String>,
+    },
+    /// Compare repeated runs of the same sweep and report how much each
+    /// case's measurements vary between them
+    #[command(after_help = "EXAMPLES:
+    dispatch-test variance monday tuesday wednesday
+    dispatch-test variance a b c --max-cv 2 --precision 0.5")]
+    Variance {
+        /// Names of the runs under outdir
+        #[arg(required = true, num_args = 2..)]
+        runs: Vec<String>,
+        /// The largest coefficient of variation, in percent, counted as
+        /// stable
+        #[arg(long, default_value_t = 5.0)]
+        max_cv: f64,
+        /// The precision, in percent, to estimate repetitions for
+        #[arg(long, default_value_t = 1.0)]
+        precision: f64,
+    },
+    /// Pack a run's manifest and results into a .tar.zst
+    #[command(after_help = "EXAMPLES:
+    dispatch-test --run-name baseline archive
+    dispatch-test archive --with-sources --output ~/results/laptop.tar.zst")]
+    Archive {
+        /// Include the generated sources
+        #[arg(long)]
+        with_sources: bool,
+        /// Where to write the archive [default: <outdir>/<run>.tar.zst]
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Unpack an archived run into the outdir
+    #[command(after_help = "EXAMPLES:
+    dispatch-test import laptop.tar.zst
+    dispatch-test --run-name from-ci import results.tar.zst")]
+    Import {
+        archive: PathBuf,
+    },
+    /// Check that the tools sweeps need are installed and working
+    #[command(after_help = "EXAMPLES:
+    dispatch-test doctor
+    dispatch-test doctor --cxx clang++
+    RUSTC_BOOTSTRAP=1 dispatch-test --outdir /scratch/cases doctor")]
+    Doctor {
+        /// The C++ compiler sweeps will be given as --cxx
+        #[arg(long, value_name = "PATH", default_value = "c++")]
+        cxx: String,
+    },
+    /// Print a shell completion script to stdout
+    #[command(after_help = "EXAMPLES:
+    dispatch-test completions bash > /etc/bash_completion.d/dispatch-test
+    dispatch-test completions zsh > ~/.zfunc/_dispatch-test")]
+    Completions {
+        #[arg(ignore_case = true)]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// The grid of cases to generate. Each axis takes a spec like
+/// `1..64:4`, `1..64:x2` or `1,2,4,8,16`.
+#[derive(Debug, Args)]
+struct SweepAxes {
+    /// Numbers of types to generate
+    #[arg(long)]
+    types: Axis,
+    /// Numbers of fns to generate
+    #[arg(long)]
+    fns: Axis,
+    /// Where `--types` ranges without a start begin
+    #[arg(long, default_value_t = 0)]
+    min_types: u32,
+    /// Where `--fns` ranges without a start begin
+    #[arg(long, default_value_t = 0)]
+    min_fns: u32,
+}
+
+/// Restricts the generated cases a phase operates on. Each axis takes
+/// the same specs as `SweepAxes`.
+#[derive(Debug, Args)]
+struct AxisFilter {
+    /// Only include cases with these numbers of types
+    #[arg(long)]
+    types: Option<Axis>,
+    /// Only include cases with these numbers of fns
+    #[arg(long)]
+    fns: Option<Axis>,
+}
+
+impl AxisFilter {
+    fn matches(&self, num_types: u32, num_fns: u32) -> bool {
+        self.types.as_ref().is_none_or(|a| a.contains(num_types))
+            && self.fns.as_ref().is_none_or(|a| a.contains(num_fns))
+    }
+}
+
+/// Caps how long a compile or run sweep starts new cases for
+#[derive(Debug, Args)]
+struct SweepBudget {
+    /// Stop starting cases after this long, like 1h or 90m, taking them
+    /// smallest first, and report how much of the grid was covered
+    #[arg(long, value_name = "DURATION")]
+    time_budget: Option<recommend::Millis>,
+    /// Under --time-budget, take these cases first, as TYPES-FNS
+    #[arg(long, value_name = "CASE,...", value_delimiter = ',', requires = "time_budget")]
+    priority: Vec<replay::CaseId>,
+    /// When the budget runs out, shared by every run a sweep covers
+    #[arg(skip)]
+    deadline: Option<Instant>,
+}
+
+impl SweepBudget {
+    /// Starts the clock, so each matrix combination's sweep draws on
+    /// what the ones before it left
+    fn start(mut self) -> SweepBudget {
+        self.deadline = self.time_budget
+            .map(|recommend::Millis(ms)| Instant::now() + Duration::from_millis(ms));
+        self
+    }
+}
+
+/// Where the run phases execute case binaries
+#[derive(Debug, Args)]
+struct RunnerOpts {
+    /// Copy the binaries to this ssh destination and run them there
+    #[arg(long, value_name = "USER@HOST")]
+    remote: Option<String>,
+    /// Run the binaries inside this Docker or Podman image
+    #[arg(long, value_name = "IMAGE", conflicts_with = "remote")]
+    container: Option<String>,
+    /// How to execute the binaries. `qemu` emulates cases compiled
+    /// with a foreign --target
+    #[arg(long, value_enum, default_value_t = RunnerKind::Native,
+          conflicts_with_all = ["remote", "container"])]
+    runner: RunnerKind,
+    /// Run each binary this many times, recording statistics and a
+    /// confidence interval on the dynamic/static ratio
+    #[arg(long, default_value_t = 1)]
+    repeat: u32,
+    /// Retry a failed run this many times, with backoff, before
+    /// counting the case as failed
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+    /// Cap each local case binary's address space, like 2G. Cases that
+    /// go over are recorded as such, and sweeps carry on
+    #[arg(long, value_name = "SIZE")]
+    mem_limit: Option<recommend::Bytes>,
+    /// Run the binaries under bubblewrap, with a read-only filesystem
+    /// and no network, for cases from untrusted templates
+    #[arg(long, conflicts_with_all = ["remote", "container", "runner"])]
+    sandbox: bool,
+    /// Drop each binary from the page cache before every launch, so run
+    /// times include loading it from disk
+    #[arg(long, conflicts_with_all = ["remote", "container", "runner", "sandbox"])]
+    cold_cache: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RunnerKind {
+    Native,
+    Qemu,
+}
+
+enum Runner {
+    Local { mem_limit: Option<u64>, cold_cache: bool },
+    Remote(remote::Remote),
+    Container(container::Container),
+    Sandbox(sandbox::Sandbox),
+    Qemu(qemu::Qemu),
+}
+
+impl RunnerOpts {
+    fn runner(&self) -> Result<Runner> {
+        if self.runner == RunnerKind::Qemu {
+            Ok(Runner::Qemu(qemu::Qemu::new()))
+        } else if let Some(ref dest) = self.remote {
+            Ok(Runner::Remote(remote::Remote::connect(dest)?))
+        } else if let Some(ref image) = self.container {
+            Ok(Runner::Container(container::Container::new(image)?))
+        } else if self.sandbox {
+            Ok(Runner::Sandbox(sandbox::Sandbox::new(self.mem_limit.map(|b| b.0))?))
+        } else {
+            Ok(Runner::Local {
+                mem_limit: self.mem_limit.map(|b| b.0),
+                cold_cache: self.cold_cache,
+            })
+        }
+    }
+}
+
+impl Runner {
+    /// Runs a binary compiled for `target`, or the host if `None`. Its
+    /// output is thrown away, unless `read_checksum` asks for the sum it
+    /// prints.
+    fn run_case(&self, bin: &Path, target: Option<&str>,
+                read_checksum: bool) -> Result<results::VariantRunResults> {
+        if let Runner::Qemu(qemu) = self {
+            return qemu.run_case(bin, target, read_checksum);
+        }
+        let (time, stdout) = self.time(bin, target, read_checksum)?;
+
+        Ok(results::VariantRunResults {
+            run_time_ms: time.as_millis() as u64,
+            run_time_stats: None,
+            instructions: None,
+            launch_time_stats: None,
+            checksum: stdout.map(|out| checksum::read(bin, &out)).transpose()?,
+        })
+    }
+
+    /// One launch's wall time, and its stdout if `capture`
+    fn time(&self, bin: &Path, target: Option<&str>,
+            capture: bool) -> Result<(Duration, Option<String>)> {
+        Ok(match self {
+            Runner::Local { mem_limit, cold_cache } => {
+                if let Some(target) = target {
+                    let arch = qemu::target_arch(target);
+                    if arch != env::consts::ARCH {
+                        bail!("{} was compiled for {}, which can't run on \
+                               this {} host; pass --runner qemu",
+                              bin.display(), target, env::consts::ARCH);
+                    }
+                }
+                if *cold_cache {
+                    pagecache::evict(bin)?;
+                }
+                run_case(bin, *mem_limit, capture)?
+            }
+            Runner::Remote(remote) => remote.run_case(bin, capture)?,
+            Runner::Container(container) => container.time_case(bin, capture)?,
+            Runner::Sandbox(sandbox) => sandbox.time(bin, capture)?,
+            Runner::Qemu(_) => {
+                bail!("launch times under qemu would mostly be qemu's own \
+                       startup; run --cold-start cases natively")
+            }
+        })
+    }
+
+    /// Runs a binary, retrying it up to `retries` times if it fails
+    fn run_retried(&self, bin: &Path, target: Option<&str>, retries: u32,
+                   read_checksum: bool) -> Result<results::VariantRunResults> {
+        retry::retry(retries, || self.run_case(bin, target, read_checksum))
+    }
+
+    /// Runs a binary `repeat` times, keeping the median run time and,
+    /// when repeated, the statistics over all of them. Only the first
+    /// run's sum is read under `read_checksum`.
+    fn run_samples(&self, bin: &Path, target: Option<&str>, repeat: u32,
+                   retries: u32, read_checksum: bool) -> Result<results::VariantRunResults> {
+        let mut first = self.run_retried(bin, target, retries, read_checksum)?;
+        if repeat <= 1 {
+            return Ok(first);
+        }
+
+        let mut samples = vec![first.run_time_ms];
+        for _ in 1..repeat {
+            samples.push(self.run_retried(bin, target, retries, false)?.run_time_ms);
+        }
+        let stats = stats::Stats::new(samples);
+        first.run_time_ms = stats.median;
+        first.run_time_stats = Some(stats);
+
+        Ok(first)
+    }
+
+    /// Launches a --cold-start binary `launches` times, keeping the
+    /// statistics of each launch's time in microseconds, and the first
+    /// launch's sum under `read_checksum`
+    fn launch_samples(&self, bin: &Path, target: Option<&str>, launches: u32,
+                      retries: u32, read_checksum: bool) -> Result<results::VariantRunResults> {
+        let mut samples = vec![];
+        let mut sum = None;
+        for launch in 0..launches.max(1) {
+            let capture = read_checksum && launch == 0;
+            let (time, stdout) = retry::retry(retries, || self.time(bin, target, capture))?;
+            if let Some(out) = stdout {
+                sum = Some(checksum::read(bin, &out)?);
+            }
+            samples.push(time.as_micros() as u64);
+        }
+        let stats = stats::Stats::new(samples);
+
+        Ok(results::VariantRunResults {
+            run_time_ms: stats.median / 1000,
+            run_time_stats: None,
+            instructions: None,
+            launch_time_stats: Some(stats),
+            checksum: sum,
+        })
+    }
 }
 
-#[derive(Debug, StructOpt)]
+#[derive(Debug, Clone, Args)]
 struct GlobalOptions {
-    #[structopt(default_value = "cases", long)]
+    #[arg(long, default_value = "cases")]
     outdir: PathBuf,
+    /// Name of the run directory under outdir. gen-all-cases and presets
+    /// default to a new timestamped run, gen-one-case to the latest run
+    /// or else a new one, and the other phases to the latest run.
+    #[arg(long)]
+    run_name: Option<String>,
+    /// Wait for another instance using the same run instead of failing
+    #[arg(long)]
+    wait_lock: bool,
+    /// Shell command to run when the phase finishes or fails. It gets
+    /// the results path and a summary as $1 and $2, and DISPATCH_TEST_*
+    /// environment variables
+    #[arg(long, value_name = "COMMAND")]
+    on_complete: Option<String>,
+    /// Stream a record of each case to stdout as a sweep finishes it,
+    /// with its results so far or why it failed. Progress goes to
+    /// stderr instead, so stdout can be parsed
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    stream: Option<stream::Format>,
+    /// Append streamed records to this file instead
+    #[arg(long, value_name = "PATH", requires = "stream")]
+    stream_file: Option<PathBuf>,
+    /// Serve the sweep's progress and latest case's measurements for
+    /// Prometheus at http://ADDR/metrics while it runs
+    #[arg(long, value_name = "ADDR")]
+    metrics_listen: Option<String>,
+}
+
+static LATEST_RUN_FILE: &str = "latest-run";
+
+fn new_run_dir(global: &GlobalOptions) -> Result<PathBuf> {
+    let label = match global.run_name {
+        Some(ref name) => name.clone(),
+        None => chrono::Local::now().format("%Y%m%d-%H%M%S").to_string(),
+    };
+    let run_dir = global.outdir.join(&label);
+    fs::create_dir_all(&run_dir)?;
+
+    Ok(run_dir)
+}
+
+/// The latest run, if there is one and it's still there
+fn latest_run_dir(outdir: &Path) -> Result<Option<PathBuf>> {
+    let latest = outdir.join(LATEST_RUN_FILE);
+    if !latest.exists() {
+        return Ok(None);
+    }
+    let run_dir = outdir.join(fs::read_to_string(latest)?.trim());
+
+    Ok(Some(run_dir).filter(|dir| dir.is_dir()))
+}
+
+/// Names the run a derived run was made from, marking it as one
+static DERIVED_FROM_FILE: &str = "derived-from";
+
+/// Empties or creates `dir` for a run the command derives from
+/// `run_dir`, as presets, replays and sweeps do for their siblings.
+/// Only a directory marked as derived before is replaced, so a run that
+/// happens to have the name survives.
+fn fresh_derived_run(dir: &Path, run_dir: &Path) -> Result<()> {
+    if dir.exists() {
+        if !dir.join(DERIVED_FROM_FILE).is_file() {
+            bail!("{} exists and wasn't made by dispatch-test from another run; \
+                   move it or pick another run name", dir.display());
+        }
+        fs::remove_dir_all(dir)?;
+    }
+    fs::create_dir_all(dir)?;
+    let from = run_dir.file_name().expect("run dirs have names");
+    fs::write(dir.join(DERIVED_FROM_FILE), from.to_string_lossy().as_bytes())?;
+
+    Ok(())
+}
+
+fn existing_run_dir(global: &GlobalOptions) -> Result<PathBuf> {
+    let label = match global.run_name {
+        Some(ref name) => name.clone(),
+        None => {
+            let latest = global.outdir.join(LATEST_RUN_FILE);
+            if !latest.exists() {
+                bail!("no runs in {}; generate cases first",
+                      global.outdir.display());
+            }
+            fs::read_to_string(latest)?.trim().to_string()
+        }
+    };
+    let run_dir = global.outdir.join(&label);
+    if !run_dir.is_dir() {
+        bail!("run {} does not exist in {}", label, global.outdir.display());
+    }
+
+    Ok(run_dir)
+}
+
+fn main() {
+    if let Err(e) = try_main() {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(error::exit_code(&e));
+    }
 }
 
-fn main() -> Result<()> {
-    let options = Options::from_args();
+fn try_main() -> Result<()> {
+    let matches = matrix::accept_lists(Options::command()).get_matches();
+    let mut options = Options::from_arg_matches(&matches)
+        .unwrap_or_else(|e| e.exit());
+    if let Cmd::CompileAllCases { ref mut matrix, .. } = options.cmd {
+        *matrix = matrix::lists(&matches).and_then(matrix::Matrix::varied);
+    }
+    let subcommand = matches.subcommand_name().unwrap_or_default().to_string();
 
     match options.cmd {
-        Cmd::GenOneCase { num_types, num_fns,
-                          no_inline, no_dedup, predictable } => {
-            let config = CaseConfig {
+        Cmd::GenOneCase { ref opts, .. } |
+        Cmd::GenAllCases { ref opts, .. } => {
+            opts.validate().unwrap_or_else(|e| e.exit());
+        }
+        Cmd::CompileOneCase { ref opts, .. } |
+        Cmd::CompileAllCases { ref opts, .. } => {
+            opts.validate().unwrap_or_else(|e| e.exit());
+            if let Cmd::CompileAllCases { matrix: Some(ref matrix), .. } = options.cmd {
+                for (_, opts) in matrix.expand(opts) {
+                    opts.validate().unwrap_or_else(|e| e.exit());
+                }
+            }
+            if opts.measure_compile {
+                warn_if_busy();
+            }
+        }
+        Cmd::Completions { shell } => {
+            clap_complete::generate(shell, &mut matrix::accept_lists(Options::command()),
+                                    "dispatch-test", &mut std::io::stdout());
+            return Ok(());
+        }
+        Cmd::Doctor { ref cxx } => {
+            doctor::doctor(&options.global.outdir, cxx)?;
+            return Ok(());
+        }
+        Cmd::Import { ref archive } => {
+            archive::import(&options.global.outdir, archive,
+                            options.global.run_name.clone())?;
+            return Ok(());
+        }
+        Cmd::Preset { preset, show: true } => {
+            for command in preset.commands() {
+                println!("{}", command);
+            }
+            return Ok(());
+        }
+        Cmd::Recommend { case, max_size, max_compile } => {
+            recommend::recommend(&options.global.outdir, case, recommend::Budget {
+                max_size, max_compile,
+            })?;
+            return Ok(());
+        }
+        Cmd::Sensitivity { ref runs } => {
+            let run_dirs = if runs.is_empty() {
+                vec![existing_run_dir(&options.global)?]
+            } else {
+                runs.iter()
+                    .map(|name| existing_run_dir(&GlobalOptions {
+                        run_name: Some(name.clone()),
+                        ..options.global.clone()
+                    }))
+                    .collect::<Result<_>>()?
+            };
+            sensitivity::sensitivity(&run_dirs)?;
+            return Ok(());
+        }
+        Cmd::Variance { ref runs, max_cv, precision } => {
+            // Reads finished runs, so none of them is locked
+            let run_dirs = runs.iter()
+                .map(|name| existing_run_dir(&GlobalOptions {
+                    run_name: Some(name.clone()),
+                    ..options.global.clone()
+                }))
+                .collect::<Result<_>>()?;
+            variance::variance(variance::VarianceConfig {
+                run_dirs, max_cv, precision,
+            })?;
+            return Ok(());
+        }
+        Cmd::Estimate { ref axes, calibrate, ref gen_opts, ref compile_opts } => {
+            gen_opts.validate().unwrap_or_else(|e| e.exit());
+            compile_opts.validate().unwrap_or_else(|e| e.exit());
+            estimate::estimate(estimate::EstimateConfig {
                 outdir: options.global.outdir.clone(),
+                types: axes.types.clone().with_min(axes.min_types),
+                fns: axes.fns.clone().with_min(axes.min_fns),
+                calibrate,
+                gen_opts: gen_opts.clone(),
+                compile_opts: compile_opts.clone(),
+            })?;
+            return Ok(());
+        }
+        Cmd::Watch { num_types, num_fns, ref path,
+                     ref gen_opts, ref compile_opts } => {
+            gen_opts.validate().unwrap_or_else(|e| e.exit());
+            compile_opts.validate().unwrap_or_else(|e| e.exit());
+            // The phases run as separate processes and take the lock
+            // themselves
+            watch::watch(watch::WatchConfig {
+                outdir: options.global.outdir.clone(),
+                run_name: options.global.run_name.clone()
+                    .unwrap_or_else(|| "watch".to_string()),
+                num_types, num_fns,
+                paths: path.clone(),
+                gen_opts: gen_opts.clone(),
+                compile_opts: compile_opts.clone(),
+            })?;
+            return Ok(());
+        }
+        _ => { }
+    }
+
+    let latest = match options.global.run_name {
+        Some(_) => None,
+        None => latest_run_dir(&options.global.outdir)?,
+    };
+    let outdir = match (&options.cmd, latest) {
+        // Cases generated one at a time go in the latest run, instead
+        // of each starting one
+        (Cmd::GenOneCase { .. }, Some(latest)) => latest,
+        (Cmd::GenOneCase { .. }, None) |
+        (Cmd::GenAllCases { .. }, _) |
+        (Cmd::Preset { .. }, _) => new_run_dir(&options.global)?,
+        _ => existing_run_dir(&options.global)?,
+    };
+
+    let _lock = lock::lock_outdir(&outdir, options.global.wait_lock)?;
+
+    // Only now the run is locked, so no other command picks it up first
+    if matches!(options.cmd, Cmd::GenOneCase { .. } | Cmd::GenAllCases { .. }
+                | Cmd::Preset { .. }) {
+        let label = outdir.file_name().expect("run dirs have names");
+        fs::write(options.global.outdir.join(LATEST_RUN_FILE),
+                  label.to_string_lossy().as_bytes())?;
+    }
+
+    if let Some(format) = options.global.stream {
+        stream::init(format, options.global.stream_file.as_deref())?;
+    }
+    if let Some(ref addr) = options.global.metrics_listen {
+        metrics::listen(addr, &subcommand)?;
+    }
+
+    let start = Instant::now();
+    let result = run_cmd(options.cmd, &outdir);
+
+    if let Some(ref hook) = options.global.on_complete {
+        hook::on_complete(hook, &subcommand, &outdir, start.elapsed(), &result);
+    }
+
+    result
+}
+
+fn run_cmd(cmd: Cmd, outdir: &Path) -> Result<()> {
+    match cmd {
+        Cmd::GenOneCase { num_types, num_fns, opts } => {
+            let config = CaseConfig {
+                outdir: outdir.to_owned(),
                 num_types, num_fns,
-            };
-            let opts = GenOpts {
-                no_inline, no_dedup, predictable
             };
             gen_one_case(config, opts)?;
         }
-        Cmd::CompileOneCase { num_types, num_fns,
-                              asm, opt_level } => {
+        Cmd::CompileOneCase { num_types, num_fns, opts, print_repro } => {
             let config = CaseConfig {
-                outdir: options.global.outdir.clone(),
+                outdir: outdir.to_owned(),
                 num_types, num_fns,
             };
-            let opts = CompileOpts {
-                asm, opt_level
-            };
-            compile_one_case(config, opts)?;
+            let result = compile_one_case(config.clone(), opts);
+            record_mem_limit(&config, result)?;
+            if print_repro {
+                print!("{}", repro::render(&config)?);
+            }
         }
-        Cmd::RunOneCase { num_types, num_fns } => {
+        Cmd::RunOneCase { num_types, num_fns, runner } => {
             let config = CaseConfig {
-                outdir: options.global.outdir.clone(),
+                outdir: outdir.to_owned(),
                 num_types, num_fns,
             };
-            run_one_case(config)?;
+            let result = run_one_case(config.clone(), &runner.runner()?,
+                                      runner.repeat, runner.retries);
+            record_mem_limit(&config, result)?;
         }
-        Cmd::GenAllCases { num_types, num_fns,
-                           step_types, step_fns,
-                           no_inline, no_dedup, predictable } => {
+        Cmd::GenAllCases { axes, sample, seed, opts } => {
             let config = MultiCaseConfig {
-                outdir: options.global.outdir.clone(),
-                num_types, num_fns,
-                step_types, step_fns,
-            };
-            let opts = GenOpts {
-                no_inline, no_dedup, predictable
+                outdir: outdir.to_owned(),
+                types: axes.types.with_min(axes.min_types),
+                fns: axes.fns.with_min(axes.min_fns),
+                sample: sample.map(|n| (n, seed)),
             };
             gen_all_cases(config, opts)?;
         }
-        Cmd::CompileAllCases { num_types, num_fns,
-                               step_types, step_fns,
-                               asm, opt_level } => {
-            let config = MultiCaseConfig {
-                outdir: options.global.outdir.clone(),
-                num_types, num_fns,
-                step_types, step_fns,
-            };
-            let opts = CompileOpts {
-                asm, opt_level
-            };
-            compile_all_cases(config, opts)?;
+        Cmd::CompileAllCases { filter, budget, opts, resume, jobs, matrix } => {
+            let budget = budget.start();
+            match matrix {
+                Some(matrix) => matrix::compile(outdir, &matrix, &opts, &filter, |dir, opts| {
+                    compile_all_cases(dir, &filter, &budget, resume, jobs, opts)
+                })?,
+                None => {
+                    matrix::clear(outdir)?;
+                    compile_all_cases(outdir, &filter, &budget, resume, jobs, opts)?
+                }
+            }
         }
-        Cmd::RunAllCases { num_types, num_fns,
-                           step_types, step_fns, } => {
-            let config = MultiCaseConfig {
-                outdir: options.global.outdir.clone(),
-                num_types, num_fns,
-                step_types, step_fns,
-            };
-            run_all_cases(config)?;
+        Cmd::RunAllCases { filter, budget, resume, refine, runner } => {
+            let runner_opts = runner;
+            let runner = runner_opts.runner()?;
+            let budget = budget.start();
+            for (flags, dir) in matrix::runs(outdir)? {
+                if let Some(flags) = flags {
+                    println!("flags: {}", flags);
+                }
+                run_all_cases(&dir, &filter, &budget, resume, &runner,
+                              runner_opts.repeat, runner_opts.retries)?;
+                if let Some(cases) = refine {
+                    refine::refine(&dir, &filter, cases, &runner,
+                                   runner_opts.repeat, runner_opts.retries)?;
+                }
+            }
+        }
+        Cmd::Explain { num_types, num_fns } => {
+            explain::explain(outdir, num_types, num_fns)?;
+        }
+        Cmd::Report { filter, by, geomean, pareto, trend, baseline } => {
+            report::report(outdir, &filter, report::ReportConfig {
+                by, geomean, pareto, trend, baseline,
+            })?;
+        }
+        Cmd::Crossover { filter, metric, along, baseline } => {
+            crossover::crossover(outdir, &filter,
+                                 crossover::CrossoverConfig { metric, along, baseline })?;
         }
+        Cmd::Replay { case } => {
+            replay::replay(outdir, case)?;
+        }
+        Cmd::ReportIssue { case } => {
+            issue::report_issue(outdir, case)?;
+        }
+        Cmd::CguSweep { case, units } => {
+            cgu::cgu_sweep(outdir, case, &units)?;
+        }
+        Cmd::Preset { preset, .. } => {
+            preset::run(outdir, preset)?;
+        }
+        Cmd::Archive { with_sources, output } => {
+            archive::archive(outdir, with_sources, output)?;
+        }
+        Cmd::Import { .. } |
+        Cmd::Doctor { .. } |
+        Cmd::Variance { .. } |
+        Cmd::Sensitivity { .. } |
+        Cmd::Recommend { .. } |
+        Cmd::Estimate { .. } |
+        Cmd::Watch { .. } |
+        Cmd::Completions { .. } => unreachable!(),
     }
 
     Ok(())
 }
 
+#[derive(Clone)]
 struct CaseConfig {
     outdir: PathBuf,
     num_types: u32,
@@ -164,358 +1043,3100 @@ struct CaseConfig {
 
 struct MultiCaseConfig {
     outdir: PathBuf,
-    num_types: u32,
-    num_fns: u32,
-    step_types: u32,
-    step_fns: u32,
+    types: Axis,
+    fns: Axis,
+    /// How many cases to pick at random from the grid, and the seed
+    sample: Option<(usize, u64)>,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Args, Serialize, Deserialize)]
 struct CompileOpts {
+    /// Also emit assembly next to the binaries
+    #[arg(long)]
     asm: bool,
-    opt_level: u32,
+    /// Passed to rustc as -Copt-level
+    #[arg(long, value_enum, default_value_t = OptLevel::O0)]
+    opt_level: OptLevel,
+    /// Passed to rustc as -Clto
+    #[arg(long, value_enum)]
+    lto: Option<Lto>,
+    /// Which codegen backend rustc uses
+    #[arg(long, value_enum, default_value_t = Backend::Llvm)]
+    codegen_backend: Backend,
+    /// Passed to rustc as -Crelocation-model. Position-independent
+    /// binaries make some calls and loads through the PLT and GOT
+    #[arg(long, value_enum)]
+    #[serde(default)]
+    relocation_model: Option<RelocModel>,
+    /// Link position-dependent executables, which for rustc means the
+    /// static relocation model
+    #[arg(long, conflicts_with = "relocation_model")]
+    #[serde(default)]
+    no_pie: bool,
+    /// Passed to rustc as -Ccodegen-units
+    #[arg(long, value_name = "N")]
+    #[serde(default)]
+    codegen_units: Option<u32>,
+    /// How the binaries link the standard library and libc, instead of
+    /// std statically and libc dynamically
+    #[arg(long, value_enum)]
+    #[serde(default)]
+    linking: Option<Linking>,
+    /// Run rustc inside this Docker or Podman image
+    #[arg(long, value_name = "IMAGE")]
+    #[serde(default)]
+    container: Option<String>,
+    /// Passed to rustc, or a clang --cxx, as --target. Run foreign
+    /// targets with --runner qemu
+    #[arg(long, value_name = "TRIPLE")]
+    #[serde(default)]
+    target: Option<String>,
+    /// Passed to rustc as -Ctarget-cpu, or to C++ as -march
+    #[arg(long, value_name = "CPU")]
+    #[serde(default)]
+    target_cpu: Option<String>,
+    /// Passed to rustc as -Ctarget-feature, like +retpoline-indirect-calls.
+    /// May be repeated
+    #[arg(long, value_name = "FEATURE")]
+    #[serde(default)]
+    target_feature: Vec<String>,
+    /// Also build both variants without --target-cpu and
+    /// --target-feature, and run those baselines beside them
+    #[arg(long, conflicts_with = "compare_mitigations")]
+    #[serde(default)]
+    compare_target: bool,
+    /// Build with indirect-branch mitigations (retpolines), which make
+    /// every indirect call, including vtable calls, more expensive
+    #[arg(long)]
+    #[serde(default)]
+    mitigations: bool,
+    /// Build with --mitigations, and also build both variants without
+    /// them as baselines so the run phase reports what they cost
+    #[arg(long)]
+    #[serde(default)]
+    compare_mitigations: bool,
+    /// The C++ compiler for cases generated with --lang cpp
+    #[arg(long, value_name = "PATH", default_value = "c++")]
+    #[serde(default = "default_cxx")]
+    cxx: String,
+    /// Build each variant --repeat times serially and record
+    /// statistics, for compile times worth comparing
+    #[arg(long)]
+    #[serde(default)]
+    measure_compile: bool,
+    /// How many builds --measure-compile makes of each variant
+    #[arg(long, default_value_t = 5, requires = "measure_compile")]
+    #[serde(default = "default_repeat")]
+    repeat: u32,
+    /// Drop the OS page cache before each build (needs root)
+    #[arg(long, requires = "measure_compile")]
+    #[serde(default)]
+    drop_caches: bool,
+    /// Build with rustc's incremental state, starting each build cold,
+    /// and also time a warm rebuild that reuses it
+    #[arg(long)]
+    #[serde(default)]
+    incremental: bool,
+    /// Stop each build after codegen and link as a separate step, so
+    /// the two are timed independently
+    #[arg(long)]
+    #[serde(default)]
+    split_link: bool,
+    /// Count the items rustc monomorphizes for each variant with
+    /// -Zprint-mono-items, in an extra untimed build
+    #[arg(long, conflicts_with = "container")]
+    #[serde(default)]
+    mono_items: bool,
+    /// Build each variant with a profile from an untimed training run
+    /// of an instrumented build, as rustc's -Cprofile-generate and
+    /// -Cprofile-use do. Needs llvm-profdata from the llvm-tools
+    /// component
+    #[arg(long, conflicts_with = "container")]
+    #[serde(default)]
+    pgo: bool,
+    /// Retry a failed compiler or linker run this many times, with
+    /// backoff, before counting the case as failed
+    #[arg(long, default_value_t = 0)]
+    #[serde(default)]
+    retries: u32,
+    /// Build reproducibly, remapping paths out of the binaries and
+    /// fixing the crate metadata, then build each variant again to
+    /// check the two builds are identical
+    #[arg(long)]
+    #[serde(default)]
+    deterministic: bool,
+    /// Cap each local compiler and linker's address space, like 8G.
+    /// Cases that go over are recorded as such, and sweeps carry on
+    #[arg(long, value_name = "SIZE")]
+    #[serde(default)]
+    mem_limit: Option<recommend::Bytes>,
+    /// Compile flags for the static variant only, over the ones above,
+    /// like "--lto fat --opt-level 3". Takes --opt-level, --lto,
+    /// --no-lto, --codegen-backend, --relocation-model, --target-cpu
+    /// and --target-feature
+    #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+    #[serde(default)]
+    static_opts: Option<variant_opts::VariantOpts>,
+    /// Compile flags for the dynamic variant only, like --static-opts
+    #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+    #[serde(default)]
+    dynamic_opts: Option<variant_opts::VariantOpts>,
 }
 
-#[derive(Clone)]
-struct GenOpts {
-    no_inline: bool,
-    no_dedup: bool,
-    predictable: bool,
+fn default_repeat() -> u32 {
+    5
 }
 
-fn prereport(action: &str, config: &CaseConfig) {
-    println!("{} case: {} types / {} fns",
-             action,
-             config.num_types,
-             config.num_fns);
+fn default_cxx() -> String {
+    "c++".to_string()
 }
 
-fn gen_one_case(config: CaseConfig, opts: GenOpts) -> Result<()> {
-    prereport("generating", &config);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+enum OptLevel {
+    #[value(name = "0")]
+    #[serde(rename = "0")]
+    O0,
+    #[value(name = "1")]
+    #[serde(rename = "1")]
+    O1,
+    #[value(name = "2")]
+    #[serde(rename = "2")]
+    O2,
+    #[value(name = "3")]
+    #[serde(rename = "3")]
+    O3,
+    #[value(name = "s")]
+    #[serde(rename = "s")]
+    Os,
+    #[value(name = "z")]
+    #[serde(rename = "z")]
+    Oz,
+}
 
-    let (static_path, dynamic_path) = gen_src_paths(&config);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Lto {
+    /// Also turns off the local ThinLTO rustc does by default
+    Off,
+    Thin,
+    Fat,
+}
 
-    gen_static(&config, &static_path, opts.clone())?;
-    gen_dynamic(&config, &dynamic_path, opts)?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RelocModel {
+    Pic,
+    Static,
+}
 
-    Ok(())
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Linking {
+    /// Link libstd as a shared library (-Cprefer-dynamic), found
+    /// through an rpath into the local toolchain, so the binaries only
+    /// run where it's installed
+    PreferDynamic,
+    /// Link libc statically too (+crt-static), or with a musl --target
+    /// a fully static build
+    Static,
 }
 
-fn compile_one_case(config: CaseConfig, opts: CompileOpts) -> Result<()> {
-    prereport("compiling", &config);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Backend {
+    Llvm,
+    /// Requires a nightly toolchain with the cranelift component
+    Cranelift,
+}
 
-    let (static_src_path, dynamic_src_path) = gen_src_paths(&config);
-    let (static_bin_path, dynamic_bin_path) = gen_bin_paths(&config);
+/// Displays a `ValueEnum` as its command-line spelling, which is also
+/// what rustc expects
+macro_rules! display_as_value {
+    ($($t:ty),*) => { $(
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let value = self.to_possible_value().expect("no skipped values");
+                f.write_str(value.get_name())
+            }
+        }
+    )* }
+}
 
-    let static_time = run_rustc_bin(&static_src_path, &static_bin_path, &opts)?;
-    let dynamic_time = run_rustc_bin(&dynamic_src_path, &dynamic_bin_path, &opts)?;
+display_as_value!(OptLevel, Lto, Backend, RelocModel, Linking, Dispatch, DynamicVia,
+                  StaticVia, CallShape, BlackBox, BranchOrder, ErrorPath, skew::Skew,
+                  report::Metric, report::CaseAxis, preset::Preset);
 
-    println!("static-compile-time  : {}", static_time.as_millis());
-    println!("dynamic-compile-time : {}", dynamic_time.as_millis());
+impl CompileOpts {
+    /// These options with a variant's --static-opts or --dynamic-opts
+    /// applied
+    fn for_variant(&self, dispatch: Dispatch) -> CompileOpts {
+        let mut opts = self.clone();
+        opts.static_opts = None;
+        opts.dynamic_opts = None;
+        let overrides = match dispatch {
+            Dispatch::Static => &self.static_opts,
+            Dispatch::Dynamic => &self.dynamic_opts,
+            // The baseline is built with just the shared options
+            Dispatch::Concrete => &None,
+        };
+        if let Some(overrides) = overrides {
+            overrides.apply(&mut opts);
+        }
+        opts
+    }
 
-    let static_size = fs::metadata(&static_bin_path)?.len();
-    let dynamic_size = fs::metadata(&dynamic_bin_path)?.len();
+    /// Under --compare-target or --compare-mitigations, these options
+    /// without the flags being compared
+    fn baseline(&self) -> Option<CompileOpts> {
+        if !self.compare_target && !self.compare_mitigations {
+            return None;
+        }
 
-    println!("static-bin-size      : {}", static_size);
-    println!("dynamic-bin-size     : {}", dynamic_size);
+        let mut base = self.clone();
+        if self.compare_target {
+            base.target_cpu = None;
+            base.target_feature = vec![];
+        }
+        base.mitigations = false;
+        base.compare_mitigations = false;
+        base.incremental = false;
+        base.split_link = false;
 
-    if opts.asm {
-        let (static_asm_path, dynamic_asm_path) = gen_asm_paths(&config);
+        Some(base)
+    }
 
-        run_rustc_asm(&static_src_path, &static_asm_path, &opts)?;
-        run_rustc_asm(&dynamic_src_path, &dynamic_asm_path, &opts)?;
+    fn relocation_model(&self) -> Option<RelocModel> {
+        if self.no_pie {
+            Some(RelocModel::Static)
+        } else {
+            self.relocation_model
+        }
     }
 
-    let (static_method_count, static_fn_count)
-        = count_symbols(&static_bin_path)?;
-    let (dynamic_method_count, dynamic_fn_count)
-        = count_symbols(&dynamic_bin_path)?;
+    fn mitigated(&self) -> bool {
+        self.mitigations || self.compare_mitigations
+    }
 
-    println!("static-method-count  : {}", static_method_count);
-    println!("static-fn-count      : {}", static_fn_count);
-    println!("dynamic-method-count : {}", dynamic_method_count);
-    println!("dynamic-fn-count     : {}", dynamic_fn_count);
+    /// Rejects combinations rustc would only fail on halfway
+    /// through a sweep
+    fn validate(&self) -> Result<(), clap::Error> {
+        if self.compare_target && self.target_cpu.is_none()
+            && self.target_feature.is_empty() {
+            return Err(Options::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "--compare-target needs --target-cpu or --target-feature"));
+        }
+        for dispatch in Dispatch::ALL {
+            let opts = self.for_variant(dispatch);
+            if opts.lto.is_some() && opts.codegen_backend == Backend::Cranelift {
+                return Err(Options::command().error(
+                    ErrorKind::ArgumentConflict,
+                    format!("--lto is not supported by --codegen-backend cranelift \
+                             (in the {} variant)", dispatch)));
+            }
+            if opts.pgo && opts.codegen_backend == Backend::Cranelift {
+                return Err(Options::command().error(
+                    ErrorKind::ArgumentConflict,
+                    format!("--pgo is not supported by --codegen-backend cranelift \
+                             (in the {} variant)", dispatch)));
+            }
+        }
 
-    Ok(())
+        Ok(())
+    }
 }
 
-fn run_one_case(config: CaseConfig) -> Result<()> {
-    prereport("running", &config);
-
-    let (static_bin_path, dynamic_bin_path) = gen_bin_paths(&config);
-    let static_time = run_case(&static_bin_path)?;
-    let dynamic_time = run_case(&dynamic_bin_path)?;
-
-    println!("static-run-time : {}", static_time.as_millis());
-    println!("dynamic-run-time: {}", dynamic_time.as_millis());
+#[derive(Debug, Clone, Args, Serialize, Deserialize)]
+struct GenOpts {
+    /// Mark generated fns and methods #[inline(never)]
+    #[arg(long)]
+    no_inline: bool,
+    /// Give every generated body distinct code so they can't be merged
+    #[arg(long)]
+    no_dedup: bool,
+    /// Group the calls in the loop by type instead of by fn
+    #[arg(long)]
+    predictable: bool,
+    /// Format the generated sources with rustfmt
+    #[arg(long)]
+    #[serde(default)]
+    rustfmt: bool,
+    /// Add comments to the generated sources explaining each part
+    #[arg(long)]
+    #[serde(default)]
+    annotate: bool,
+    /// The language to generate the programs in
+    #[arg(long, value_enum, default_value_t = Lang::Rust)]
+    // Left out for Rust so cases from before C++ keep their hashes
+    #[serde(default, skip_serializing_if = "Lang::is_rust")]
+    lang: Lang,
+    /// Reference every generated fn and method from a #[used] static so
+    /// the optimizer can't remove their symbols, keeping symbol counts
+    /// meaningful at high opt levels
+    #[arg(long)]
+    // Options added since hashing are left out when off, so older
+    // cases keep their hashes
+    #[serde(default, skip_serializing_if = "is_false")]
+    keep_symbols: bool,
+    /// Call through a struct field holding each value: a Box<dyn Io> in
+    /// the dynamic program and a generic T: Io in the static one
+    #[arg(long)]
+    #[serde(default, skip_serializing_if = "is_false")]
+    holder: bool,
+    /// Also call each type's method through this many structs generic
+    /// over T: Io, each instantiated per type in the static program
+    #[arg(long, default_value_t = 0)]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    structs: u32,
+    /// Give the trait an associated type, the method's return type,
+    /// which the dynamic program has to pin as dyn Io<Out = u8>
+    #[arg(long)]
+    #[serde(default, skip_serializing_if = "is_false")]
+    assoc_type: bool,
+    /// Give the trait a generic method. It needs `where Self: Sized`
+    /// to keep dyn Io, so only the static program's fns can call it
+    #[arg(long)]
+    #[serde(default, skip_serializing_if = "is_false")]
+    generic_method: bool,
+    /// Take the trait to the edge of object safety: on top of the
+    /// generic method, a method returning Self. The dynamic program
+    /// works around both, with a type-erased argument and a boxed copy.
+    #[arg(long)]
+    #[serde(default, skip_serializing_if = "is_false")]
+    object_safety_stress: bool,
+    /// Select the type in the static program with a const generic
+    /// parameter and a match on it, instead of a type parameter
+    #[arg(long, conflicts_with = "holder")]
+    #[serde(default, skip_serializing_if = "is_false")]
+    const_generic: bool,
+    /// Give the trait a blanket default impl that each type's impl
+    /// specializes, under the nightly min_specialization feature
+    #[arg(long, conflicts_with_all = ["assoc_type", "object_safety_stress"])]
+    #[serde(default, skip_serializing_if = "is_false")]
+    specialization: bool,
+    /// How the dynamic program picks each call's type at run time
+    #[arg(long, value_enum, default_value_t = DynamicVia::Vtable,
+          conflicts_with_all = ["holder", "structs"])]
+    #[serde(default, skip_serializing_if = "DynamicVia::is_vtable")]
+    dynamic_via: DynamicVia,
+    /// How the static program's fns pick each call's type
+    #[arg(long, value_enum, default_value_t = StaticVia::Generics,
+          conflicts_with_all = ["holder", "structs", "const_generic", "trait_depth",
+                                "visitor", "threads", "errors"])]
+    #[serde(default, skip_serializing_if = "StaticVia::is_generics")]
+    static_via: StaticVia,
+    /// The order a branch-tree tests the types in
+    #[arg(long, value_enum, default_value_t = BranchOrder::LikelyFirst)]
+    #[serde(default, skip_serializing_if = "BranchOrder::is_likely_first")]
+    branch_order: BranchOrder,
+    /// How often each type appears at the loop's call sites. Each fn
+    /// is still called num-types times per pass.
+    #[arg(long, value_enum, default_value_t = skew::Skew::Uniform)]
+    #[serde(default, skip_serializing_if = "skew::Skew::is_uniform")]
+    skew: skew::Skew,
+    /// How many distinct types each fn and struct is called with, from
+    /// 1 (monomorphic) up; 0 calls each with every type (megamorphic).
+    /// Each is still called num-types times per pass.
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "skew")]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    site_types: u32,
+    /// Derive a chain of this many traits from Io, Sub1: Io up to
+    /// SubN, and have the fns take the most derived
+    #[arg(long, value_name = "N", default_value_t = 0,
+          conflicts_with_all = ["holder", "structs", "dynamic_via"])]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    trait_depth: u32,
+    /// Upcast each dynamic fn's &dyn SubN to &dyn Io before calling
+    /// the method, under --trait-depth
+    #[arg(long, requires = "trait_depth")]
+    #[serde(default, skip_serializing_if = "is_false")]
+    upcast: bool,
+    /// Model the visitor pattern: the static program matches on an enum
+    /// of the types, and the dynamic program downcasts &dyn Io to each
+    /// type in turn
+    #[arg(long, conflicts_with_all = ["holder", "structs", "dynamic_via",
+                                      "const_generic", "trait_depth"])]
+    #[serde(default, skip_serializing_if = "is_false")]
+    visitor: bool,
+    /// Make one pass over the call sites instead of the hot loop, so a
+    /// run measures process startup and first calls. The run phase then
+    /// launches each binary at least 100 times, or --repeat times
+    #[arg(long)]
+    #[serde(default, skip_serializing_if = "is_false")]
+    cold_start: bool,
+    /// Run the loop on this many threads at once, each with its own
+    /// clones of the values in Arcs: Arc<Tn> in the static program and
+    /// Arc<dyn Io + Send + Sync> in the dynamic one
+    #[arg(long, value_name = "N", default_value_t = 0,
+          conflicts_with_all = ["holder", "structs", "dynamic_via", "visitor",
+                                "const_generic", "trait_depth"])]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    threads: u32,
+    /// Split the loop between this many async tasks on a minimal
+    /// single-threaded executor, each yielding after every pass. The
+    /// static program polls each task's concrete future; the dynamic
+    /// one polls them as Pin<Box<dyn Future>>
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "threads")]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    tasks: u32,
+    /// Have the method and fns return Result, with an enum of the
+    /// types' errors in the static program and Box<dyn Error> in the
+    /// dynamic one, failing on every call or never
+    #[arg(long, value_enum,
+          conflicts_with_all = ["holder", "structs", "dynamic_via", "visitor",
+                                "const_generic", "assoc_type", "generic_method",
+                                "object_safety_stress", "specialization"])]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    errors: Option<ErrorPath>,
+    /// Give each type an array of this many components and have the
+    /// fns run over them, as systems do in an entity-component system:
+    /// a &[Tn] in the static program and a &[Box<dyn Io>] in the
+    /// dynamic one
+    #[arg(long, value_name = "N", default_value_t = 0,
+          conflicts_with_all = ["holder", "structs", "dynamic_via", "static_via", "visitor",
+                                "const_generic", "trait_depth", "threads", "errors"])]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    components: u32,
+    /// Make the types the states of a state machine, advanced once per
+    /// state each pass, each transition calling its state's method
+    /// through the fns: an enum matched on in the static program and a
+    /// Box<dyn State> in the dynamic one
+    #[arg(long, conflicts_with_all = ["holder", "structs", "dynamic_via", "static_via",
+                                      "visitor", "const_generic", "trait_depth",
+                                      "threads", "tasks", "errors", "components",
+                                      "predictable", "skew", "site_types"])]
+    #[serde(default, skip_serializing_if = "is_false")]
+    state_machine: bool,
+    /// How the loop makes its calls: listed in its body, each from a fn
+    /// of its own, or through an array of those fns' pointers
+    #[arg(long, value_enum, default_value_t = CallShape::InlineLoop,
+          conflicts_with_all = ["holder", "dynamic_via", "threads", "components",
+                                "state_machine"])]
+    #[serde(default, skip_serializing_if = "CallShape::is_inline_loop")]
+    call_shape: CallShape,
+    /// Rebind each value through black_box with the pass number at the
+    /// top of every pass, so no call's argument is loop-invariant and
+    /// none can be hoisted out of the loop. Compiling checks the loop
+    /// in main's assembly still has a call or black_box per call site.
+    #[arg(long, conflicts_with_all = ["const_generic", "state_machine", "call_shape",
+                                      "threads", "tasks"])]
+    #[serde(default, skip_serializing_if = "is_false")]
+    vary_args: bool,
+    /// Sum a result for each method call and print it at exit, and check
+    /// when running that both programs printed the same sum
+    #[arg(long, conflicts_with = "threads")]
+    #[serde(default, skip_serializing_if = "is_false")]
+    checksum: bool,
+    /// Also generate a concrete program, whose call sites call each
+    /// type's method directly, with no fns, generics or trait objects:
+    /// a lower bound to report the others against with `report
+    /// --baseline concrete`
+    #[arg(long, conflicts_with_all = ["holder", "structs", "object_safety_stress",
+                                      "state_machine", "components"])]
+    #[serde(default, skip_serializing_if = "is_false")]
+    concrete: bool,
+    /// What the generated code's black_box is: std's, an empty inline
+    /// asm block, a volatile read, or nothing at all
+    #[arg(long, value_enum, default_value_t = BlackBox::Std)]
+    #[serde(default, skip_serializing_if = "BlackBox::is_std")]
+    blackbox: BlackBox,
+}
 
-    Ok(())
+fn is_false(b: &bool) -> bool {
+    !b
 }
 
-fn ranges(config: &MultiCaseConfig) ->
-    (impl Iterator<Item = u32> + Clone,
-     impl Iterator<Item = u32> + Clone)
-{
-    let type_range = if config.step_types > 0 {
-        (0..=config.num_types).step_by(config.step_types as usize)
-    } else {
-        (config.num_types..=config.num_types).step_by(1)
-    };
-    let fn_range = if config.step_fns > 0 {
-        (0..=config.num_fns).step_by(config.step_fns as usize)
-    } else {
-        (config.num_fns..=config.num_fns).step_by(1)
-    };
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
 
-    (type_range, fn_range)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Lang {
+    #[default]
+    Rust,
+    Cpp,
 }
 
-fn run_all_for(config: MultiCaseConfig, test: impl Fn(CaseConfig) -> Result<()>) -> Result<()> {
-    let (type_range, fn_range) = ranges(&config);
-    
-    for type_num in type_range {
-        for fn_num in fn_range.clone() {
-            let config = CaseConfig {
-                outdir: config.outdir.clone(),
-                num_types: type_num,
-                num_fns: fn_num,
-            };
-            test(config)?;
-        }
+impl Lang {
+    fn is_rust(&self) -> bool {
+        *self == Lang::Rust
     }
 
-    Ok(())
+    fn src_ext(&self) -> &'static str {
+        match self {
+            Lang::Rust => "rs",
+            Lang::Cpp => "cpp",
+        }
+    }
 }
 
-fn gen_all_cases(config: MultiCaseConfig, opts: GenOpts) -> Result<()> {
-    run_all_for(config, |c| gen_one_case(c, opts.clone()))
+/// How the dynamic program's fns find the method to call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum DynamicVia {
+    /// Through a &dyn Io trait object's vtable
+    #[default]
+    Vtable,
+    /// Through a match on an integer tag, which rustc can lower to a
+    /// jump table
+    JumpTable,
+    /// Through an if/else-if chain comparing an integer tag with each
+    /// type's in turn
+    BranchTree,
+    /// Through a table of fn pointers indexed by an integer tag
+    FnTable,
+    /// Through a Box<dyn Fn(u32)> per value, a closure calling its
+    /// type's method with the fn's number as the event
+    Closure,
 }
 
-fn compile_all_cases(config: MultiCaseConfig, opts: CompileOpts) -> Result<()> {
-    run_all_for(config, |c| compile_one_case(c, opts.clone()))
-}
+impl DynamicVia {
+    fn is_vtable(&self) -> bool {
+        *self == DynamicVia::Vtable
+    }
 
-fn run_all_cases(config: MultiCaseConfig) -> Result<()> {
-    run_all_for(config, &run_one_case)
+    /// Whether the fns take an integer tag and pick the type themselves
+    fn has_tag(&self) -> bool {
+        matches!(self, DynamicVia::JumpTable | DynamicVia::BranchTree | DynamicVia::FnTable)
+    }
 }
 
-fn gen_src_paths(config: &CaseConfig) -> (PathBuf, PathBuf) {
-    gen_paths(config, "rs")
+/// How the static program's fns find the method to call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum StaticVia {
+    /// Through a type parameter, each fn copied per type
+    #[default]
+    Generics,
+    /// Through a match on an enum with a variant per type, the closed
+    /// set of handlers an event loop can know up front
+    Enum,
 }
 
-fn gen_bin_paths(config: &CaseConfig) -> (PathBuf, PathBuf) {
-    gen_paths(config, "bin")
+impl StaticVia {
+    fn is_generics(&self) -> bool {
+        *self == StaticVia::Generics
+    }
 }
 
-fn gen_asm_paths(config: &CaseConfig) -> (PathBuf, PathBuf) {
-    gen_paths(config, "S")
+/// The order a branch-tree compares a tag with the types'
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum BranchOrder {
+    /// The most often called type first, so most calls take the first
+    /// branch
+    #[default]
+    LikelyFirst,
+    /// Each fn starts at a different type, so no type is favored
+    Uniform,
 }
 
-fn gen_paths(config: &CaseConfig, ext: &str) -> (PathBuf, PathBuf) {
-    let mut static_path = config.outdir.clone();
-    static_path.push(
-        format!("static-{:04}-{:04}.{}",
-                config.num_types, config.num_fns,
-                ext));
-    let mut dynamic_path = config.outdir.clone();
-    dynamic_path.push(
-        format!("dynamic-{:04}-{:04}.{}",
-                config.num_types, config.num_fns,
-                ext));
-    (static_path, dynamic_path)
+impl BranchOrder {
+    fn is_likely_first(&self) -> bool {
+        *self == BranchOrder::LikelyFirst
+    }
 }
 
-
-static HEADER: &'static str = "
-#![feature(test)]
-
-use std::hint::black_box;
-
-trait Io { fn do_io_m(&self); }
-";
-
-macro_rules! type_template { () => { "
-struct T{num}({types});
-impl Io for T{num} {{
-    {inlining}
-    fn do_io_m(&self) {{
-        black_box(self);
-        if {no_dedup} {{
-            black_box(&{num});
-        }}
-    }}
-}}
-"
-}}
-
-macro_rules! fn_static_template { () => { "
-{inlining}
-fn do_io_f{num}<T: Io>(v: &T) {{
-    v.do_io_m();
-    if {no_dedup} {{
-        black_box(&{num});
-    }}
-}}
-"
-}}
-
-macro_rules! fn_dynamic_template { () => { "
-{inlining}
-fn do_io_f{num}(v: &dyn Io) {{
-    v.do_io_m();
-    if {no_dedup} {{
-        black_box(&{num});
-    }}
-}}
-"
-}}
-
-fn gen_static(config: &CaseConfig, path: &Path, opts: GenOpts) -> Result<()> {
-    gen_case(config, path, write_fn_static, opts)
+/// Where the loop's calls are made from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum CallShape {
+    /// Every call site listed in the loop's body
+    #[default]
+    InlineLoop,
+    /// Each call site in an #[inline(never)] fn of its own, which the
+    /// loop calls in turn
+    Outlined,
+    /// The same fns, called through an array of fn pointers the
+    /// optimizer can't see into
+    IndirectThroughArray,
 }
 
-fn gen_dynamic(config: &CaseConfig, path: &Path, opts: GenOpts) -> Result<()> {
-    gen_case(config, path, write_fn_dynamic, opts)
+impl CallShape {
+    fn is_inline_loop(&self) -> bool {
+        *self == CallShape::InlineLoop
+    }
 }
 
-const TEST_LOOPS: usize = 100_000;
+/// The optimization barrier the generated code calls black_box
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BlackBox {
+    /// std::hint::black_box
+    #[default]
+    Std,
+    /// An empty inline asm block that takes the value's address
+    Asm,
+    /// A volatile read of the value
+    Volatile,
+    /// No barrier, leaving the optimizer free
+    None,
+}
 
-type WriteFn = fn(f: &mut dyn Write, num: u32, opts: &GenOpts) -> Result<()>;
+impl BlackBox {
+    fn is_std(&self) -> bool {
+        *self == BlackBox::Std
+    }
 
-fn write_fn_static(f: &mut dyn Write, num: u32, opts: &GenOpts) -> Result<()> {
-    Ok(writeln!(f, fn_static_template!(),
-                num = num, inlining = inline_str(opts),
-                no_dedup = opts.no_dedup)?)
+    /// The generated code's definition of black_box
+    fn header(self) -> &'static str {
+        match self {
+            BlackBox::Std => HEADER,
+            BlackBox::Asm => ASM_BLACK_BOX,
+            BlackBox::Volatile => VOLATILE_BLACK_BOX,
+            BlackBox::None => NO_BLACK_BOX,
+        }
+    }
 }
 
-fn write_fn_dynamic(f: &mut dyn Write, num: u32, opts: &GenOpts) -> Result<()> {
-    Ok(writeln!(f, fn_dynamic_template!(),
-                num = num, inlining = inline_str(opts),
-                no_dedup = opts.no_dedup)?)
+/// How often the method fails under --errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ErrorPath {
+    /// It could fail, but never does, so only the Result plumbing costs
+    Rare,
+    /// Every call fails, building and dropping an error
+    Hot,
 }
 
-fn inline_str(opts: &GenOpts) -> &'static str {
-    if opts.no_inline {
-        "#[inline(never)]"
-    } else {
-        ""
-    }
+/// How a case's program dispatches its calls. Every case is built once
+/// per variant, and files and results are named after them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Dispatch {
+    Static,
+    Dynamic,
+    /// Not dispatched at all: the baseline a case generated with
+    /// --concrete also has
+    Concrete,
 }
 
-fn gen_case(config: &CaseConfig, path: &Path,
-            write_fn: WriteFn, opts: GenOpts) -> Result<()> {
-    assert!(path.extension().expect("") == "rs");
-    let dir = path.parent().expect("directory");
-    fs::create_dir_all(&dir)?;
-    let mut file = File::create(path)?;
+impl Dispatch {
+    /// Every variant, including the concrete one only some cases have
+    const ALL: [Dispatch; 3] = [Dispatch::Static, Dispatch::Dynamic, Dispatch::Concrete];
 
-    writeln!(file, "// types = {}, fns = {}",
-             config.num_types, config.num_fns)?;
-    writeln!(file)?;
+    /// The pairs of variants whose ratios are reported, numerator
+    /// first. Those with the concrete baseline only when a case has it.
+    const RATIOS: [(Dispatch, Dispatch); 3] = [
+        (Dispatch::Dynamic, Dispatch::Static),
+        (Dispatch::Static, Dispatch::Concrete),
+        (Dispatch::Dynamic, Dispatch::Concrete),
+    ];
+}
 
-    if config.num_types == 0 || config.num_fns == 0 {
-        writeln!(file, "#![allow(unused)]")?;
+impl GenOpts {
+    /// The variants of a case generated with these options
+    fn variants(&self) -> Vec<Dispatch> {
+        Dispatch::ALL.iter()
+            .copied()
+            .filter(|&d| d != Dispatch::Concrete || self.concrete)
+            .collect()
     }
+}
 
-    writeln!(file, "{}", HEADER)?;
+impl GenOpts {
+    fn validate(&self) -> Result<(), clap::Error> {
+        if self.lang == Lang::Cpp && (self.rustfmt || self.annotate) {
+            return Err(Options::command().error(
+                ErrorKind::ArgumentConflict,
+                "--rustfmt and --annotate only apply to --lang rust"));
+        }
+        let rust_only = [
+            (self.holder, "--holder"),
+            (self.structs > 0, "--structs"),
+            (self.assoc_type, "--assoc-type"),
+            (self.generic_method, "--generic-method"),
+            (self.object_safety_stress, "--object-safety-stress"),
+            (self.const_generic, "--const-generic"),
+            (self.specialization, "--specialization"),
+            (!self.dynamic_via.is_vtable(), "--dynamic-via"),
+            (!self.static_via.is_generics(), "--static-via"),
+            (!self.skew.is_uniform(), "--skew"),
+            (self.site_types > 0, "--site-types"),
+            (self.trait_depth > 0, "--trait-depth"),
+            (self.visitor, "--visitor"),
+            (self.threads > 0, "--threads"),
+            (self.tasks > 0, "--tasks"),
+            (self.errors.is_some(), "--errors"),
+            (self.components > 0, "--components"),
+            (self.state_machine, "--state-machine"),
+            (!self.call_shape.is_inline_loop(), "--call-shape"),
+            (self.vary_args, "--vary-args"),
+            (self.checksum, "--checksum"),
+            (self.concrete, "--concrete"),
+            (!self.blackbox.is_std(), "--blackbox"),
+        ];
+        if !self.branch_order.is_likely_first() && self.dynamic_via != DynamicVia::BranchTree {
+            return Err(Options::command().error(
+                ErrorKind::ArgumentConflict,
+                "--branch-order only applies to --dynamic-via branch-tree"));
+        }
+        if self.vary_args && matches!(self.blackbox, BlackBox::Volatile | BlackBox::None) {
+            // Its check counts the barriers' asm blocks
+            return Err(Options::command().error(
+                ErrorKind::ArgumentConflict,
+                "--vary-args needs --blackbox std or asm"));
+        }
+        if let Some((_, flag)) = rust_only.iter().find(|(on, _)| *on) {
+            if self.lang == Lang::Cpp {
+                return Err(Options::command().error(
+                    ErrorKind::ArgumentConflict,
+                    format!("{} only applies to --lang rust", flag)));
+            }
+        }
 
-    for type_num in 0..config.num_types {
-        let types = gen_type(type_num, config.num_types);
-        writeln!(file, type_template!(),
-                 num = type_num, types = types,
-                 inlining = inline_str(&opts),
-                 no_dedup = opts.no_dedup)?;
+        Ok(())
     }
+}
+
+fn prereport(action: &str, config: &CaseConfig) {
+    println!("{} case: {} types / {} fns",
+             action,
+             config.num_types,
+             config.num_fns);
+}
+
+fn gen_one_case(config: CaseConfig, opts: GenOpts) -> Result<()> {
+    prereport("generating", &config);
+
+    let generate = |dispatch, path: &Path| match (opts.lang, dispatch) {
+        (Lang::Rust, Dispatch::Static) => gen_static(&config, path, opts.clone()),
+        (Lang::Rust, Dispatch::Dynamic) => gen_dynamic(&config, path, opts.clone()),
+        (Lang::Cpp, Dispatch::Static) => cpp::gen_static(&config, path, opts.clone()),
+        (Lang::Cpp, Dispatch::Dynamic) => cpp::gen_dynamic(&config, path, opts.clone()),
+        (_, Dispatch::Concrete) => gen_concrete(&config, path, opts.clone()),
+    };
+    gen_src_paths(&config, &opts).iter()
+        .try_for_each(|(dispatch, path)| generate(*dispatch, path))
+        .context(Failure::new(Phase::Generation, &config))?;
+
+    manifest::record_case(&config, &opts, cmdline::invocation())?;
+    summary::write(&config)?;
+
+    Ok(())
+}
+
+/// One of a case's variants to build, with its options
+struct VariantBuild {
+    dispatch: Dispatch,
+    src: PathBuf,
+    bin: PathBuf,
+    opts: CompileOpts,
+}
+
+impl VariantBuild {
+    /// A line of the compile report, like `static-bin-size      : 1234`
+    fn line(&self, report: &mut String, what: &str, value: impl fmt::Display) -> fmt::Result {
+        writeln!(report, "{:<21}: {}", format!("{}-{}", self.dispatch, what), value)
+    }
+}
+
+fn compile_one_case(config: CaseConfig, opts: CompileOpts) -> Result<()> {
+    prereport("compiling", &config);
+
+    let case = manifest::find_case(&config)?;
+    let lang = case.gen_opts.lang;
+    let srcs = gen_src_paths(&config, &case.gen_opts);
+
+    if srcs.iter().any(|(_, src)| !src.exists()) {
+        bail!("case {} types / {} fns has not been generated in {}",
+              config.num_types, config.num_fns, config.outdir.display());
+    }
+
+    for (_, src) in &srcs {
+        hash::verify(&config, &case.gen_opts, src)?;
+    }
+
+    let (run_bin, run_asm): (CompileFn, CompileFn) = match lang {
+        Lang::Rust => (run_rustc_bin, run_rustc_asm),
+        Lang::Cpp => (cpp::run_cxx_bin, cpp::run_cxx_asm),
+    };
+
+    // Printed all at once so parallel compiles don't interleave
+    let mut report = String::new();
+    writeln!(report, "compiled case: {} types / {} fns",
+             config.num_types, config.num_fns)?;
+
+    let builds: Vec<VariantBuild> = srcs.into_iter()
+        .map(|(dispatch, src)| VariantBuild {
+            dispatch,
+            src,
+            bin: variant_path(&config, dispatch, "bin"),
+            opts: opts.for_variant(dispatch),
+        })
+        .collect();
+    let static_build = builds.iter().find(|b| b.dispatch == Dispatch::Static)
+        .expect("every case has a static variant");
+
+    let compilation = || Failure::new(Phase::Compilation, &config);
+    let analysis = || Failure::new(Phase::Analysis, &config);
+
+    if opts.pgo {
+        if lang != Lang::Rust {
+            bail!("--pgo only applies to cases generated with --lang rust");
+        }
+        for b in &builds {
+            pgo::train(&b.src, &b.opts).with_context(compilation)?;
+        }
+    }
+
+    let mut times = BTreeMap::new();
+    for b in &builds {
+        let time = measure_compile(&opts, &b.bin, || run_bin(&b.src, &b.bin, &b.opts))
+            .with_context(compilation)?;
+        times.insert(b.dispatch, time);
+    }
+
+    type Part = for<'a> fn(&'a CompileTimes) -> Option<&'a CompileTime>;
+    let phases: [(&str, Part); 4] = [
+        ("compile", |t| Some(&t.build)),
+        ("codegen", |t| t.codegen.as_ref()),
+        ("link", |t| t.link.as_ref()),
+        ("rebuild", |t| t.rebuild.as_ref()),
+    ];
+    for (phase, part) in phases.iter() {
+        let measured: Option<Vec<&CompileTime>> = times.values().map(part).collect();
+        let measured = match measured {
+            Some(measured) => measured,
+            None => continue,
+        };
+        for (b, t) in builds.iter().zip(&measured) {
+            b.line(&mut report, &format!("{}-time", phase), t.ms)?;
+        }
+        if measured.iter().all(|t| t.stats.is_some()) {
+            for (b, t) in builds.iter().zip(&measured) {
+                let stats = t.stats.as_ref().expect("checked above");
+                b.line(&mut report, &format!("{}-stats", phase), stats)?;
+            }
+        }
+    }
+
+    for (num, den) in Dispatch::RATIOS {
+        let stats = |d| times.get(&d).and_then(|t| t.build.stats.as_ref()).cloned();
+        if let Some(ratio) = results::ratio(&stats(num), &stats(den)) {
+            writeln!(report, "compile-time-ratio   : {} ({}/{}, 95% CI)", ratio, num, den)?;
+        }
+    }
+
+    let mut sizes = BTreeMap::new();
+    for b in &builds {
+        sizes.insert(b.dispatch, fs::metadata(&b.bin)?.len());
+    }
+
+    let mut deterministic = BTreeMap::new();
+    if opts.deterministic {
+        for b in &builds {
+            let same = verify_deterministic(run_bin, &b.src, &b.bin, &b.opts)
+                .with_context(compilation)?;
+            deterministic.insert(b.dispatch, same);
+        }
+        for b in &builds {
+            let same = deterministic[&b.dispatch];
+            b.line(&mut report, "deterministic", if same { "yes" } else { "no, a rebuild differs" })?;
+        }
+    }
+
+    for b in &builds {
+        b.line(&mut report, "bin-size", sizes[&b.dispatch])?;
+    }
+
+    let mut bin_sha256 = BTreeMap::new();
+    for b in &builds {
+        bin_sha256.insert(b.dispatch, hash::file_digest(&b.bin)?);
+    }
+
+    // A --vary-args case's assembly is always checked
+    let mut asm_paths = BTreeMap::new();
+    if opts.asm || case.gen_opts.vary_args {
+        for b in &builds {
+            let asm = variant_path(&config, b.dispatch, "S");
+            run_asm(&b.src, &asm, &b.opts).with_context(compilation)?;
+            asm_paths.insert(b.dispatch, asm);
+        }
+        if case.gen_opts.vary_args && config.num_types > 0 {
+            let sites = ((config.num_fns.max(1) + case.gen_opts.structs)
+                         * config.num_types) as usize;
+            let values = config.num_types as usize;
+            let mut calls = vec![];
+            for b in &builds {
+                calls.push(asmcheck::check(&asm_paths[&b.dispatch], sites, values)
+                           .with_context(analysis)?);
+            }
+            for (b, calls) in builds.iter().zip(calls) {
+                b.line(&mut report, "loop-calls",
+                       format_args!("{} of at least {}", calls, sites + values))?;
+            }
+        }
+    }
+    let asm_sha256 = asm_paths.into_iter()
+        .map(|(dispatch, asm)| Ok((dispatch, hash::file_digest(&asm)?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    let mut baseline_sizes = vec![];
+    for b in &builds {
+        if let Some(base) = b.opts.baseline() {
+            let path = variant_path(&config, b.dispatch, "base.bin");
+            run_bin(&b.src, &path, &base).with_context(compilation)?;
+            baseline_sizes.push((b, fs::metadata(&path)?.len()));
+        }
+    }
+    for (b, size) in baseline_sizes {
+        b.line(&mut report, "baseline-size", size)?;
+    }
+
+    let mut mono_items = BTreeMap::new();
+    if opts.mono_items {
+        if lang != Lang::Rust {
+            bail!("--mono-items only applies to cases generated with --lang rust");
+        }
+        for b in &builds {
+            let items = mono::count(&b.src, &b.bin, &b.opts).with_context(analysis)?;
+            b.line(&mut report, "mono-items",
+                   format_args!("{} ({} fns, {} generated)", items.items, items.fns,
+                                items.generated))?;
+            mono_items.insert(b.dispatch, items);
+        }
+    }
+
+    let kept = case.gen_opts.keep_symbols;
+    let mut symbols = BTreeMap::new();
+    for b in &builds {
+        let counts = symbols::count(&b.bin).with_context(analysis)?;
+        let instances = fn_instances(&config, b.dispatch);
+        let loss = counts.loss(config.num_types, instances, kept);
+        symbols.insert(b.dispatch, (counts, loss, instances));
+    }
+
+    for b in &builds {
+        let (counts, _, _) = &symbols[&b.dispatch];
+        b.line(&mut report, "method-count", counts.methods)?;
+        b.line(&mut report, "fn-count", counts.fns)?;
+    }
+    for b in &builds {
+        let (_, loss, _) = &symbols[&b.dispatch];
+        b.line(&mut report, "eliminated", format_args!("{} methods, {} fns",
+                                                       loss.methods_eliminated,
+                                                       loss.fns_eliminated))?;
+        b.line(&mut report, "merged", format_args!("{} methods, {} fns",
+                                                   loss.methods_merged, loss.fns_merged))?;
+    }
+    for b in &builds {
+        let (_, loss, instances) = &symbols[&b.dispatch];
+        b.line(&mut report, "fold-rate", fold_rate(loss.fns_merged, *instances))?;
+    }
+
+    let diff = symbols::write_diff(&config, &static_build.bin,
+                                   &variant_path(&config, Dispatch::Dynamic, "bin"))
+        .with_context(analysis)?;
+    writeln!(report, "static-only-symbols  : {}", diff.static_only.len())?;
+    writeln!(report, "dynamic-only-symbols : {}", diff.dynamic_only.len())?;
+
+    print!("{}", report);
+
+    let variants = times.into_iter()
+        .map(|(dispatch, times)| {
+            let (counts, loss, _) = symbols.remove(&dispatch).expect("counted every variant");
+            (dispatch, results::VariantCompileResults {
+                compile_time_ms: times.build.ms,
+                compile_time_stats: times.build.stats,
+                codegen_time_ms: times.codegen.as_ref().map(|t| t.ms),
+                link_time_ms: times.link.as_ref().map(|t| t.ms),
+                rebuild_time_ms: times.rebuild.as_ref().map(|t| t.ms),
+                rebuild_time_stats: times.rebuild.and_then(|t| t.stats),
+                commands: times.commands,
+                bin_size: sizes[&dispatch],
+                method_count: counts.methods,
+                fn_count: counts.fns,
+                symbol_loss: Some(loss),
+                mono_items: mono_items.remove(&dispatch),
+                deterministic: deterministic.get(&dispatch).copied(),
+                bin_sha256: bin_sha256.remove(&dispatch),
+                asm_sha256: asm_sha256.get(&dispatch).cloned(),
+            })
+        })
+        .collect();
+
+    results::record_compile(&config, results::CompileResults {
+        opts: opts.clone(),
+        command: Some(cmdline::invocation()),
+        jobs: jobs::limit(),
+        variants,
+    })?;
+    manifest::set_status(&config, CaseStatus::Compiled)?;
+    summary::write(&config)?;
+    repro::write(&config)?;
+
+    Ok(())
+}
+
+/// How many fn instances a variant's program has, one per generated fn
+/// and, when its fns are generic, per type
+fn fn_instances(config: &CaseConfig, dispatch: Dispatch) -> u32 {
+    match dispatch {
+        Dispatch::Static => config.num_fns * config.num_types,
+        Dispatch::Dynamic => config.num_fns,
+        Dispatch::Concrete => 0,
+    }
+}
+
+/// Under --deterministic, builds a variant again beside the first
+/// build and checks the two are byte-for-byte the same
+fn verify_deterministic(run_bin: CompileFn, src: &Path, bin: &Path,
+                        opts: &CompileOpts) -> Result<bool> {
+    let mut again = opts.clone();
+    again.incremental = false;
+    again.split_link = false;
+
+    let out = bin.with_extension("verify.bin");
+    run_bin(src, &out, &again)?;
+    let same = fs::read(bin)? == fs::read(&out)?;
+    fs::remove_file(&out)?;
+
+    Ok(same)
+}
+
+/// The share of fn instances that don't have a body of their own
+fn fold_rate(folded: usize, instances: u32) -> String {
+    if instances == 0 {
+        return "no fns".to_string();
+    }
+    format!("{:.0}% of {} fn instances", 100.0 * folded as f64 / instances as f64,
+            instances)
+}
+
+/// A compile time, the median under --measure-compile
+struct CompileTime {
+    ms: u64,
+    stats: Option<stats::Stats>,
+}
+
+struct CompileTimes {
+    build: CompileTime,
+    /// Under --split-link, the parts of the build before and during
+    /// linking
+    codegen: Option<CompileTime>,
+    link: Option<CompileTime>,
+    /// A warm rebuild under --incremental
+    rebuild: Option<CompileTime>,
+    /// The command lines of the first build
+    commands: Vec<String>,
+}
+
+/// Compiles once, or under --measure-compile builds --repeat times.
+/// With --incremental each build starts from cold incremental state
+/// and is followed by a warm rebuild, which is timed too.
+fn measure_compile(opts: &CompileOpts, out: &Path,
+                   compile: impl Fn() -> Result<BuildTime>) -> Result<CompileTimes> {
+    let repeat = if opts.measure_compile { opts.repeat.max(1) } else { 1 };
+    let incr_dir = incremental_dir(out);
+    let mut cold = vec![];
+    let mut codegen = vec![];
+    let mut link = vec![];
+    let mut warm = vec![];
+    let mut commands = vec![];
+
+    for _ in 0..repeat {
+        if opts.drop_caches {
+            drop_caches()?;
+        }
+        if opts.incremental && incr_dir.exists() {
+            fs::remove_dir_all(&incr_dir)?;
+        }
+        let build = compile()?;
+        if commands.is_empty() {
+            commands = build.commands;
+        }
+        cold.push(build.total.as_millis() as u64);
+        if let Some(link_time) = build.link {
+            codegen.push((build.total - link_time).as_millis() as u64);
+            link.push(link_time.as_millis() as u64);
+        }
+        if opts.incremental {
+            warm.push(compile()?.total.as_millis() as u64);
+        }
+    }
+
+    let summarize = |samples: Vec<u64>| {
+        if samples.is_empty() {
+            return None;
+        }
+        let stats = stats::Stats::new(samples);
+        Some(CompileTime {
+            ms: stats.median,
+            stats: Some(stats).filter(|_| opts.measure_compile),
+        })
+    };
+
+    Ok(CompileTimes {
+        build: summarize(cold).expect("built at least once"),
+        codegen: summarize(codegen),
+        link: summarize(link),
+        rebuild: summarize(warm),
+        commands,
+    })
+}
+
+/// Where rustc keeps a binary's incremental state under --incremental
+fn incremental_dir(out: &Path) -> PathBuf {
+    out.with_extension("incr")
+}
+
+fn drop_caches() -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg("sync && echo 3 > /proc/sys/vm/drop_caches")
+        .status()?;
+
+    if !status.success() {
+        bail!("dropping caches failed; --drop-caches needs root");
+    }
+
+    Ok(())
+}
+
+/// Measurements are only comparable on an otherwise idle machine
+fn warn_if_busy() {
+    let load = fs::read_to_string("/proc/loadavg").ok()
+        .and_then(|l| l.split_whitespace().next()?.parse::<f64>().ok());
+
+    if let Some(load) = load {
+        if load > 1.0 {
+            println!("warning: load average is {:.2}; compile times \
+                      measured now will be noisy", load);
+        }
+    }
+}
+
+fn run_one_case(config: CaseConfig, runner: &Runner, repeat: u32,
+                retries: u32) -> Result<()> {
+    prereport("running", &config);
+
+    let case = manifest::find_case(&config)?;
+    let bins = gen_bin_paths(&config, &case.gen_opts);
+
+    if bins.iter().any(|(_, bin)| !bin.exists()) {
+        bail!("case {} types / {} fns has not been compiled in {}",
+              config.num_types, config.num_fns, config.outdir.display());
+    }
+
+    for (_, src) in gen_src_paths(&config, &case.gen_opts) {
+        hash::verify(&config, &case.gen_opts, &src)?;
+    }
+
+    if case.status < CaseStatus::Compiled {
+        bail!("case {} types / {} fns was regenerated after it was \
+               compiled; compile it again",
+              config.num_types, config.num_fns);
+    }
+
+    let compile_opts = results::find_case(&config)?
+        .and_then(|c| c.compile)
+        .map(|c| c.opts);
+    let target = compile_opts.as_ref().and_then(|o| o.target.clone());
+    let compare_target = compile_opts.as_ref()
+        .is_some_and(|o| o.compare_target || o.compare_mitigations);
+    let compare_mitigations = compile_opts.is_some_and(|o| o.compare_mitigations);
+
+    let execution = || Failure::new(Phase::Execution, &config);
+    let read_checksum = case.gen_opts.checksum;
+    let samples = |bin: &Path| if case.gen_opts.cold_start {
+        runner.launch_samples(bin, target.as_deref(),
+                              repeat.max(COLD_START_LAUNCHES), retries, read_checksum)
+    } else {
+        runner.run_samples(bin, target.as_deref(), repeat, retries, read_checksum)
+    };
+    let runs = bins.iter()
+        .map(|(dispatch, bin)| Ok((*dispatch, samples(bin)?)))
+        .collect::<Result<results::Variants<_>>>()
+        .with_context(execution)?;
+
+    if read_checksum {
+        let sums = runs.iter()
+            .map(|(dispatch, r)| (dispatch, r.checksum.expect("read on the first run")))
+            .collect::<Vec<_>>();
+        let sum = checksum::verify(&sums).with_context(execution)?;
+        println!("checksum            : {:016x}", sum);
+    }
+
+    if runs.iter().all(|(_, r)| r.instructions.is_some()) {
+        for (dispatch, r) in runs.iter() {
+            println!("{:<20}: {}", format!("{}-instructions", dispatch),
+                     r.instructions.unwrap_or_default());
+        }
+    }
+    let emulated = matches!(runner, Runner::Qemu(_));
+    let label = if emulated { " (emulated)" } else { "" };
+    for (dispatch, r) in runs.iter() {
+        println!("{:<16}: {}", format!("{}-run-time{}", dispatch, label), r.run_time_ms);
+    }
+    if runs.iter().all(|(_, r)| r.launch_time_stats.is_some()) {
+        for (dispatch, r) in runs.iter() {
+            let stats = r.launch_time_stats.as_ref().expect("checked above");
+            println!("{:<22}: {}", format!("{}-launch-time-us", dispatch), stats.median);
+        }
+    }
+    for (num, den) in Dispatch::RATIOS {
+        if let Some(ratio) = runs.run_time_ratio(num, den) {
+            println!("run-time-ratio{}  : {} ({}/{}, 95% CI)", label, ratio, num, den);
+        }
+    }
+
+    let baseline = if compare_target {
+        let base = bins.iter()
+            .map(|(dispatch, _)| {
+                let path = variant_path(&config, *dispatch, "base.bin");
+                Ok((*dispatch, runner.run_retried(&path, target.as_deref(), retries, false)?))
+            })
+            .collect::<Result<results::Variants<_>>>()
+            .with_context(execution)?;
+        for (dispatch, b) in base.iter() {
+            println!("{:<25}: {}", format!("{}-baseline-run-time{}", dispatch, label),
+                     b.run_time_ms);
+        }
+        if compare_mitigations {
+            for (dispatch, b) in base.iter() {
+                let ran = runs.get(dispatch).expect("ran every variant");
+                println!("{:<23}: {}", format!("{}-mitigation-cost", dispatch),
+                         run_time_delta(b.run_time_ms, ran.run_time_ms));
+            }
+        }
+        Some(base)
+    } else {
+        None
+    };
+
+    results::record_run(&config, results::RunResults {
+        variants: runs,
+        host: match runner {
+            Runner::Remote(remote) => Some(remote.host.clone()),
+            _ => None,
+        },
+        container: match runner {
+            Runner::Container(container) => Some(container.image().to_string()),
+            _ => None,
+        },
+        emulated,
+        sandboxed: matches!(runner, Runner::Sandbox(_)),
+        cold_cache: matches!(runner, Runner::Local { cold_cache: true, .. }),
+        baseline,
+    })?;
+    manifest::set_status(&config, CaseStatus::Ran)?;
+    summary::write(&config)?;
+
+    Ok(())
+}
+
+/// How much slower a run got than its baseline
+fn run_time_delta(base_ms: u64, ms: u64) -> String {
+    let delta = ms as i64 - base_ms as i64;
+    if base_ms == 0 {
+        return format!("{:+} ms", delta);
+    }
+    format!("{:+} ms ({:+.1}%)", delta, 100.0 * delta as f64 / base_ms as f64)
+}
+
+/// Records a case that went over --mem-limit in the results, passing
+/// the result on
+fn record_mem_limit(config: &CaseConfig, result: Result<()>) -> Result<()> {
+    let err = match result {
+        Err(ref e) => e,
+        Ok(()) => return result,
+    };
+    if let (Some(over), Some(failure)) = (err.downcast_ref::<memlimit::MemLimitExceeded>(),
+                                          err.downcast_ref::<Failure>()) {
+        results::record_mem_limit(config, results::MemLimitOutcome {
+            phase: failure.phase,
+            limit: over.limit,
+        })?;
+        summary::write(config)?;
+    }
+
+    result
+}
+
+/// Records a case that went over --mem-limit and lets a sweep carry on
+/// to the next
+fn carry_on_over_mem_limit(config: &CaseConfig, result: Result<()>) -> Result<()> {
+    match record_mem_limit(config, result) {
+        Err(e) if e.downcast_ref::<memlimit::MemLimitExceeded>().is_some() => {
+            println!("{:#}; carrying on", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Reports a case a sweep has finished a phase of to --stream and
+/// --metrics-listen, passing its result through
+fn finished(config: &CaseConfig, phase: Phase, result: Result<()>) -> Result<()> {
+    metrics::case(config, phase, &result);
+    stream::case(config, phase, result)
+}
+
+fn run_all_for(config: MultiCaseConfig, test: impl Fn(CaseConfig) -> Result<()>) -> Result<()> {
+    interrupt::install();
+
+    let mut cases = vec![];
+    for type_num in config.types.values() {
+        for fn_num in config.fns.values() {
+            cases.push(CaseConfig {
+                outdir: config.outdir.clone(),
+                num_types: type_num,
+                num_fns: fn_num,
+            });
+        }
+    }
+    if let Some((n, seed)) = config.sample {
+        let grid = cases.len();
+        cases = stats::sample(cases, n, seed);
+        println!("sampling {} of the grid's {} cases with seed {}", cases.len(), grid, seed);
+    }
+
+    let total = cases.len();
+    metrics::sweep(total);
+    for (done, case) in cases.into_iter().enumerate() {
+        if interrupt::interrupted() {
+            return Err(interrupt::stopped(done, total, &case,
+                                          "the cases so far are in the manifest"));
+        }
+        let result = test(case.clone());
+        if result.is_err() && interrupt::interrupted() {
+            return Err(interrupt::stopped(done, total, &case,
+                                          "the cases so far are in the manifest"));
+        }
+        result?;
+    }
+
+    Ok(())
+}
+
+fn run_all_discovered(outdir: &Path, filter: &AxisFilter, budget: &SweepBudget,
+                      resume_past: Option<CaseStatus>, jobs: usize,
+                      test: impl Fn(CaseConfig) -> Result<()> + Sync) -> Result<()> {
+    let mut cases = manifest::load(outdir)?;
+
+    cases.retain(|c| filter.matches(c.num_types, c.num_fns));
+
+    if cases.is_empty() {
+        bail!("no generated cases in {} match", outdir.display());
+    }
+
+    if let Some(done) = resume_past {
+        let total = cases.len();
+        cases.retain(|c| c.status < done);
+        if let Some(first) = cases.first() {
+            println!("resuming at case: {} types / {} fns ({} of {} done)",
+                     first.num_types, first.num_fns,
+                     total - cases.len(), total);
+        } else {
+            println!("all {} cases already done", total);
+        }
+    }
+
+    if budget.time_budget.is_some() {
+        budget::order(&mut cases, &budget.priority);
+    }
+    let deadline = budget.deadline;
+    let out_of_time = || deadline.is_some_and(|d| Instant::now() >= d);
+    let finished = Mutex::new(vec![]);
+    let report = |finished: Mutex<Vec<(u32, u32)>>| {
+        if let Some(time_budget) = budget.time_budget {
+            budget::report(&cases, &finished.into_inner().expect("poisoned"), time_budget);
+        }
+    };
+
+    interrupt::install();
+    let total = cases.len();
+    metrics::sweep(total);
+    let resume = "rerun the phase with --resume to continue there";
+
+    if jobs <= 1 {
+        for (done, case) in cases.iter().enumerate() {
+            if out_of_time() {
+                break;
+            }
+            if interrupt::interrupted() {
+                return Err(interrupt::stopped(done, total, &case.config(outdir), resume));
+            }
+            let result = test(case.config(outdir));
+            if result.is_err() && interrupt::interrupted() {
+                return Err(interrupt::stopped(done, total, &case.config(outdir), resume));
+            }
+            result?;
+            finished.lock().expect("poisoned").push((case.num_types, case.num_fns));
+        }
+        report(finished);
+        return Ok(());
+    }
+
+    // Workers take cases in order until one fails or the sweep is
+    // interrupted. Cases finish out of order, so an interrupted sweep
+    // resumes at the first case any worker didn't finish.
+    let queue = Mutex::new(cases.iter());
+    let failed = AtomicBool::new(false);
+    let unfinished = Mutex::new(vec![]);
+    let errors: Vec<anyhow::Error> = thread::scope(|s| {
+        let workers: Vec<_> = (0..jobs).map(|_| s.spawn(|| -> Result<()> {
+            while !failed.load(Ordering::SeqCst) && !interrupt::interrupted()
+                && !out_of_time()
+            {
+                let case = queue.lock().expect("poisoned").next();
+                let case = match case {
+                    Some(case) => case,
+                    None => break,
+                };
+                let result = jobs::with_token(|| test(case.config(outdir)))
+                    .and_then(|r| r);
+                if result.is_err() && interrupt::interrupted() {
+                    unfinished.lock().expect("poisoned").push(case);
+                    break;
+                }
+                if result.is_err() {
+                    failed.store(true, Ordering::SeqCst);
+                    return result;
+                }
+                finished.lock().expect("poisoned").push((case.num_types, case.num_fns));
+            }
+            Ok(())
+        })).collect();
+
+        workers.into_iter()
+            .filter_map(|w| w.join().expect("worker panicked").err())
+            .collect()
+    });
+
+    if let Some(e) = errors.into_iter().next() {
+        return Err(e);
+    }
+    if interrupt::interrupted() {
+        let mut unfinished = unfinished.into_inner().expect("poisoned");
+        unfinished.extend(queue.into_inner().expect("poisoned"));
+        unfinished.sort_by_key(|c| (c.num_types, c.num_fns));
+        if let Some(next) = unfinished.first() {
+            return Err(interrupt::stopped(total - unfinished.len(), total,
+                                          &next.config(outdir), resume));
+        }
+    }
+    report(finished);
+
+    Ok(())
+}
+
+fn gen_all_cases(config: MultiCaseConfig, opts: GenOpts) -> Result<()> {
+    run_all_for(config, |c| {
+        let result = gen_one_case(c.clone(), opts.clone());
+        finished(&c, Phase::Generation, result)
+    })
+}
+
+fn compile_all_cases(outdir: &Path, filter: &AxisFilter, budget: &SweepBudget, resume: bool,
+                     jobs: Option<usize>, opts: CompileOpts) -> Result<()> {
+    let resume_past = if resume { Some(CaseStatus::Compiled) } else { None };
+    let workers = match jobs {
+        Some(jobs) => {
+            let limit = jobs::init(jobs)?;
+            // Under make, as many workers as make allows will get tokens
+            limit.unwrap_or_else(|| thread::available_parallelism()
+                                 .map_or(1, |n| n.get()))
+        }
+        None => 1,
+    };
+    run_all_discovered(outdir, filter, budget, resume_past, workers, |c| {
+        let result = compile_one_case(c.clone(), opts.clone());
+        carry_on_over_mem_limit(&c, finished(&c, Phase::Compilation, result))
+    })
+}
+
+fn run_all_cases(outdir: &Path, filter: &AxisFilter, budget: &SweepBudget, resume: bool,
+                 runner: &Runner, repeat: u32, retries: u32) -> Result<()> {
+    let resume_past = if resume { Some(CaseStatus::Ran) } else { None };
+    // Runs are never parallel, since they'd disturb each other's times
+    run_all_discovered(outdir, filter, budget, resume_past, 1, |c| {
+        let over = results::find_case(&c)?.and_then(|r| r.mem_limit_exceeded);
+        if over.is_some_and(|o| o.phase == Phase::Compilation) {
+            println!("skipping case: {} types / {} fns went over the memory \
+                      limit compiling", c.num_types, c.num_fns);
+            return Ok(());
+        }
+        let result = run_one_case(c.clone(), runner, repeat, retries);
+        carry_on_over_mem_limit(&c, finished(&c, Phase::Execution, result))
+    })
+}
+
+fn gen_src_paths(config: &CaseConfig, opts: &GenOpts) -> Vec<(Dispatch, PathBuf)> {
+    gen_paths(config, opts, opts.lang.src_ext())
+}
+
+fn gen_bin_paths(config: &CaseConfig, opts: &GenOpts) -> Vec<(Dispatch, PathBuf)> {
+    gen_paths(config, opts, "bin")
+}
+
+/// The file with the extension of each of the case's variants
+fn gen_paths(config: &CaseConfig, opts: &GenOpts, ext: &str) -> Vec<(Dispatch, PathBuf)> {
+    opts.variants().into_iter()
+        .map(|dispatch| (dispatch, variant_path(config, dispatch, ext)))
+        .collect()
+}
+
+/// A variant's file for a case, like `dynamic-0008-0004.bin`
+fn variant_path(config: &CaseConfig, dispatch: Dispatch, ext: &str) -> PathBuf {
+    config.outdir.join(format!("{}-{:04}-{:04}.{}", dispatch,
+                               config.num_types, config.num_fns, ext))
+}
+
+
+static HEADER: &str = "
+use std::hint::black_box;
+";
+
+/// The header under --blackbox asm: the value's address escapes into an
+/// asm block that may read or write any memory
+static ASM_BLACK_BOX: &str = "
+#[inline(always)]
+fn black_box<T>(v: T) -> T {
+    unsafe {
+        ::std::arch::asm!(\"/* {0} */\", in(reg) &v, options(nostack, preserves_flags));
+    }
+    v
+}
+";
+
+/// The header under --blackbox volatile: the value is read back through
+/// a volatile load the optimizer must keep
+static VOLATILE_BLACK_BOX: &str = "
+#[inline(always)]
+fn black_box<T>(v: T) -> T {
+    unsafe {
+        let copy = std::ptr::read_volatile(&v);
+        std::mem::forget(v);
+        copy
+    }
+}
+";
+
+/// The header under --blackbox none
+static NO_BLACK_BOX: &str = "
+#[inline(always)]
+fn black_box<T>(v: T) -> T {
+    v
+}
+";
+
+/// The loop's rebinding of a value under --vary-args
+static VARY_ARGS: &str = "
+#[inline(always)]
+fn vary<T>(v: T, pass: usize) -> T {
+    black_box((v, pass)).0
+}
+";
+
+/// The running sum of the methods' results under --checksum. A plain
+/// load and store, as a locked add would cost more than the call.
+static CHECKSUM: &str = "
+static CHECKSUM: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[inline(always)]
+fn checksum(result: u64) {
+    use std::sync::atomic::Ordering::Relaxed;
+    CHECKSUM.store(CHECKSUM.load(Relaxed).wrapping_add(result), Relaxed);
+}
+";
+
+/// The blanket impl the types' impls specialize under --specialization
+fn gen_default_impl() -> TokenStream {
+    quote! {
+        impl<T> Io for T {
+            default fn do_io_m(&self) {
+                black_box(self);
+            }
+        }
+    }
+}
+
+/// The trait the types implement, with the extra items the trait-shape
+/// options add
+fn gen_trait(opts: &GenOpts) -> Result<TokenStream> {
+    // Downcasting needs the trait objects to be Any too
+    let bounds = if opts.visitor { quote!(: std::any::Any) } else { quote!() };
+    let mut items = if opts.assoc_type {
+        quote! {
+            type Out;
+            fn do_io_m(&self) -> Self::Out;
+        }
+    } else {
+        let ret = method_ret(opts);
+        quote!(fn do_io_m(&self) #ret;)
+    };
+    if opts.generic_method || opts.object_safety_stress {
+        // Only the static program's fns call it
+        items.extend(quote! {
+            #[allow(dead_code)]
+            fn do_io_g<U>(&self, u: U) where Self: Sized {
+                black_box(u);
+                self.do_io_m();
+            }
+        });
+    }
+    if opts.object_safety_stress {
+        // Each program calls one of each pair: the static program the
+        // methods trait objects can't have, the dynamic program the
+        // object-safe stand-ins
+        let dyn_io = dyn_io(opts);
+        items.extend(quote! {
+            #[allow(dead_code)]
+            fn do_io_any(&self, u: &dyn std::any::Any) {
+                black_box(u);
+                self.do_io_m();
+            }
+            #[allow(dead_code)]
+            fn dup(&self) -> Self where Self: Sized;
+            #[allow(dead_code)]
+            fn dup_boxed(&self) -> Box<#dyn_io>;
+        });
+    }
+    Ok(quote!(trait Io #bounds { #items }))
+}
+
+/// The traits derived from Io under --trait-depth, each implemented for
+/// everything implementing the one before
+fn gen_sub_traits(opts: &GenOpts) -> TokenStream {
+    (1..=opts.trait_depth).map(|depth| {
+        let sub = format_ident!("Sub{}", depth);
+        let sup = if depth == 1 { format_ident!("Io") } else { format_ident!("Sub{}", depth - 1) };
+        quote! {
+            trait #sub: #sup {}
+            impl<T: #sup> #sub for T {}
+        }
+    }).collect()
+}
+
+/// The trait the fns take their argument as, the most derived under
+/// --trait-depth
+fn fn_trait(opts: &GenOpts) -> syn::Ident {
+    if opts.trait_depth > 0 {
+        format_ident!("Sub{}", opts.trait_depth)
+    } else {
+        format_ident!("Io")
+    }
+}
+
+/// The trait object type the dynamic fns take
+fn dyn_fn_trait(opts: &GenOpts) -> syn::Type {
+    let bound = fn_trait(opts);
+    if opts.assoc_type {
+        parse_quote!(dyn #bound<Out = u8>)
+    } else {
+        parse_quote!(dyn #bound)
+    }
+}
+
+/// The trait object type, which names the associated type under
+/// --assoc-type. Every type's `Out` is the same so they share one.
+fn dyn_io(opts: &GenOpts) -> syn::Type {
+    if opts.assoc_type {
+        parse_quote!(dyn Io<Out = u8>)
+    } else {
+        parse_quote!(dyn Io)
+    }
+}
+
+/// The impls of the trait's copying methods under
+/// --object-safety-stress, to follow a type's do_io_m
+fn type_extra(num: u32, opts: &GenOpts) -> TokenStream {
+    if !opts.object_safety_stress {
+        return quote!();
+    }
+    let ty = format_ident!("T{}", num);
+    let dyn_io = dyn_io(opts);
+    quote! {
+        fn dup(&self) -> Self {
+            #ty(self.0)
+        }
+        fn dup_boxed(&self) -> Box<#dyn_io> {
+            Box::new(#ty(self.0))
+        }
+    }
+}
+
+/// A type's method's addition to the --checksum sum, after the method's
+/// first statement
+fn method_checksum(num: u32, opts: &GenOpts) -> TokenStream {
+    if !opts.checksum {
+        return quote!();
+    }
+    // Spread out, so a call to the wrong type can't be made up for by
+    // calls to others
+    let result = ast::hex((num as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    quote!(checksum(#result);)
+}
+
+/// The end of each method and fn, which --no-dedup makes differ from
+/// all the others'
+fn dedup_guard(num: u32, opts: &GenOpts) -> TokenStream {
+    let (no_dedup, num) = (opts.no_dedup, ast::num(num));
+    quote! {
+        if #no_dedup {
+            black_box(&#num);
+        }
+    }
+}
+
+/// What the method returns
+fn method_ret(opts: &GenOpts) -> syn::ReturnType {
+    if opts.assoc_type {
+        parse_quote!(-> u8)
+    } else if opts.errors.is_some() {
+        parse_quote!(-> Result<(), Error>)
+    } else {
+        syn::ReturnType::Default
+    }
+}
+
+/// A type and its impl of Io. The method returns its value under
+/// --assoc-type, and fails with the type's error under --errors.
+fn gen_type_item(num: u32, num_types: u32, opts: &GenOpts) -> Result<TokenStream> {
+    let ty = format_ident!("T{}", num);
+    let field = gen_type(num, num_types);
+    let (assoc, ret, tail) = if opts.assoc_type {
+        (quote!(type Out = #field;), quote!(-> #field), quote!(self.0))
+    } else if let Some(path) = opts.errors {
+        let (error, fails) = (format_ident!("E{}", num), path == ErrorPath::Hot);
+        let tail = quote! {
+            if black_box(#fails) {
+                return Err(#error.into());
+            }
+            Ok(())
+        };
+        (quote!(), quote!(-> Result<(), Error>), tail)
+    } else {
+        (quote!(), quote!(), quote!())
+    };
+    let checksum = method_checksum(num, opts);
+    let guard = dedup_guard(num, opts);
+    let extra = type_extra(num, opts);
+
+    Ok(quote! {
+        struct #ty(#field);
+        impl Io for #ty {
+            #assoc
+            fn do_io_m(&self) #ret {
+                black_box(self);
+                #checksum
+                #guard
+                #tail
+            }
+            #extra
+        }
+    })
+}
+
+/// What the fns return
+fn fn_ret(opts: &GenOpts) -> syn::ReturnType {
+    if opts.errors.is_some() {
+        parse_quote!(-> Result<(), Error>)
+    } else {
+        syn::ReturnType::Default
+    }
+}
+
+/// The --errors error types, one per type, that both programs' methods
+/// fail with
+fn gen_error_types(num_types: u32) -> TokenStream {
+    (0..num_types).map(|num| {
+        let error = format_ident!("E{}", num);
+        let message = format!("T{} failed", num);
+        quote! {
+            #[derive(Debug)]
+            struct #error;
+            impl std::fmt::Display for #error {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    write!(f, #message)
+                }
+            }
+            impl std::error::Error for #error {}
+        }
+    }).collect()
+}
+
+/// A fn, `do_io_f{num}`, taking `params` and dispatching with `body`.
+/// Under --errors it passes on the methods' errors.
+fn fn_item(num: u32, generics: TokenStream, params: TokenStream, body: TokenStream,
+           opts: &GenOpts) -> TokenStream {
+    let name = format_ident!("do_io_f{}", num);
+    let ret = fn_ret(opts);
+    let guard = dedup_guard(num, opts);
+    let ok = if opts.errors.is_some() { quote!(Ok(())) } else { quote!() };
+
+    quote! {
+        fn #name #generics(#params) #ret {
+            #body
+            #guard
+            #ok
+        }
+    }
+}
+
+/// A --structs struct's method, calling its value's
+fn struct_method(num: u32, opts: &GenOpts) -> TokenStream {
+    let guard = dedup_guard(num, opts);
+    quote! {
+        fn do_io_s(&self) {
+            self.io.do_io_m();
+            #guard
+        }
+    }
+}
+
+/// The parts of a generated case that differ between the static and
+/// dynamic programs
+struct Variant {
+    /// None for the concrete program, whose call sites call the
+    /// methods themselves
+    gen_fn: Option<GenItemFn>,
+    /// A call from the loop to a fn with a type's value
+    fn_call: FnCallFn,
+    write_direct_call: WriteDirectCallFn,
+    write_keep_fn: WriteKeepFn,
+    write_value: WriteValueFn,
+    gen_struct: GenItemFn,
+    write_keep_struct: WriteKeepFn,
+    /// A type's value as the `io` of a --structs struct
+    struct_io: StructIoFn,
+    /// The struct the fns take under --holder
+    holder: fn(opts: &GenOpts) -> Result<TokenStream>,
+    /// The enum of the types the fns take under --visitor or
+    /// --static-via enum, if any
+    visitor_enum: fn(num_types: u32) -> Option<TokenStream>,
+    /// What a type's value is shared as between --threads
+    shared_value: fn(type_num: u32, opts: &GenOpts) -> String,
+    write_tasks: WriteTasksFn,
+    /// The Error the methods and fns return under --errors
+    error_type: fn(num_types: u32) -> TokenStream,
+    header_note: &'static str,
+    fns_note: fn(opts: &GenOpts) -> &'static str,
+    holder_note: &'static str,
+    structs_note: &'static str,
+    /// The states and their transitions under --state-machine
+    gen_states: GenStatesFn,
+    /// main's binding of the first state, `s`, under --state-machine
+    first_state: fn(num_types: u32) -> String,
+    /// main's statement advancing `s` one transition
+    advance: &'static str,
+    states_note: &'static str,
+}
+
+static STATIC_VARIANT: Variant = Variant {
+    gen_fn: Some(gen_fn_static),
+    fn_call: fn_call_static,
+    write_direct_call: write_direct_call_static,
+    write_keep_fn: write_keep_fn_static,
+    write_value: write_value_static,
+    gen_struct: gen_struct_static,
+    write_keep_struct: write_keep_struct_static,
+    struct_io: struct_io_static,
+    holder: |_| Ok(quote!(struct Holder<T: Io> { io: T })),
+    visitor_enum: |num_types| {
+        let types: Vec<_> = (0..num_types).map(|t| format_ident!("T{}", t)).collect();
+        Some(quote!(enum Val { #(#types(#types)),* }))
+    },
+    shared_value: |type_num, _| format!("T{}", type_num),
+    write_tasks: write_tasks_static,
+    error_type: error_enum,
+    header_note: explain::HEADER_NOTE,
+    fns_note: |opts| if opts.const_generic {
+        explain::CONST_GENERIC_FNS_NOTE
+    } else if opts.visitor {
+        explain::STATIC_VISITOR_FNS_NOTE
+    } else if takes_enum(opts) {
+        explain::STATIC_ENUM_FNS_NOTE
+    } else if opts.components > 0 {
+        explain::STATIC_SYSTEMS_FNS_NOTE
+    } else {
+        explain::STATIC_FNS_NOTE
+    },
+    holder_note: explain::STATIC_HOLDER_NOTE,
+    structs_note: explain::STATIC_STRUCTS_NOTE,
+    gen_states: gen_states_static,
+    first_state: |num_types| format!("let mut s = black_box(State::S0(T0({})));",
+                                     gen_ctor(0, num_types)),
+    advance: "s = step(s);",
+    states_note: explain::STATIC_STATES_NOTE,
+};
+
+static DYNAMIC_VARIANT: Variant = Variant {
+    gen_fn: Some(gen_fn_dynamic),
+    fn_call: fn_call_dynamic,
+    write_direct_call: write_direct_call_dynamic,
+    write_keep_fn: write_keep_fn_dynamic,
+    write_value: write_value_dynamic,
+    gen_struct: gen_struct_dynamic,
+    write_keep_struct: write_keep_struct_dynamic,
+    struct_io: struct_io_dynamic,
+    holder: |opts| {
+        let dyn_io = dyn_io(opts);
+        Ok(quote!(struct Holder { io: Box<#dyn_io> }))
+    },
+    visitor_enum: |_| None,
+    shared_value: |_, opts| format!("{} + Send + Sync", ast::type_text(&dyn_io(opts))),
+    write_tasks: write_tasks_dynamic,
+    error_type: |_| quote!(type Error = Box<dyn std::error::Error>;),
+    header_note: explain::HEADER_NOTE,
+    fns_note: |opts| match opts.dynamic_via {
+        DynamicVia::Vtable if opts.visitor => explain::DYNAMIC_VISITOR_FNS_NOTE,
+        DynamicVia::Vtable if opts.components > 0 => explain::DYNAMIC_SYSTEMS_FNS_NOTE,
+        DynamicVia::Vtable => explain::DYNAMIC_FNS_NOTE,
+        DynamicVia::JumpTable => explain::JUMP_TABLE_FNS_NOTE,
+        DynamicVia::BranchTree => explain::BRANCH_TREE_FNS_NOTE,
+        DynamicVia::FnTable => explain::FN_TABLE_FNS_NOTE,
+        DynamicVia::Closure => explain::CLOSURE_FNS_NOTE,
+    },
+    holder_note: explain::DYNAMIC_HOLDER_NOTE,
+    structs_note: explain::DYNAMIC_STRUCTS_NOTE,
+    gen_states: gen_states_dynamic,
+    first_state: |num_types| format!("let mut s: Box<dyn State> = black_box(Box::new(T0({})));",
+                                     gen_ctor(0, num_types)),
+    advance: "s = s.next();",
+    states_note: explain::DYNAMIC_STATES_NOTE,
+};
+
+/// The --concrete baseline, whose call sites call each type's method
+/// on its own value, as the static program's fns do once inlined
+static CONCRETE_VARIANT: Variant = Variant {
+    gen_fn: None,
+    fn_call: |_, _, value, _| format!("{}.do_io_m()", value),
+    write_direct_call: |code, _, value, _, opts| {
+        code.line(call_stmt(&format!("{}.do_io_m()", value), opts));
+        Ok(())
+    },
+    write_keep_fn: |_, _, _, _| {},
+    write_value: |code, type_num, ctor, _| {
+        code.line(format_args!("static V{num}: &T{num} = &T{num}({ctor});",
+                               num = type_num, ctor = ctor));
+    },
+    gen_struct: |_, _, _| unreachable!("--concrete conflicts with --structs"),
+    write_keep_struct: |_, _, _, _| {},
+    struct_io: struct_io_static,
+    holder: |_| unreachable!("--concrete conflicts with --holder"),
+    visitor_enum: |_| None,
+    shared_value: |type_num, _| format!("T{}", type_num),
+    write_tasks: write_tasks_static,
+    error_type: error_enum,
+    header_note: explain::CONCRETE_HEADER_NOTE,
+    fns_note: |_| explain::CONCRETE_CALLS_NOTE,
+    holder_note: "",
+    structs_note: "",
+    gen_states: |_, _| unreachable!("--concrete conflicts with --state-machine"),
+    first_state: |_| unreachable!("--concrete conflicts with --state-machine"),
+    advance: "",
+    states_note: "",
+};
+
+/// The Error of the static and concrete programs under --errors, an
+/// enum of the types' errors
+fn error_enum(num_types: u32) -> TokenStream {
+    let errors: Vec<_> = (0..num_types).map(|t| format_ident!("E{}", t)).collect();
+    quote! {
+        #[allow(dead_code)]
+        #[derive(Debug)]
+        enum Error { #(#errors(#errors)),* }
+        #(impl From<#errors> for Error {
+            fn from(e: #errors) -> Error { Error::#errors(e) }
+        })*
+    }
+}
+
+fn gen_static(config: &CaseConfig, path: &Path, opts: GenOpts) -> Result<()> {
+    gen_case(config, path, &STATIC_VARIANT, opts)
+}
+
+fn gen_dynamic(config: &CaseConfig, path: &Path, opts: GenOpts) -> Result<()> {
+    gen_case(config, path, &DYNAMIC_VARIANT, opts)
+}
+
+fn gen_concrete(config: &CaseConfig, path: &Path, opts: GenOpts) -> Result<()> {
+    gen_case(config, path, &CONCRETE_VARIANT, opts)
+}
+
+pub(crate) const TEST_LOOPS: usize = 100_000;
+
+/// How many times main repeats the call sites
+pub(crate) fn test_loops(opts: &GenOpts) -> usize {
+    if opts.cold_start { 1 } else { TEST_LOOPS }
+}
+
+/// The fewest launches a --cold-start case's latency is measured over
+const COLD_START_LAUNCHES: u32 = 100;
+
+/// Builds one of a case's numbered items, its fns or its structs
+type GenItemFn = fn(num: u32, num_types: u32, opts: &GenOpts) -> Result<TokenStream>;
+
+fn gen_fn_static(num: u32, num_types: u32, opts: &GenOpts) -> Result<TokenStream> {
+    let call = |recv: TokenStream| method_call_static(&recv, num, opts);
+    if takes_enum(opts) {
+        let dispatch = visitor_match(&quote!(v), num_types, &call);
+        return Ok(fn_item(num, quote!(), quote!(v: &Val), dispatch, opts));
+    }
+    if opts.const_generic {
+        let arms = type_arms(num_types, &call);
+        let dispatch = quote! {
+            match K {
+                #arms
+                _ => unreachable!(),
+            }
+        };
+        return Ok(fn_item(num, quote!(<const K: u32>), quote!(), dispatch, opts));
+    }
+    if opts.holder {
+        return Ok(fn_item(num, quote!(<T: Io>), quote!(h: &Holder<T>), call(quote!(h.io)),
+                          opts));
+    }
+    let bound = fn_trait(opts);
+    let call = call(quote!(v));
+    if opts.components > 0 {
+        return Ok(fn_item(num, quote!(<T: #bound>), quote!(v: &[T]),
+                          quote!(for v in v { #call }), opts));
+    }
+    Ok(fn_item(num, quote!(<T: #bound>), quote!(v: &T), call, opts))
+}
+
+/// A type's value, as the match arms and branches make it
+fn type_value(type_num: u32, num_types: u32) -> TokenStream {
+    let (ty, ctor) = (format_ident!("T{}", type_num), gen_ctor(type_num, num_types));
+    quote!(#ty(#ctor))
+}
+
+/// A match arm per type, each calling the method on its own value
+/// with `call`
+fn type_arms(num_types: u32, call: &dyn Fn(TokenStream) -> TokenStream) -> TokenStream {
+    (0..num_types).map(|type_num| {
+        let (arm, call) = (ast::num(type_num), call(type_value(type_num, num_types)));
+        quote!(#arm => { #call })
+    }).collect()
+}
+
+/// The dynamic program's dispatch on `tag` to a call for each type,
+/// under a --dynamic-via other than vtable. Uniform branch-trees start
+/// at type `first`.
+fn tag_dispatch(tag: &TokenStream, num_types: u32, first: u32, opts: &GenOpts,
+                call: &dyn Fn(TokenStream) -> TokenStream) -> TokenStream {
+    match opts.dynamic_via {
+        DynamicVia::Vtable | DynamicVia::Closure => unreachable!("only tags are matched on"),
+        DynamicVia::JumpTable => {
+            let arms = type_arms(num_types, call);
+            quote! {
+                match #tag {
+                    #arms
+                    _ => unreachable!(),
+                }
+            }
+        }
+        DynamicVia::BranchTree => {
+            if num_types == 0 {
+                return quote!(unreachable!(););
+            }
+            let order: Vec<u32> = match opts.branch_order {
+                BranchOrder::LikelyFirst => skew::by_frequency(num_types, opts.skew),
+                BranchOrder::Uniform => {
+                    let first = first % num_types;
+                    (first..num_types).chain(0..first).collect()
+                }
+            };
+            let branches = order.into_iter().map(|type_num| {
+                let (num, call) = (ast::num(type_num), call(type_value(type_num, num_types)));
+                quote!(if #tag == #num { #call })
+            });
+            quote!(#(#branches)else* else { unreachable!(); })
+        }
+        DynamicVia::FnTable => {
+            let len = ast::num(num_types);
+            let entries = (0..num_types).map(|type_num| {
+                let call = call(type_value(type_num, num_types));
+                quote!(|| { #call })
+            });
+            // A block of its own, so direct calls' tables don't clash
+            quote!({
+                static TABLE: [fn(); #len] = [#(#entries),*];
+                TABLE[#tag as usize]();
+            })
+        }
+    }
+}
+
+/// Whether the static program's fns take a Val enum of the types, under
+/// --visitor or --static-via enum
+fn takes_enum(opts: &GenOpts) -> bool {
+    opts.visitor || opts.static_via == StaticVia::Enum
+}
+
+/// The static program's match on a Val enum at `recv`, with an arm per
+/// type calling its method on `t`
+fn visitor_match(recv: &TokenStream, num_types: u32,
+                 call: &dyn Fn(TokenStream) -> TokenStream) -> TokenStream {
+    let arms = (0..num_types).map(|type_num| {
+        let (ty, call) = (format_ident!("T{}", type_num), call(quote!(t)));
+        quote!(Val::#ty(ref t) => { #call })
+    });
+    quote!(match *#recv { #(#arms)* })
+}
+
+/// The dynamic program's --visitor counterpart, trying to downcast
+/// `recv` to each type in turn
+fn visitor_downcasts(recv: &TokenStream, num_types: u32,
+                     call: &dyn Fn(TokenStream) -> TokenStream) -> TokenStream {
+    let rest = if num_types == 0 {
+        quote!(black_box(any);)
+    } else {
+        let downcasts = (0..num_types).map(|type_num| {
+            let (ty, call) = (format_ident!("T{}", type_num), call(quote!(t)));
+            quote!(if let Some(t) = any.downcast_ref::<#ty>() { #call })
+        });
+        quote!(#(#downcasts)else* else { unreachable!(); })
+    };
+    quote! {
+        let any: &dyn std::any::Any = #recv;
+        #rest
+    }
+}
+
+fn gen_fn_dynamic(num: u32, num_types: u32, opts: &GenOpts) -> Result<TokenStream> {
+    if opts.visitor {
+        // Each downcast finds a concrete type, so the static program's
+        // calls apply
+        let dispatch = visitor_downcasts(&quote!(v), num_types,
+                                         &|recv| method_call_static(&recv, num, opts));
+        let dyn_io = dyn_io(opts);
+        return Ok(fn_item(num, quote!(), quote!(v: &#dyn_io), dispatch, opts));
+    }
+    if opts.dynamic_via == DynamicVia::Closure {
+        // The closure makes the method call, knowing its type
+        let event = ast::num(num);
+        return Ok(fn_item(num, quote!(), quote!(v: &dyn Fn(u32)), quote!(v(#event);), opts));
+    }
+    if opts.dynamic_via.has_tag() {
+        // Each branch calls a concrete type, so the static program's
+        // calls apply
+        let dispatch = tag_dispatch(&quote!(tag), num_types, num, opts,
+                                    &|recv| method_call_static(&recv, num, opts));
+        return Ok(fn_item(num, quote!(), quote!(tag: u32), dispatch, opts));
+    }
+    if opts.holder {
+        let call = method_call_dynamic(&quote!(h.io), num, opts);
+        return Ok(fn_item(num, quote!(), quote!(h: &Holder), call, opts));
+    }
+    let dyn_io = dyn_io(opts);
+    let call = method_call_dynamic(&quote!(v), num, opts);
+    if opts.components > 0 {
+        return Ok(fn_item(num, quote!(), quote!(v: &[Box<#dyn_io>]),
+                          quote!(for v in v { #call }), opts));
+    }
+    let upcast = if opts.upcast { quote!(let v: &#dyn_io = v;) } else { quote!() };
+    let dyn_fn_trait = dyn_fn_trait(opts);
+    Ok(fn_item(num, quote!(), quote!(v: &#dyn_fn_trait), quote!(#upcast #call), opts))
+}
+
+/// A fn's call to the method on `recv`. Under --generic-method the
+/// static program calls the generic method, instantiated per type, and
+/// under --object-safety-stress it also copies the value.
+fn method_call_static(recv: &TokenStream, num: u32, opts: &GenOpts) -> TokenStream {
+    let arg = ast::suffixed(num as u64, "u32");
+    if opts.object_safety_stress {
+        quote! {
+            #recv.do_io_g(#arg);
+            black_box(#recv.dup()).do_io_m();
+        }
+    } else if opts.generic_method {
+        quote!(#recv.do_io_g(#arg);)
+    } else if opts.errors.is_some() {
+        quote!(#recv.do_io_m()?;)
+    } else {
+        quote!(#recv.do_io_m();)
+    }
+}
+
+/// The dynamic program's counterpart, which can't call the generic
+/// method on a trait object and does its work inline instead. Under
+/// --object-safety-stress it uses the object-safe stand-ins: the
+/// argument goes through &dyn Any and the copy is boxed.
+fn method_call_dynamic(recv: &TokenStream, num: u32, opts: &GenOpts) -> TokenStream {
+    let arg = ast::suffixed(num as u64, "u32");
+    if opts.object_safety_stress {
+        quote! {
+            #recv.do_io_any(&#arg);
+            #recv.dup_boxed().do_io_m();
+        }
+    } else if opts.generic_method {
+        quote! {
+            black_box(#arg);
+            #recv.do_io_m();
+        }
+    } else if opts.errors.is_some() {
+        quote!(#recv.do_io_m()?;)
+    } else {
+        quote!(#recv.do_io_m();)
+    }
+}
+
+/// A call as a statement in main, which drops its Result under --errors
+fn call_stmt(call: &str, opts: &GenOpts) -> String {
+    if opts.errors.is_some() {
+        format!("let _ = black_box({});", call)
+    } else {
+        format!("{};", call)
+    }
+}
+
+/// The name a call site gives type `type_num`'s value: its static, or
+/// under --vary-args the local each pass rebinds it to, as statics
+/// can't be shadowed
+fn value_name(type_num: u32, opts: &GenOpts) -> String {
+    if opts.vary_args {
+        format!("v{}", type_num)
+    } else {
+        format!("V{}", type_num)
+    }
+}
+
+/// A call through a fn, on the value named `value`
+type FnCallFn = fn(fn_num: u32, type_num: u32, value: &str, opts: &GenOpts) -> String;
+
+fn fn_call_static(fn_num: u32, type_num: u32, value: &str, opts: &GenOpts) -> String {
+    if opts.const_generic {
+        format!("do_io_f{}::<{}>()", fn_num, type_num)
+    } else {
+        format!("do_io_f{}({})", fn_num, value)
+    }
+}
+
+fn fn_call_dynamic(fn_num: u32, _type_num: u32, value: &str, _opts: &GenOpts) -> String {
+    format!("do_io_f{}({})", fn_num, value)
+}
+
+/// Writes a call straight to a type's method, on the value named
+/// `value`, for cases with no fns
+type WriteDirectCallFn = fn(code: &mut Code, type_num: u32, value: &str, num_types: u32,
+                            opts: &GenOpts) -> Result<()>;
+
+/// A plain method call on `recv`, for the dispatch main makes itself
+fn call_method(recv: TokenStream) -> TokenStream {
+    quote!(#recv.do_io_m();)
+}
+
+fn write_direct_call_static(code: &mut Code, _type_num: u32, value: &str, num_types: u32,
+                            opts: &GenOpts) -> Result<()> {
+    if takes_enum(opts) {
+        let value = format_ident!("{}", value);
+        code.lines(&ast::stmts_text(visitor_match(&quote!(#value), num_types, &call_method))?);
+    } else if opts.holder {
+        code.line(format_args!("{}.io.do_io_m();", value));
+    } else if opts.components > 0 {
+        code.line(format_args!("for v in {} {{ v.do_io_m(); }}", value));
+    } else {
+        code.line(call_stmt(&format!("{}.do_io_m()", value), opts));
+    }
+    Ok(())
+}
+
+fn write_direct_call_dynamic(code: &mut Code, type_num: u32, value: &str, num_types: u32,
+                             opts: &GenOpts) -> Result<()> {
+    if opts.visitor {
+        // A block per call, for its `any`
+        let value = format_ident!("{}", value);
+        let downcasts = visitor_downcasts(&quote!(#value), num_types, &call_method);
+        code.lines(&ast::stmts_text(quote!({ #downcasts }))?);
+    } else if opts.dynamic_via == DynamicVia::Closure {
+        code.line(format_args!("{}(0);", value));
+    } else if opts.dynamic_via.has_tag() {
+        let value = format_ident!("{}", value);
+        code.lines(&ast::stmts_text(tag_dispatch(&quote!(#value), num_types, type_num, opts,
+                                                 &call_method))?);
+    } else if opts.holder {
+        // The field is already a Box<dyn Io>
+        code.line(format_args!("{}.io.do_io_m();", value));
+    } else if opts.components > 0 {
+        code.line(format_args!("for v in {} {{ v.do_io_m(); }}", value));
+    } else {
+        code.line(call_stmt(&format!("({} as &{}).do_io_m()", value,
+                                     ast::type_text(&dyn_io(opts))),
+                            opts));
+    }
+    Ok(())
+}
+
+/// Writes the --keep-symbols statics referencing one fn
+type WriteKeepFn = fn(code: &mut Code, fn_num: u32, num_types: u32, opts: &GenOpts);
+
+fn write_keep_fn_static(code: &mut Code, fn_num: u32, num_types: u32, opts: &GenOpts) {
+    if takes_enum(opts) {
+        code.line(format_args!("#[used] static KEEP_F{num}: fn(&Val) = do_io_f{num};",
+                               num = fn_num));
+        return;
+    }
+    for type_num in 0..num_types {
+        if opts.const_generic {
+            code.line(format_args!("#[used] static KEEP_F{fn_num}_T{type_num}: fn() \
+                                    = do_io_f{fn_num}::<{type_num}>;",
+                                   fn_num = fn_num, type_num = type_num));
+            continue;
+        }
+        let arg = if opts.holder {
+            format!("&Holder<T{}>", type_num)
+        } else if opts.components > 0 {
+            format!("&[T{}]", type_num)
+        } else {
+            format!("&T{}", type_num)
+        };
+        code.line(format_args!("#[used] static KEEP_F{fn_num}_T{type_num}: fn({arg}){ret} \
+                                = do_io_f{fn_num}::<T{type_num}>;",
+                               fn_num = fn_num, type_num = type_num, arg = arg,
+                               ret = ast::ret_text(&fn_ret(opts))));
+    }
+}
+
+fn write_keep_fn_dynamic(code: &mut Code, fn_num: u32, _num_types: u32, opts: &GenOpts) {
+    let arg = if opts.holder {
+        "&Holder".to_string()
+    } else if opts.components > 0 {
+        format!("&[Box<{}>]", ast::type_text(&dyn_io(opts)))
+    } else if opts.dynamic_via == DynamicVia::Closure {
+        "&dyn Fn(u32)".to_string()
+    } else if opts.dynamic_via.has_tag() {
+        "u32".to_string()
+    } else {
+        format!("&{}", ast::type_text(&dyn_fn_trait(opts)))
+    };
+    code.line(format_args!("#[used] static KEEP_F{num}: fn({arg}){ret} = do_io_f{num};",
+                           num = fn_num, arg = arg, ret = ast::ret_text(&fn_ret(opts))));
+}
+
+/// Writes the binding in main for one type's value, which the loop
+/// passes to the fns as `V{type_num}`
+type WriteValueFn = fn(code: &mut Code, type_num: u32, ctor: &str, opts: &GenOpts);
+
+fn write_value_static(code: &mut Code, type_num: u32, ctor: &str, opts: &GenOpts) {
+    if takes_enum(opts) {
+        code.line(format_args!("static V{num}: &Val = &Val::T{num}(T{num}({ctor}));",
+                               num = type_num, ctor = ctor));
+    } else if opts.holder {
+        code.line(format_args!("static V{num}: &Holder<T{num}> = \
+                                &Holder {{ io: T{num}({ctor}) }};",
+                               num = type_num, ctor = ctor));
+    } else if opts.components > 0 {
+        code.line(format_args!("static V{num}: &[T{num}] = &[const {{ T{num}({ctor}) }}; {len}];",
+                               num = type_num, ctor = ctor, len = opts.components));
+    } else {
+        code.line(format_args!("static V{num}: &T{num} = &T{num}({ctor});",
+                               num = type_num, ctor = ctor));
+    }
+}
+
+fn write_value_dynamic(code: &mut Code, type_num: u32, ctor: &str, opts: &GenOpts) {
+    if opts.dynamic_via.has_tag() {
+        // A tag the optimizer can't see through, so the match is made
+        // at run time
+        code.line(format_args!("let V{num}: u32 = black_box({num});", num = type_num));
+    } else if opts.dynamic_via == DynamicVia::Closure {
+        // Each closure captures a static value and is only known to the
+        // fns through its vtable
+        code.line(format_args!("static H{num}: &T{num} = &T{num}({ctor});",
+                               num = type_num, ctor = ctor));
+        code.line(format_args!("let V{num}: Box<dyn Fn(u32)> = Box::new(|event| {{ \
+                                black_box(event); H{num}.do_io_m(); }});", num = type_num));
+        code.line(format_args!("let V{num}: &dyn Fn(u32) = &*V{num};", num = type_num));
+    } else if opts.components > 0 {
+        // A Box can't be built in a static
+        code.line(format_args!("let V{num}: Vec<Box<{dyn_io}>> = (0..{len}).map(|_| \
+                                Box::new(T{num}({ctor})) as Box<{dyn_io}>).collect();",
+                               num = type_num, ctor = ctor, len = opts.components,
+                               dyn_io = ast::type_text(&dyn_io(opts))));
+        code.line(format_args!("let V{num}: &[Box<{dyn_io}>] = &V{num};",
+                               num = type_num, dyn_io = ast::type_text(&dyn_io(opts))));
+    } else if opts.holder {
+        code.line(format_args!("let V{num}: &Holder = \
+                                &Holder {{ io: Box::new(T{num}({ctor})) }};",
+                               num = type_num, ctor = ctor));
+    } else {
+        code.line(format_args!("static V{num}: &T{num} = &T{num}({ctor});",
+                               num = type_num, ctor = ctor));
+    }
+}
+
+fn gen_struct_static(num: u32, _num_types: u32, opts: &GenOpts) -> Result<TokenStream> {
+    let name = format_ident!("S{}", num);
+    let method = struct_method(num, opts);
+    Ok(quote! {
+        struct #name<'a, T: Io> { io: &'a T }
+        impl<'a, T: Io> #name<'a, T> { #method }
+    })
+}
+
+fn gen_struct_dynamic(num: u32, _num_types: u32, opts: &GenOpts) -> Result<TokenStream> {
+    let name = format_ident!("S{}", num);
+    let dyn_io = dyn_io(opts);
+    let method = struct_method(num, opts);
+    Ok(quote! {
+        struct #name<'a> { io: &'a #dyn_io }
+        impl<'a> #name<'a> { #method }
+    })
+}
+
+fn write_keep_struct_static(code: &mut Code, struct_num: u32, num_types: u32, _opts: &GenOpts) {
+    for type_num in 0..num_types {
+        code.line(format_args!("#[used] static KEEP_S{struct_num}_T{type_num}: \
+                                fn(&S{struct_num}<'static, T{type_num}>) \
+                                = S{struct_num}::<T{type_num}>::do_io_s;",
+                               struct_num = struct_num, type_num = type_num));
+    }
+}
+
+fn write_keep_struct_dynamic(code: &mut Code, struct_num: u32, _num_types: u32,
+                             _opts: &GenOpts) {
+    code.line(format_args!("#[used] static KEEP_S{num}: fn(&S{num}<'static>) = S{num}::do_io_s;",
+                           num = struct_num));
+}
+
+/// A struct's io field, from the value named `value`
+type StructIoFn = fn(value: &str, opts: &GenOpts) -> String;
 
-    for fn_num in 0..config.num_fns {
-        write_fn(&mut file, fn_num, &opts)?;
+fn struct_io_static(value: &str, opts: &GenOpts) -> String {
+    if opts.holder {
+        format!("&{}.io", value)
+    } else {
+        value.to_string()
+    }
+}
+
+fn struct_io_dynamic(value: &str, opts: &GenOpts) -> String {
+    if opts.holder {
+        format!("&*{}.io", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// The attributes --no-inline adds to the fns and methods dispatch
+/// goes through
+fn inline_attrs(opts: &GenOpts) -> Vec<syn::Attribute> {
+    if opts.no_inline {
+        vec![parse_quote!(#[inline(never)])]
+    } else {
+        vec![]
+    }
+}
+
+/// Writes the items in `tokens`, after an empty line. Those `measured`
+/// are the ones dispatch goes through, and take the attributes the
+/// options add to its fns and methods.
+fn write_items(code: &mut Code, tokens: TokenStream, measured: bool,
+               opts: &GenOpts) -> Result<()> {
+    let mut items = ast::items(tokens)?;
+    if measured {
+        for attr in inline_attrs(opts) {
+            ast::inject_attr(&mut items, &attr);
+        }
+    }
+    code.blank();
+    code.lines(&ast::render(items));
+    Ok(())
+}
+
+fn gen_case(config: &CaseConfig, path: &Path,
+            variant: &Variant, opts: GenOpts) -> Result<()> {
+    assert!(path.extension().expect("") == "rs");
+    let dir = path.parent().expect("directory");
+    fs::create_dir_all(dir)?;
+    // Built in memory so it can be checked before anything is written
+    let mut file = Code::new();
+
+    file.line(format_args!("// types = {}, fns = {}", config.num_types, config.num_fns));
+    file.line(hash::header_line(&hash::case_hash(config, &opts)?));
+    file.blank();
+
+    if config.num_types == 0 || config.num_fns == 0 {
+        file.line("#![allow(unused)]");
+    }
+
+    if opts.annotate {
+        explain::write_note(&mut file, variant.header_note);
+    }
+
+    file.blank();
+    file.line("#![feature(test)]");
+    if opts.specialization {
+        file.line("#![feature(min_specialization)]");
+    }
+    write!(file, "{}", opts.blackbox.header())?;
+    if opts.vary_args {
+        write!(file, "{}", VARY_ARGS)?;
+    }
+    if opts.checksum {
+        write!(file, "{}", CHECKSUM)?;
+    }
+    if opts.annotate {
+        if opts.assoc_type {
+            explain::write_note(&mut file, explain::ASSOC_TYPE_NOTE);
+        }
+        if opts.generic_method {
+            explain::write_note(&mut file, explain::GENERIC_METHOD_NOTE);
+        }
+        if opts.object_safety_stress {
+            explain::write_note(&mut file, explain::OBJECT_SAFETY_NOTE);
+        }
+    }
+    write_items(&mut file, gen_trait(&opts)?, false, &opts)?;
+    if opts.trait_depth > 0 {
+        if opts.annotate {
+            explain::write_note(&mut file, explain::TRAIT_DEPTH_NOTE);
+        }
+        write_items(&mut file, gen_sub_traits(&opts), false, &opts)?;
+    }
+    if opts.errors.is_some() {
+        write_items(&mut file, gen_error_types(config.num_types), false, &opts)?;
+        write_items(&mut file, (variant.error_type)(config.num_types), false, &opts)?;
+    }
+    if opts.specialization {
+        if opts.annotate {
+            explain::write_note(&mut file, explain::SPECIALIZATION_NOTE);
+        }
+        write_items(&mut file, gen_default_impl(), true, &opts)?;
     }
 
-    writeln!(file)?;
-    writeln!(file, "fn main() {{")?;
+    if opts.annotate {
+        explain::write_note(&mut file, explain::TYPES_NOTE);
+    }
 
     for type_num in 0..config.num_types {
-        writeln!(file, "    static V{num}: &T{num} = &T{num}({ctor});",
-                 num = type_num, ctor = gen_ctor(type_num, config.num_types))?;
+        write_items(&mut file, gen_type_item(type_num, config.num_types, &opts)?, true, &opts)?;
+    }
+
+    if opts.annotate {
+        explain::write_note(&mut file, (variant.fns_note)(&opts));
+    }
+
+    if takes_enum(&opts) {
+        if let Some(def) = (variant.visitor_enum)(config.num_types) {
+            write_items(&mut file, def, false, &opts)?;
+        }
+    }
+
+    if opts.holder {
+        if opts.annotate {
+            explain::write_note(&mut file, variant.holder_note);
+        }
+        write_items(&mut file, (variant.holder)(&opts)?, false, &opts)?;
     }
-    writeln!(file)?;
 
-    writeln!(file, "    for _ in 0..{} {{", TEST_LOOPS)?;
+    if opts.structs > 0 && opts.annotate {
+        explain::write_note(&mut file, variant.structs_note);
+    }
+    for struct_num in 0..opts.structs {
+        let def = (variant.gen_struct)(struct_num, config.num_types, &opts)?;
+        write_items(&mut file, def, true, &opts)?;
+    }
 
-    if !opts.predictable {
+    if let Some(gen_fn) = variant.gen_fn {
         for fn_num in 0..config.num_fns {
-            for type_num in 0..config.num_types {
-                writeln!(file, "        do_io_f{fn_num}(V{type_num});",
-                         fn_num = fn_num,
-                         type_num = type_num)?;
+            write_items(&mut file, gen_fn(fn_num, config.num_types, &opts)?, true, &opts)?;
+        }
+    }
+
+    if opts.keep_symbols {
+        file.blank();
+        for type_num in 0..config.num_types {
+            file.line(format_args!("#[used] static KEEP_M{num}: fn(&T{num}){ret} \
+                                    = <T{num} as Io>::do_io_m;",
+                                   num = type_num, ret = ast::ret_text(&method_ret(&opts))));
+        }
+        for fn_num in 0..config.num_fns {
+            (variant.write_keep_fn)(&mut file, fn_num, config.num_types, &opts);
+        }
+        for struct_num in 0..opts.structs {
+            (variant.write_keep_struct)(&mut file, struct_num, config.num_types, &opts);
+        }
+    }
+
+    if opts.state_machine {
+        if opts.annotate {
+            explain::write_note(&mut file, variant.states_note);
+        }
+        let states = (variant.gen_states)(config.num_types, config.num_fns)?;
+        write_items(&mut file, states, true, &opts)?;
+    }
+
+    if opts.annotate {
+        explain::write_note(&mut file, &explain::main_note(&opts));
+    }
+
+    file.blank();
+    if opts.holder || !opts.dynamic_via.is_vtable() || opts.threads > 0
+        || opts.components > 0
+    {
+        // The values are locals named like the statics
+        file.line("#[allow(non_snake_case)]");
+    }
+    file.open("fn main()");
+
+    for type_num in 0..config.num_types {
+        if opts.state_machine {
+            // The states make their own values
+            if type_num == 0 {
+                file.line((variant.first_state)(config.num_types));
+            }
+        } else if opts.threads > 0 {
+            file.line(format_args!("let V{num}: std::sync::Arc<{shared}> = \
+                                    std::sync::Arc::new(T{num}({ctor}));",
+                                   num = type_num, shared = (variant.shared_value)(type_num, &opts),
+                                   ctor = gen_ctor(type_num, config.num_types)));
+        } else {
+            (variant.write_value)(&mut file, type_num,
+                                  &gen_ctor(type_num, config.num_types).to_string(), &opts);
+        }
+    }
+    file.blank();
+
+    // The types main calls the methods with directly, and a fn or
+    // struct with
+    let call_types = skew::call_types(config.num_types, opts.skew);
+    let site_types = |site| {
+        skew::site_call_types(config.num_types, site, opts.site_types, opts.skew)
+    };
+
+    // The loop's call sites, each its own lines, with empty ones
+    // between groups
+    let mut sites: Vec<String> = vec![];
+    if config.num_types == 0 {
+        // Nothing to call; keep the loop itself so its overhead is
+        // still measured
+        sites.push("black_box(());\n".to_string());
+    } else if opts.state_machine {
+        for _ in 0..config.num_types {
+            sites.push(format!("{}\n", variant.advance));
+        }
+    } else if config.num_fns == 0 {
+        // No fns to dispatch through, so call the methods directly
+        for &type_num in &call_types {
+            let mut site = Code::new();
+            (variant.write_direct_call)(&mut site, type_num, &value_name(type_num, &opts),
+                                        config.num_types, &opts)?;
+            sites.push(site.finish());
+        }
+    } else if !opts.predictable {
+        for fn_num in 0..config.num_fns {
+            for type_num in site_types(fn_num) {
+                let value = value_name(type_num, &opts);
+                sites.push(format!("{}\n",
+                                   call_stmt(&(variant.fn_call)(fn_num, type_num, &value,
+                                                                &opts),
+                                             &opts)));
             }
-            writeln!(file)?;
+            sites.push(String::new());
         }
     } else {
-        for type_num in 0..config.num_types {
-            for fn_num in 0..config.num_fns {
-                writeln!(file, "        do_io_f{fn_num}(V{type_num});",
-                         fn_num = fn_num,
-                         type_num = type_num)?;
+        // Each fn's calls in turn, then grouped by type
+        let fn_types: Vec<_> = (0..config.num_fns).map(site_types).collect();
+        let mut calls = vec![];
+        for slot in 0..config.num_types as usize {
+            for (fn_num, types) in fn_types.iter().enumerate() {
+                calls.push((fn_num, types[slot]));
+            }
+        }
+        calls.sort_by_key(|&(_, type_num)| type_num);
+        for (i, &(fn_num, type_num)) in calls.iter().enumerate() {
+            let value = value_name(type_num, &opts);
+            sites.push(format!("{}\n",
+                               call_stmt(&(variant.fn_call)(fn_num as u32, type_num, &value,
+                                                            &opts),
+                                         &opts)));
+            if calls.get(i + 1).is_none_or(|&(_, next)| next != type_num) {
+                sites.push(String::new());
+            }
+        }
+    }
+
+    if config.num_types > 0 {
+        let mut calls = vec![];
+        for struct_num in 0..opts.structs {
+            for type_num in site_types(struct_num) {
+                calls.push((struct_num, type_num));
+            }
+        }
+        if opts.predictable {
+            calls.sort_by_key(|&(struct_num, type_num)| (type_num, struct_num));
+        }
+        for (struct_num, type_num) in calls {
+            sites.push(format!("S{} {{ io: {} }}.do_io_s();\n",
+                               struct_num,
+                               (variant.struct_io)(&value_name(type_num, &opts), &opts)));
+        }
+    }
+
+    if !opts.call_shape.is_inline_loop() {
+        // Each site in a fn of its own, which can see main's statics
+        sites.retain(|site| !site.is_empty());
+        for (site_num, site) in sites.iter().enumerate() {
+            file.fn_item("#[inline(never)]", format_args!("site{}()", site_num),
+                         |c| c.lines(site));
+        }
+        if opts.call_shape == CallShape::IndirectThroughArray {
+            let names: Vec<_> = (0..sites.len()).map(|n| format!("site{}", n)).collect();
+            file.line(format_args!("static SITES: [fn(); {}] = [{}];",
+                                   sites.len(), names.join(", ")));
+        }
+        file.blank();
+    }
+
+    // What each pass makes its calls with
+    let calls = |c: &mut Code| match opts.call_shape {
+        CallShape::InlineLoop => {
+            for site in &sites {
+                if site.is_empty() {
+                    c.blank();
+                } else {
+                    c.lines(site);
+                }
+            }
+        }
+        CallShape::Outlined => {
+            for site_num in 0..sites.len() {
+                c.line(format_args!("site{}();", site_num));
             }
-            writeln!(file)?;
         }
+        CallShape::IndirectThroughArray => {
+            // Through the array as the optimizer can't see it, so each
+            // call stays indirect
+            c.for_loop("site", "black_box(&SITES)", |c| c.line("site();"));
+        }
+    };
+
+    // Under --threads the loop is written as usual, then into each
+    // thread's closure. Under --tasks only its body is, into each
+    // task's poll.
+    let mut main_loop = Code::new();
+    if opts.tasks > 0 {
+        calls(&mut main_loop);
+    } else if opts.vary_args && config.num_types > 0 {
+        main_loop.for_loop("pass", format_args!("0..{}", test_loops(&opts)), |c| {
+            for type_num in 0..config.num_types {
+                c.line(format_args!("let {} = vary(V{}, pass);",
+                                    value_name(type_num, &opts), type_num));
+            }
+            calls(c);
+        });
+    } else {
+        main_loop.for_loop("_", format_args!("0..{}", test_loops(&opts)), calls);
+    }
+    let main_loop = main_loop.finish();
+    if opts.threads > 0 {
+        write_threads(&mut file, &main_loop, config.num_types, opts.threads);
+    } else if opts.tasks > 0 {
+        let loops = (test_loops(&opts) / opts.tasks as usize).max(1);
+        file.line("let mut cx = std::task::Context::from_waker(std::task::Waker::noop());");
+        (variant.write_tasks)(&mut file, &main_loop, opts.tasks, loops);
+    } else {
+        file.lines(&main_loop);
+    }
+    if opts.checksum {
+        file.line(format_args!("println!(\"{}{{:016x}}\", \
+                                CHECKSUM.load(std::sync::atomic::Ordering::Relaxed));",
+                               checksum::PREFIX));
     }
+    file.close();
 
-    writeln!(file, "    }}")?;
-    writeln!(file, "}}")?;
+    let file = file.finish();
+    validate_source(config, path, file.as_bytes())?;
+    fs::write(path, file)?;
 
-    file.flush()?;
-    drop(file);
+    if opts.rustfmt {
+        run_rustfmt(path)?;
+    }
 
     Ok(())
 }
 
-fn gen_type(num: u32, num_types: u32) -> String {
-    "u8".to_string()
+/// Builds the --state-machine states and their transitions
+type GenStatesFn = fn(num_types: u32, num_fns: u32) -> Result<TokenStream>;
+
+/// The work of a transition out of a state, `recv`: a call through each
+/// fn, or to the method if there are none
+fn state_work(recv: TokenStream, as_arg: TokenStream, num_fns: u32) -> TokenStream {
+    if num_fns == 0 {
+        return quote!(#recv.do_io_m(););
+    }
+    (0..num_fns).map(|fn_num| {
+        let f = format_ident!("do_io_f{}", fn_num);
+        quote!(#f(#as_arg);)
+    }).collect()
+}
+
+fn gen_states_static(num_types: u32, num_fns: u32) -> Result<TokenStream> {
+    let states: Vec<_> = (0..num_types).map(|t| format_ident!("S{}", t)).collect();
+    let types: Vec<_> = (0..num_types).map(|t| format_ident!("T{}", t)).collect();
+    let mut arms = quote!();
+    for type_num in 0..num_types {
+        let next = ((type_num + 1) % num_types) as usize;
+        let (state, next_state, next_type) = (&states[type_num as usize], &states[next],
+                                              &types[next]);
+        let ctor = gen_ctor(next as u32, num_types);
+        let work = state_work(quote!(t), quote!(t), num_fns);
+        arms.extend(quote! {
+            State::#state(ref t) => {
+                #work
+                State::#next_state(#next_type(#ctor))
+            }
+        });
+    }
+
+    Ok(quote! {
+        enum State { #(#states(#types)),* }
+        fn step(s: State) -> State {
+            match s { #arms }
+        }
+    })
+}
+
+fn gen_states_dynamic(num_types: u32, num_fns: u32) -> Result<TokenStream> {
+    let mut states = quote! {
+        trait State { fn next(self: Box<Self>) -> Box<dyn State>; }
+    };
+    for type_num in 0..num_types {
+        let next = (type_num + 1) % num_types;
+        let (ty, next_type) = (format_ident!("T{}", type_num), format_ident!("T{}", next));
+        let ctor = gen_ctor(next, num_types);
+        let work = state_work(quote!(self), quote!(&*self), num_fns);
+        states.extend(quote! {
+            impl State for #ty {
+                fn next(self: Box<Self>) -> Box<dyn State> {
+                    #work
+                    Box::new(#next_type(#ctor))
+                }
+            }
+        });
+    }
+    Ok(states)
 }
 
-fn gen_ctor(num: u32, num_types: u32) -> String {
-    "0_u8".to_string()
+/// Writes main's loop, `body`, to run on `threads` scoped threads. Each
+/// thread takes its own clones of the values, and borrows from them
+/// under the values' usual names.
+fn write_threads(code: &mut Code, body: &str, num_types: u32, threads: u32) {
+    code.open("std::thread::scope(|s|");
+    code.for_loop("_", format_args!("0..{}", threads), |c| {
+        for type_num in 0..num_types {
+            c.line(format_args!("let V{num} = V{num}.clone();", num = type_num));
+        }
+        c.open("s.spawn(move ||");
+        for type_num in 0..num_types {
+            c.line(format_args!("let V{num} = &*V{num};", num = type_num));
+        }
+        c.lines(body);
+        c.close_with(");");
+    });
+    code.close_with(");");
 }
 
-fn run_rustc_bin(src: &Path, out: &Path, opts: &CompileOpts) -> Result<Duration> {
-    run_rustc(src, out, "link", opts)
+/// Writes the --tasks futures, each making `loops` passes of the loop's
+/// body, `body`, and the executor polling them in turn
+type WriteTasksFn = fn(code: &mut Code, body: &str, tasks: u32, loops: usize);
+
+/// A --tasks future, in a block its caller opens, that makes one pass
+/// of `body` each time it's polled, so it's pending after each pass and
+/// ready on the poll after its last
+fn write_task(code: &mut Code, body: &str, loops: usize) {
+    code.line("let mut pass = 0;");
+    code.open("std::future::poll_fn(move |_|");
+    code.block(format_args!("if pass == {}", loops), |c| {
+        c.line("return std::task::Poll::Ready(());");
+    });
+    code.line("pass += 1;");
+    code.blank();
+    code.lines(body);
+    code.line("std::task::Poll::Pending");
+    code.close_with(")");
 }
 
-fn run_rustc_asm(src: &Path, out: &Path, opts: &CompileOpts) -> Result<Duration> {
-    run_rustc(src, out, "asm", opts)
+fn write_tasks_static(code: &mut Code, body: &str, tasks: u32, loops: usize) {
+    for task in 0..tasks {
+        code.open(format_args!("let mut task{} = std::pin::pin!(", task));
+        write_task(code, body, loops);
+        code.close_with(");");
+    }
+    code.blank();
+    code.for_loop("_", format_args!("0..={}", loops), |c| {
+        for task in 0..tasks {
+            c.line(format_args!("let _ = std::future::Future::poll(task{}.as_mut(), &mut cx);",
+                                task));
+        }
+    });
 }
 
-fn run_rustc(src: &Path, out: &Path, emit: &str, opts: &CompileOpts) -> Result<Duration> {
-    let start = Instant::now();
+fn write_tasks_dynamic(code: &mut Code, body: &str, tasks: u32, loops: usize) {
+    code.line("let mut tasks: Vec<std::pin::Pin<Box<dyn std::future::Future<Output = ()> + '_>>> \
+               = vec![");
+    code.indented(|c| {
+        for _ in 0..tasks {
+            c.open("Box::pin(");
+            write_task(c, body, loops);
+            c.close_with("),");
+        }
+    });
+    code.line("];");
+    code.blank();
+    code.for_loop("_", format_args!("0..={}", loops), |c| {
+        c.for_loop("task", "tasks.iter_mut()", |c| {
+            c.line("let _ = std::future::Future::poll(task.as_mut(), &mut cx);");
+        });
+    });
+}
+
+/// Catches generator bugs at generation time instead of hours later
+/// when the compile sweep reaches the broken case
+fn validate_source(config: &CaseConfig, path: &Path, src: &[u8]) -> Result<()> {
+    let src = std::str::from_utf8(src)?;
+
+    if let Err(e) = syn::parse_file(src) {
+        bail!("generated invalid Rust for case {} types / {} fns ({}): {}",
+              config.num_types, config.num_fns, path.display(), e);
+    }
+
+    Ok(())
+}
 
-    let status = Command::new("rustc")
+fn gen_type(num: u32, num_types: u32) -> syn::Type {
+    parse_quote!(u8)
+}
+
+fn gen_ctor(num: u32, num_types: u32) -> syn::LitInt {
+    ast::suffixed(0, "u8")
+}
+
+fn run_rustfmt(src: &Path) -> Result<()> {
+    let status = Command::new("rustfmt")
+        .arg("--edition")
+        .arg("2018")
         .arg(src)
-        .arg("--emit")
-        .arg(emit)
-        .arg("-o")
-        .arg(out)
-        .arg(format!("-Copt-level={}", opts.opt_level))
         .status()?;
 
     if !status.success() {
-        bail!("rustc failed");
+        bail!("rustfmt failed on {}", src.display());
     }
 
-    let end = Instant::now();
+    Ok(())
+}
 
-    Ok(end - start)
+/// How long a build took, and how much of that was linking when
+/// linking is timed separately with --split-link
+struct BuildTime {
+    total: Duration,
+    link: Option<Duration>,
+    /// The compiler and linker command lines it ran
+    commands: Vec<String>,
+}
+
+/// Compiles a source to an output
+type CompileFn = fn(src: &Path, out: &Path, opts: &CompileOpts) -> Result<BuildTime>;
+
+fn run_rustc_bin(src: &Path, out: &Path, opts: &CompileOpts) -> Result<BuildTime> {
+    run_rustc(src, out, "link", opts)
+}
+
+fn run_rustc_asm(src: &Path, out: &Path, opts: &CompileOpts) -> Result<BuildTime> {
+    run_rustc(src, out, "asm", opts)
+}
+
+fn run_rustc(src: &Path, out: &Path, emit: &str, opts: &CompileOpts) -> Result<BuildTime> {
+    let dir = src.parent().expect("src paths have parents");
+    let (src, out) = tool_paths(src, out, opts);
+    let mut args = rustc_args(&src, &out, emit, opts);
+
+    if !opts.split_link || emit != "link" {
+        let total = run_tool("rustc", dir, &args, opts)?;
+        let commands = vec![tool_command("rustc", dir, &args, opts)];
+        return Ok(BuildTime { total, link: None, commands });
+    }
+
+    // -Zno-link stops after codegen, leaving the objects and a .rlink
+    // file describing how to link them for -Zlink-only
+    args.push("-Zno-link".into());
+    let codegen = run_tool("rustc", dir, &args, opts)?;
+
+    let mut link_args: Vec<OsString> = vec![
+        "-Zlink-only".into(),
+        out.with_extension("rlink").into(),
+        "-o".into(),
+        out.clone().into(),
+    ];
+    if let Some(ref target) = opts.target {
+        link_args.push("--target".into());
+        link_args.push(target.into());
+    }
+    let link = run_tool("rustc", dir, &link_args, opts)?;
+    let rlink = out.with_extension("rlink");
+    fs::remove_file(dir.join(rlink.file_name().expect("rlink paths have names")))?;
+
+    Ok(BuildTime {
+        total: codegen + link,
+        link: Some(link),
+        commands: vec![tool_command("rustc", dir, &args, opts),
+                       tool_command("rustc", dir, &link_args, opts)],
+    })
+}
+
+/// The source and output paths to give a compiler. In a container,
+/// where the run directory is the working directory, these are just
+/// the file names, since sources and outputs are side by side.
+fn tool_paths(src: &Path, out: &Path, opts: &CompileOpts) -> (PathBuf, PathBuf) {
+    if opts.container.is_some() {
+        (PathBuf::from(src.file_name().expect("src paths have names")),
+         PathBuf::from(out.file_name().expect("out paths have names")))
+    } else {
+        (src.to_owned(), out.to_owned())
+    }
+}
+
+/// The command line `run_tool` runs, for the results
+fn tool_command(program: &str, dir: &Path, args: &[OsString],
+                opts: &CompileOpts) -> String {
+    cmdline::tool(program, dir, args, opts.container.as_deref())
+}
+
+/// Runs a compiler or linker, locally or in --container, and returns
+/// how long it took. Failures are retried under --retries.
+fn run_tool(program: &str, dir: &Path, args: &[OsString],
+            opts: &CompileOpts) -> Result<Duration> {
+    retry::retry(opts.retries, || run_tool_once(program, dir, args, opts))
 }
 
-fn run_case(bin: &Path) -> Result<Duration> {
+fn run_tool_once(program: &str, dir: &Path, args: &[OsString],
+                 opts: &CompileOpts) -> Result<Duration> {
+    if let Some(ref image) = opts.container {
+        let container = container::Container::new(image)?;
+        return container.time(dir, program, args);
+    }
+
     let start = Instant::now();
 
-    let status = Command::new(bin)
-        .status()?;
+    let mem_limit = opts.mem_limit.map(|b| b.0);
+    let mut cmd = Command::new(program);
+    jobs::configure(&mut cmd);
+    cmd.args(args);
+    let status = memlimit::output(&mut cmd, mem_limit)?.status;
 
     if !status.success() {
-        bail!("running case failed");
+        bail!("{} failed with {}: {}", program, status,
+              tool_command(program, dir, args, opts));
     }
 
     let end = Instant::now();
@@ -523,21 +4144,101 @@ fn run_case(bin: &Path) -> Result<Duration> {
     Ok(end - start)
 }
 
-fn count_symbols(bin: &Path) -> Result<(usize, usize)> {
-    let output = Command::new("nm")
-        .arg(bin)
-        .output()?;
+fn rustc_args(src: &Path, out: &Path, emit: &str, opts: &CompileOpts) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        src.into(),
+        "--emit".into(),
+        emit.into(),
+        "-o".into(),
+        out.into(),
+        format!("-Copt-level={}", opts.opt_level).into(),
+    ];
+    if let Some(ref target) = opts.target {
+        args.push("--target".into());
+        args.push(target.into());
+    }
+    // Only the binary's build is measured; --asm builds start over
+    if opts.incremental && emit == "link" {
+        let mut arg = OsString::from("-Cincremental=");
+        arg.push(incremental_dir(out));
+        args.push(arg);
+    }
+    if let Some(lto) = opts.lto {
+        args.push(format!("-Clto={}", lto).into());
+    }
+    if opts.codegen_backend == Backend::Cranelift {
+        args.push("-Zcodegen-backend=cranelift".into());
+    }
+    if let Some(ref cpu) = opts.target_cpu {
+        args.push(format!("-Ctarget-cpu={}", cpu).into());
+    }
+    let mut features = opts.target_feature.clone();
+    if opts.linking == Some(Linking::Static) {
+        features.push("+crt-static".to_string());
+    }
+    if !features.is_empty() {
+        args.push(format!("-Ctarget-feature={}", features.join(",")).into());
+    }
+    if opts.linking == Some(Linking::PreferDynamic) {
+        args.push("-Cprefer-dynamic".into());
+        args.push("-Crpath".into());
+    }
+    if let Some(model) = opts.relocation_model() {
+        args.push(format!("-Crelocation-model={}", model).into());
+    }
+    if let Some(n) = opts.codegen_units {
+        args.push(format!("-Ccodegen-units={}", n).into());
+    }
+    if opts.deterministic {
+        args.push(remap_path_prefix("--remap-path-prefix=", src));
+        args.push("-Cmetadata=dispatch-test".into());
+    }
+    if opts.pgo {
+        let mut arg = OsString::from("-Cprofile-use=");
+        arg.push(pgo::profile_path(src));
+        args.push(arg);
+    }
+    if opts.mitigated() {
+        // The standard library isn't rebuilt with retpolines. Only the
+        // case's own calls are measured, so the mismatch doesn't matter.
+        args.push("-Zretpoline".into());
+        args.push("-Cunsafe-allow-abi-mismatch=retpoline".into());
+    }
+
+    args
+}
+
+/// A compiler flag mapping the source's directory to `.`, so builds in
+/// different places embed the same paths
+fn remap_path_prefix(flag: &str, src: &Path) -> OsString {
+    let dir = src.parent().expect("src paths have parents");
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_owned());
+    let mut arg = OsString::from(flag);
+    arg.push(dir);
+    arg.push("=.");
+    arg
+}
+
+/// Times a local launch, with the binary's stdout thrown away unless
+/// `capture` keeps it
+fn run_case(bin: &Path, mem_limit: Option<u64>,
+            capture: bool) -> Result<(Duration, Option<String>)> {
+    let start = Instant::now();
+
+    let mut cmd = Command::new(bin);
+    cmd.stdout(if capture { Stdio::piped() } else { Stdio::null() });
+    let output = memlimit::output(&mut cmd, mem_limit)?;
 
     if !output.status.success() {
-        bail!("running nm failed");
+        bail!("{} failed with {}", bin.display(), output.status);
     }
 
-    let out_str = String::from_utf8_lossy(&output.stdout);
-    let lines = out_str.lines();
-    let method_count = lines.clone().filter(|s| s.contains("do_io_m")).count();
-    let fn_count = lines.filter(|s| s.contains("do_io_f")).count();
+    let end = Instant::now();
 
-    Ok((method_count, fn_count))
+    let stdout = Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        .filter(|_| capture);
+    Ok((end - start, stdout))
 }
 
 
+
@@ -0,0 +1,131 @@
+//! The manifest records which cases have been generated into an outdir,
+//! so the compile and run phases can discover them instead of being
+//! told the same ranges again.
+
+use anyhow::{Result, anyhow, bail};
+use atomic_blobject::AtomBlob;
+use std::path::{Path, PathBuf};
+
+use crate::{CaseConfig, GenOpts};
+
+pub static MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub cases: Vec<CaseEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CaseEntry {
+    pub num_types: u32,
+    pub num_fns: u32,
+    pub gen_opts: GenOpts,
+    #[serde(default)]
+    pub status: CaseStatus,
+    /// The dispatch-test command line that generated the case
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+/// How far through the pipeline a case has made it, so an interrupted
+/// sweep can be resumed.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Serialize, Deserialize)]
+pub enum CaseStatus {
+    #[default]
+    Generated,
+    Compiled,
+    Ran,
+}
+
+impl CaseEntry {
+    pub fn config(&self, outdir: &Path) -> CaseConfig {
+        CaseConfig {
+            outdir: outdir.to_owned(),
+            num_types: self.num_types,
+            num_fns: self.num_fns,
+        }
+    }
+}
+
+fn manifest_path(outdir: &Path) -> PathBuf {
+    outdir.join(MANIFEST_FILE)
+}
+
+fn open(outdir: &Path) -> Result<AtomBlob<Manifest>> {
+    // atomic_blobject's errors aren't Sync, so they can't go through `?`
+    AtomBlob::new(manifest_path(outdir))
+        .map_err(|e| anyhow!("opening manifest: {}", e))
+}
+
+pub fn record_case(config: &CaseConfig, opts: &GenOpts,
+                   command: String) -> Result<()> {
+    let mut blob = open(&config.outdir)?;
+    let mut manifest = blob.get_mut()
+        .map_err(|e| anyhow!("locking manifest: {}", e))?;
+
+    manifest.cases.retain(|c| {
+        (c.num_types, c.num_fns) != (config.num_types, config.num_fns)
+    });
+    manifest.cases.push(CaseEntry {
+        num_types: config.num_types,
+        num_fns: config.num_fns,
+        gen_opts: opts.clone(),
+        status: CaseStatus::Generated,
+        command: Some(command),
+    });
+    manifest.cases.sort_by_key(|c| (c.num_types, c.num_fns));
+
+    manifest.commit()
+        .map_err(|e| anyhow!("writing manifest: {}", e))?;
+
+    Ok(())
+}
+
+pub fn load(outdir: &Path) -> Result<Vec<CaseEntry>> {
+    if !manifest_path(outdir).exists() {
+        bail!("no manifest in {}; generate cases first with \
+               gen-one-case or gen-all-cases",
+              outdir.display());
+    }
+
+    let mut blob = open(outdir)?;
+    let manifest = blob.get()
+        .map_err(|e| anyhow!("reading manifest: {}", e))?;
+
+    Ok(manifest.cases.clone())
+}
+
+pub fn find_case(config: &CaseConfig) -> Result<CaseEntry> {
+    let cases = load(&config.outdir)?;
+    let case = cases.into_iter().find(|c| {
+        (c.num_types, c.num_fns) == (config.num_types, config.num_fns)
+    });
+
+    match case {
+        Some(case) => Ok(case),
+        None => bail!("case {} types / {} fns has not been generated in {}",
+                      config.num_types, config.num_fns,
+                      config.outdir.display()),
+    }
+}
+
+pub fn set_status(config: &CaseConfig, status: CaseStatus) -> Result<()> {
+    let mut blob = open(&config.outdir)?;
+    let mut manifest = blob.get_mut()
+        .map_err(|e| anyhow!("locking manifest: {}", e))?;
+
+    let entry = manifest.cases.iter_mut().find(|c| {
+        (c.num_types, c.num_fns) == (config.num_types, config.num_fns)
+    });
+
+    // Cases generated by hand outside of the manifest are left alone
+    if let Some(entry) = entry {
+        entry.status = status;
+    }
+
+    manifest.commit()
+        .map_err(|e| anyhow!("writing manifest: {}", e))?;
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! Compile option matrices, for studying how flags interact in one
+//! sweep. `compile-all-cases` takes lists of values for --opt-level,
+//! --lto and --codegen-units, like `--opt-level 0,3 --lto off,fat
+//! --codegen-units 1,16`. Every combination of the listed values is
+//! built into its own run directory beside the original, like
+//! `cgu-sweep`'s, and the original run lists them so that
+//! `run-all-cases`, `report` and `crossover` cover each one.
+
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueEnum};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{manifest, AxisFilter, CompileOpts, Lto, OptLevel};
+
+pub static MATRIX_FILE: &str = "matrix.json";
+
+static COMMAND: &str = "compile-all-cases";
+
+/// The values listed for each option the matrix can vary
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    opt_level: Vec<OptLevel>,
+    lto: Vec<Lto>,
+    codegen_units: Vec<u32>,
+}
+
+/// One combination's run, as listed in the original run's matrix file
+#[derive(Clone, Serialize, Deserialize)]
+struct Combination {
+    /// The values, like `O3-lto-fat-cgu16`
+    flags: String,
+    /// The run directory's name, beside the original
+    run: String,
+}
+
+/// One value of one of the matrix's options
+#[derive(Clone, Copy)]
+enum Setting {
+    OptLevel(OptLevel),
+    Lto(Lto),
+    CodegenUnits(u32),
+}
+
+impl Setting {
+    fn flag(self) -> String {
+        match self {
+            Setting::OptLevel(level) => format!("O{}", level),
+            Setting::Lto(lto) => {
+                format!("lto-{}", lto.to_possible_value().expect("no skipped values").get_name())
+            }
+            Setting::CodegenUnits(n) => format!("cgu{}", n),
+        }
+    }
+
+    fn apply(self, opts: &mut CompileOpts) {
+        match self {
+            Setting::OptLevel(level) => opts.opt_level = level,
+            Setting::Lto(lto) => opts.lto = Some(lto),
+            Setting::CodegenUnits(n) => opts.codegen_units = Some(n),
+        }
+    }
+}
+
+/// Lets compile-all-cases's --opt-level, --lto and --codegen-units
+/// take lists
+pub fn accept_lists(cmd: Command) -> Command {
+    fn list(arg: Arg) -> Arg {
+        let help = format!("{}. A comma-separated list compiles each value in a run of its own",
+                           arg.get_help().map(|h| h.to_string()).unwrap_or_default());
+        arg.value_delimiter(',').action(ArgAction::Append).help(help)
+    }
+    cmd.mut_subcommand(COMMAND, |sub| {
+        sub.mut_arg("opt_level", list)
+            .mut_arg("codegen_units", list)
+            .mut_arg("lto", list)
+    })
+}
+
+/// The lists given to compile-all-cases. The options parsed from the
+/// same matches take the first of each.
+pub fn lists(matches: &ArgMatches) -> Option<Matrix> {
+    let (_, sub) = matches.subcommand().filter(|&(name, _)| name == COMMAND)?;
+    let opt_level = sub.get_many::<OptLevel>("opt_level")
+        .map_or(vec![], |v| v.copied().collect());
+    let codegen_units = sub.get_many::<u32>("codegen_units")
+        .map_or(vec![], |v| v.copied().collect());
+    let lto = sub.get_many::<Lto>("lto")
+        .map_or(vec![], |v| v.copied().collect());
+
+    Some(Matrix { opt_level, lto, codegen_units })
+}
+
+impl Matrix {
+    /// The matrix, if any option was given more than one value
+    pub fn varied(self) -> Option<Matrix> {
+        let varies = [self.opt_level.len(), self.lto.len(), self.codegen_units.len()]
+            .iter().any(|&n| n > 1);
+
+        if varies { Some(self) } else { None }
+    }
+
+    /// Every combination of the values over the shared options, with
+    /// their flags
+    pub fn expand(&self, base: &CompileOpts) -> Vec<(String, CompileOpts)> {
+        let axes: [Vec<Setting>; 3] = [
+            self.opt_level.iter().map(|&l| Setting::OptLevel(l)).collect(),
+            self.lto.iter().map(|&l| Setting::Lto(l)).collect(),
+            self.codegen_units.iter().map(|&n| Setting::CodegenUnits(n)).collect(),
+        ];
+
+        let mut combinations = vec![(vec![], base.clone())];
+        for axis in axes.iter().filter(|a| a.len() > 1) {
+            combinations = combinations.into_iter()
+                .flat_map(|(flags, opts)| axis.iter().map(move |&setting| {
+                    let mut flags: Vec<String> = flags.clone();
+                    flags.push(setting.flag());
+                    let mut opts = opts.clone();
+                    setting.apply(&mut opts);
+                    (flags, opts)
+                }))
+                .collect();
+        }
+
+        combinations.into_iter()
+            .map(|(flags, opts)| (flags.join("-"), opts))
+            .collect()
+    }
+}
+
+/// Compiles each of the matrix's combinations into its own run,
+/// generating the original run's cases there first
+pub fn compile(outdir: &Path, matrix: &Matrix, opts: &CompileOpts, filter: &AxisFilter,
+               mut compile_all: impl FnMut(&Path, CompileOpts) -> Result<()>) -> Result<()> {
+    let label = outdir.file_name().expect("run dirs have names").to_string_lossy();
+    let combinations: Vec<_> = matrix.expand(opts).into_iter()
+        .map(|(flags, opts)| {
+            let run = format!("{}-{}", label, flags);
+            (Combination { flags, run }, opts)
+        })
+        .collect();
+    let listed: Vec<&Combination> = combinations.iter().map(|(c, _)| c).collect();
+    fs::write(outdir.join(MATRIX_FILE), serde_json::to_string_pretty(&listed)?)?;
+
+    let cases = manifest::load(outdir)?;
+    for (combination, opts) in combinations {
+        println!("flags: {}", combination.flags);
+        let dir = outdir.with_file_name(&combination.run);
+        fs::create_dir_all(&dir)?;
+        let generated = manifest::load(&dir).unwrap_or_default();
+        for case in cases.iter().filter(|c| filter.matches(c.num_types, c.num_fns)) {
+            // Resumed sweeps keep what the combination already has
+            if generated.iter().any(|g| (g.num_types, g.num_fns) == (case.num_types, case.num_fns)) {
+                continue;
+            }
+            crate::gen_one_case(case.config(&dir), case.gen_opts.clone())?;
+        }
+        compile_all(&dir, opts)?;
+    }
+
+    Ok(())
+}
+
+/// Forgets the run's matrix, when its cases are compiled on their own
+/// again, so later phases cover them rather than the combinations
+pub fn clear(outdir: &Path) -> Result<()> {
+    match fs::remove_file(outdir.join(MATRIX_FILE)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The run's directories to cover, with their flags: each combination's
+/// if it was compiled as a matrix, otherwise its own
+pub fn runs(outdir: &Path) -> Result<Vec<(Option<String>, PathBuf)>> {
+    let path = outdir.join(MATRIX_FILE);
+    if !path.exists() {
+        return Ok(vec![(None, outdir.to_owned())]);
+    }
+    let combinations: Vec<Combination> = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    Ok(combinations.into_iter()
+       .map(|c| (Some(c.flags), outdir.with_file_name(c.run)))
+       .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{accept_lists, lists};
+    use crate::{Cmd, Lto, Options};
+    use clap::{CommandFactory, FromArgMatches};
+
+    #[test]
+    fn expands_the_cross_product() {
+        let parse = |args: &[&str]| {
+            let matches = accept_lists(Options::command())
+                .try_get_matches_from(["dispatch-test", "compile-all-cases"].iter().chain(args))
+                .unwrap();
+            let matrix = lists(&matches).unwrap().varied();
+            match Options::from_arg_matches(&matches).unwrap().cmd {
+                Cmd::CompileAllCases { opts, .. } => (opts, matrix),
+                _ => unreachable!(),
+            }
+        };
+        let (base, matrix) = parse(&["--opt-level", "0,3", "--lto", "off,fat",
+                                     "--codegen-units", "1,16"]);
+        let combinations = matrix.unwrap().expand(&base);
+        let flags: Vec<&str> = combinations.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(flags.len(), 8);
+        assert_eq!(flags[0], "O0-lto-off-cgu1");
+        assert_eq!(flags[7], "O3-lto-fat-cgu16");
+        assert_eq!(combinations[0].1.lto, Some(Lto::Off));
+        assert_eq!(combinations[7].1.codegen_units, Some(16));
+
+        let (base, matrix) = parse(&["--opt-level", "3", "--lto", "off,fat"]);
+        let combinations = matrix.unwrap().expand(&base);
+        assert_eq!(combinations[1].0, "lto-fat");
+        assert_eq!(combinations[1].1.opt_level, base.opt_level);
+        let (base, matrix) = parse(&["--lto", "thin"]);
+        assert!(matrix.is_none());
+        assert_eq!(base.lto, Some(Lto::Thin));
+    }
+}
@@ -0,0 +1,153 @@
+//! Capping the memory of the compilers and case binaries a sweep runs
+//! locally, so one pathological case can't exhaust the machine. The cap
+//! is on address space (RLIMIT_AS), which needs no privileges, so it
+//! counts reservations as well as resident memory and should be set
+//! generously.
+
+use anyhow::Result;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::thread;
+
+/// A process was stopped by the limit, recorded as its own outcome
+/// rather than as a compile or run failure
+#[derive(Debug)]
+pub struct MemLimitExceeded {
+    pub limit: u64,
+}
+
+impl fmt::Display for MemLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exceeded --mem-limit of {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for MemLimitExceeded {}
+
+/// Limits the address space of the command's process
+pub fn apply(cmd: &mut Command, limit: Option<u64>) {
+    if let Some(limit) = limit {
+        let rlimit = libc::rlimit {
+            rlim_cur: limit as libc::rlim_t,
+            rlim_max: limit as libc::rlim_t,
+        };
+        // Only async-signal-safe calls between fork and exec
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::setrlimit(libc::RLIMIT_AS, &rlimit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// What allocation failures print: Rust's alloc error handler, LLVM's
+/// and the dynamic loader's when it can't map a library
+const ALLOC_FAILURES: &[&str] = &[
+    "memory allocation of",
+    "failed to allocate",
+    "out of memory",
+    "Cannot allocate memory",
+];
+
+/// Runs the command under the limit and returns its output, or
+/// `MemLimitExceeded` if it failed for lack of memory. Under a limit
+/// stderr is captured to look for allocation failures, and passed
+/// through; stdout is captured only if the command pipes it.
+pub fn output(cmd: &mut Command, limit: Option<u64>) -> Result<Output> {
+    apply(cmd, limit);
+    if limit.is_some() {
+        cmd.stderr(Stdio::piped());
+    }
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().map(|mut out| thread::spawn(move || {
+        let mut buf = vec![];
+        out.read_to_end(&mut buf).map(|_| buf)
+    }));
+    let stderr = child.stderr.take().map(|mut err| thread::spawn(move || {
+        let mut buf = vec![];
+        let mut chunk = [0; 4096];
+        loop {
+            let n = err.read(&mut chunk)?;
+            if n == 0 {
+                return Ok::<_, io::Error>(buf);
+            }
+            io::stderr().write_all(&chunk[..n])?;
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    }));
+    let (status, maxrss) = wait(child.id())?;
+    let join = |t: Option<thread::JoinHandle<io::Result<Vec<u8>>>>| {
+        t.map_or(Ok(vec![]), |t| t.join().expect("pipe reader panicked"))
+    };
+    let output = Output { status, stdout: join(stdout)?, stderr: join(stderr)? };
+
+    if !status.success() {
+        check(status, maxrss, &output.stderr, limit)?;
+    }
+
+    Ok(output)
+}
+
+/// Waits for the process, returning its status and peak resident set
+/// in bytes
+fn wait(pid: u32) -> Result<(ExitStatus, u64)> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        let r = unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut usage) };
+        if r != -1 {
+            break;
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e.into());
+        }
+    }
+
+    // Linux reports kilobytes
+    Ok((ExitStatus::from_raw(status), usage.ru_maxrss as u64 * 1024))
+}
+
+/// Whether a process that failed under the limit was stopped by it.
+/// Signals and exit codes alone don't say: an ICE aborts and a bad
+/// program faults with or without a limit. Only a process that printed
+/// an allocation failure, or whose resident set came near the limit,
+/// counts; anything else is an ordinary failure.
+pub fn check(status: ExitStatus, maxrss: u64, stderr: &[u8],
+             limit: Option<u64>) -> Result<()> {
+    let limit = match limit {
+        Some(limit) if !status.success() => limit,
+        _ => return Ok(()),
+    };
+    let stderr = String::from_utf8_lossy(stderr);
+    let near = maxrss >= limit / 10 * 9;
+    if near || ALLOC_FAILURES.iter().any(|s| stderr.contains(s)) {
+        return Err(MemLimitExceeded { limit }.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::check;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    #[test]
+    fn only_evidence_is_over_the_limit() {
+        let limit = Some(1 << 30);
+        let segv = ExitStatus::from_raw(libc::SIGSEGV);
+        assert!(check(segv, 50 << 20, b"", limit).is_ok());
+        assert!(check(segv, 1 << 30, b"", limit).is_err());
+        let abort = ExitStatus::from_raw(libc::SIGABRT);
+        assert!(check(abort, 50 << 20, b"error: internal compiler error", limit).is_ok());
+        assert!(check(abort, 50 << 20, b"memory allocation of 4096 bytes failed", limit).is_err());
+        assert!(check(segv, 1 << 30, b"", None).is_ok());
+    }
+}
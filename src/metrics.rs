@@ -0,0 +1,162 @@
+//! Serving a sweep's progress and its latest case's measurements in
+//! Prometheus's text format under `--metrics-listen`, so long jobs on
+//! shared machines can be watched from existing dashboards. The server
+//! lives as long as the process, answering one scrape at a time.
+
+use anyhow::{Result, anyhow};
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::Phase;
+use crate::results::{self, CaseResults};
+use crate::{interrupt, CaseConfig, Dispatch};
+
+struct Progress {
+    command: String,
+    started: Instant,
+    total: usize,
+    done: usize,
+    failed: usize,
+    latest: Option<(Phase, CaseResults)>,
+}
+
+static PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+
+/// A scrape that doesn't send its request in this long is dropped
+static READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts serving `/metrics` on `addr` for the subcommand being run
+pub fn listen(addr: &str, command: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| anyhow!("can't listen on {}: {}", addr, e))?;
+    *PROGRESS.lock().expect("poisoned") = Some(Progress {
+        command: command.to_string(),
+        started: Instant::now(),
+        total: 0,
+        done: 0,
+        failed: 0,
+        latest: None,
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A broken scrape only affects the scraper
+            let _ = serve(stream);
+        }
+    });
+
+    Ok(())
+}
+
+/// Starts counting a sweep of `total` cases
+pub fn sweep(total: usize) {
+    if let Some(ref mut p) = *PROGRESS.lock().expect("poisoned") {
+        p.total = total;
+        p.done = 0;
+        p.failed = 0;
+    }
+}
+
+/// Counts a case a sweep has finished a phase of, keeping its results.
+/// A case cut short by Ctrl-C isn't finished.
+pub fn case(config: &CaseConfig, phase: Phase, result: &Result<()>) {
+    if result.is_err() && interrupt::interrupted() {
+        return;
+    }
+    let mut progress = PROGRESS.lock().expect("poisoned");
+    let p = match *progress {
+        Some(ref mut p) => p,
+        None => return,
+    };
+    p.done += 1;
+    if result.is_err() {
+        p.failed += 1;
+    }
+    if let Ok(Some(results)) = results::find_case(config) {
+        p.latest = Some((phase, results));
+    }
+}
+
+fn serve(mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let response = if path == "/metrics" {
+        let body = render();
+        format!("HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/plain; version=0.0.4\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}", body.len(), body)
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string()
+    };
+    stream.write_all(response.as_bytes())?;
+
+    Ok(())
+}
+
+fn render() -> String {
+    let progress = PROGRESS.lock().expect("poisoned");
+    let p = match *progress {
+        Some(ref p) => p,
+        None => return String::new(),
+    };
+
+    let mut out = String::new();
+    let mut metric = |name: &str, help: &str, samples: &[(String, f64)]| {
+        let _ = writeln!(out, "# HELP dispatch_test_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE dispatch_test_{} gauge", name);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "dispatch_test_{}{{{}}} {}", name, labels, value);
+        }
+    };
+
+    let command = format!("command=\"{}\"", p.command);
+    metric("cases_total", "Cases in the sweep",
+           &[(command.clone(), p.total as f64)]);
+    metric("cases_done", "Cases the sweep has finished, including failures",
+           &[(command.clone(), p.done as f64)]);
+    metric("cases_failed", "Cases that failed",
+           &[(command.clone(), p.failed as f64)]);
+    metric("elapsed_seconds", "Time since the command started",
+           &[(command, p.started.elapsed().as_secs_f64())]);
+
+    if let Some((phase, ref case)) = p.latest {
+        let labels = |dispatch: Dispatch| {
+            format!("phase=\"{}\",num_types=\"{}\",num_fns=\"{}\",variant=\"{}\"",
+                    phase, case.num_types, case.num_fns, dispatch)
+        };
+        let per_variant = |value: &dyn Fn(Dispatch) -> Option<u64>| {
+            case.variants().into_iter()
+                .filter_map(|d| Some((labels(d), value(d)? as f64)))
+                .collect::<Vec<_>>()
+        };
+        let compile = case.compile.as_ref();
+        let run = case.run.as_ref();
+        metric("latest_compile_time_ms", "The latest case's compile time",
+               &per_variant(&|d| Some(compile?.variant(d)?.compile_time_ms)));
+        metric("latest_bin_size_bytes", "The latest case's binary size",
+               &per_variant(&|d| Some(compile?.variant(d)?.bin_size)));
+        metric("latest_run_time_ms", "The latest case's run time",
+               &per_variant(&|d| Some(run?.variant(d)?.run_time_ms)));
+        metric("latest_instructions", "The latest case's instruction count",
+               &per_variant(&|d| run?.variant(d)?.instructions));
+    }
+
+    out
+}
@@ -0,0 +1,61 @@
+//! Counting the items rustc monomorphizes for a case, with
+//! `-Zprint-mono-items`, for `--mono-items`. This is the duplication
+//! the compiler actually generated, before LLVM and the linker remove
+//! or merge any of it, which nm can only infer from what's left.
+
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::CompileOpts;
+
+/// The mono items rustc collected for one program
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonoItems {
+    /// Every item, including the standard library's
+    pub items: usize,
+    pub fns: usize,
+    /// The generated methods and fns, counting each instantiation
+    pub generated: usize,
+}
+
+/// Builds the source to a scratch object with the item collector's
+/// output turned on. This build is not timed.
+pub fn count(src: &Path, out: &Path, opts: &CompileOpts) -> Result<MonoItems> {
+    let obj = out.with_extension("mono.o");
+    let mut args = crate::rustc_args(src, &obj, "obj", opts);
+    args.push("-Zprint-mono-items".into());
+
+    let output = Command::new("rustc")
+        .args(&args)
+        .output()?;
+    if obj.exists() {
+        fs::remove_file(&obj)?;
+    }
+
+    if !output.status.success() {
+        bail!("rustc -Zprint-mono-items failed on {}: {}", src.display(),
+              String::from_utf8_lossy(&output.stderr));
+    }
+
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses lines like `MONO_ITEM fn do_io_f0::<T1> @@ cgu[Internal]`
+fn parse(out: &str) -> MonoItems {
+    let mut items = MonoItems { items: 0, fns: 0, generated: 0 };
+
+    for item in out.lines().filter_map(|l| l.strip_prefix("MONO_ITEM ")) {
+        let item = item.split(" @@ ").next().unwrap_or(item);
+        items.items += 1;
+        if let Some(name) = item.strip_prefix("fn ") {
+            items.fns += 1;
+            if name.contains("do_io_") {
+                items.generated += 1;
+            }
+        }
+    }
+
+    items
+}
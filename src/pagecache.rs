@@ -0,0 +1,25 @@
+//! Evicting a case binary from the page cache before each launch under
+//! `--cold-cache`, so its run time includes reading it from disk the way
+//! a short-lived process's first launch would. Only the binary's own
+//! pages are dropped; shared libraries other processes have mapped stay
+//! cached, as they would on a real system.
+
+use anyhow::{Result, anyhow};
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Asks the kernel to drop the file's cached pages
+pub fn evict(path: &Path) -> Result<()> {
+    let file = File::open(path)?;
+    // Dirty pages aren't dropped, and the linker just wrote these
+    file.sync_data()?;
+    let err = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if err != 0 {
+        return Err(anyhow!("can't evict {} from the page cache: {}",
+                           path.display(), io::Error::from_raw_os_error(err)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! Profile-guided builds for `--pgo`. Each variant is first built with
+//! `-Cprofile-generate` and run once to train it, and the merged profile
+//! goes to the builds that are measured with `-Cprofile-use`, so LLVM
+//! knows which types the calls reach and may speculatively devirtualize
+//! the dynamic ones. Training isn't timed.
+
+use anyhow::{Result, bail};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::CompileOpts;
+
+/// The merged profile the measured builds of a source use
+pub fn profile_path(src: &Path) -> PathBuf {
+    src.with_extension("profdata")
+}
+
+/// Builds the source instrumented, runs it and merges its profile
+pub fn train(src: &Path, opts: &CompileOpts) -> Result<()> {
+    let dir = src.parent().expect("src paths have parents");
+    let raw = src.with_extension("profraw");
+    let bin = src.with_extension("pgo.bin");
+    if raw.exists() {
+        fs::remove_dir_all(&raw)?;
+    }
+
+    let mut opts = opts.clone();
+    opts.pgo = false;
+    opts.incremental = false;
+    let mut args = crate::rustc_args(src, &bin, "link", &opts);
+    let mut arg = OsString::from("-Cprofile-generate=");
+    arg.push(&raw);
+    args.push(arg);
+    crate::run_tool("rustc", dir, &args, &opts)?;
+
+    let status = Command::new(&bin).output()?.status;
+    if !status.success() {
+        bail!("the instrumented {} failed with {}", bin.display(), status);
+    }
+
+    let profdata = llvm_profdata()?;
+    let output = Command::new(&profdata)
+        .arg("merge")
+        .arg("-o").arg(profile_path(src))
+        .arg(&raw)
+        .output()?;
+    if !output.status.success() {
+        bail!("{} couldn't merge the profile of {}; its LLVM must be rustc's \
+               (`rustup component add llvm-tools` installs one that is): {}",
+              profdata.display(), bin.display(),
+              String::from_utf8_lossy(&output.stderr).trim());
+    }
+    fs::remove_dir_all(&raw)?;
+    fs::remove_file(&bin)?;
+
+    Ok(())
+}
+
+/// The toolchain's llvm-profdata, from the llvm-tools component, or
+/// else the one on PATH, which only works if its LLVM is rustc's
+pub fn llvm_profdata() -> Result<PathBuf> {
+    let output = Command::new("rustc").args(["--print", "sysroot"]).output()?;
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let output = Command::new("rustc").arg("-vV").output()?;
+    let host = String::from_utf8_lossy(&output.stdout).lines()
+        .find_map(|line| line.strip_prefix("host: ").map(str::to_string));
+    let bundled = host.map(|host| {
+        sysroot.join("lib").join("rustlib").join(host).join("bin").join("llvm-profdata")
+    });
+
+    Ok(match bundled {
+        Some(path) if path.exists() => path,
+        _ => PathBuf::from("llvm-profdata"),
+    })
+}
@@ -0,0 +1,381 @@
+//! Built-in experiments, each a sweep with the gen, compile and run
+//! options that make its comparison meaningful, for a first result
+//! without knowing which axes matter.
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use crate::results;
+use crate::{manifest, CaseConfig, Cmd, Dispatch, Options};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Static against dynamic dispatch at -O3 over a grid of types and
+    /// fns, with repeated runs
+    VtableVsGeneric,
+    /// Dynamic dispatch through vtables against static dispatch through
+    /// a match on an enum of the types, at -O3 over a grid of types and
+    /// fns, with repeated runs
+    VtableVsEnum,
+    /// How compile times grow with the number of types, from repeated
+    /// builds
+    CompileTimeScaling,
+    /// Binary sizes built the way size-constrained targets are, at
+    /// opt-level z with fat LTO
+    SizeEmbedded,
+    /// The same cases built for glibc and, fully statically, for musl,
+    /// in two runs, comparing sizes and run times. Needs the musl
+    /// target installed
+    MuslVsGlibc,
+    /// The monomorphization pattern of serde-heavy crates: many small
+    /// fns generic over a few implementors, as serialize impls are over
+    /// formats, each calling a generic method, as serialize_field is
+    /// over field types, against the same through &dyn with the
+    /// argument type-erased. Repeated builds and runs
+    SerdeStyle,
+    /// Event callbacks: handlers as an enum against handlers behind
+    /// &dyn Io, and, in a second run, against boxed Fn closures, each
+    /// fn delivering one kind of event, comparing sizes and run times
+    EventCallbacks,
+    /// An entity-component system: systems running over each type's
+    /// array of components, generic over the type against over slices
+    /// of boxed trait objects, at -O3 with repeated runs
+    Ecs,
+    /// A bytecode interpreter's opcode dispatch, each type an opcode:
+    /// through a match, a table of fn pointers and trait objects, in
+    /// three runs, reporting each one's time per dispatch against the
+    /// static program's direct calls
+    Interpreter,
+    /// A state machine with the types as its states, stepped as an enum
+    /// against as Box<dyn State>, from repeated builds and runs, for how
+    /// run and compile times grow with the number of states
+    StateMachine,
+    /// Profile-guided builds against plain ones at each --skew, in six
+    /// runs, reporting how much of the dynamic variant's run-time
+    /// penalty PGO recovers, as speculative devirtualization would.
+    /// Needs llvm-profdata from the llvm-tools component
+    PgoBySkew,
+}
+
+/// The run a phase goes in, as a suffix to the preset's run's name, or
+/// "" for the preset's own run; the subcommand; and its arguments
+type Phase = (&'static str, &'static str, &'static [&'static str]);
+
+static MUSL_GRID: &[&str] = &["--types", "1..32:x2", "--fns", "1..8:x2"];
+
+/// The gen options of each of the PGO preset's pairs of runs, after
+/// their run suffixes without and with --pgo
+static PGO_SKEWS: &[(&str, &str, &[&str])] = &[
+    ("", "pgo", &["--types", "2..16:x2", "--fns", "4"]),
+    ("zipf", "zipf-pgo", &["--types", "2..16:x2", "--fns", "4", "--skew", "zipf"]),
+    ("single", "single-pgo", &["--types", "2..16:x2", "--fns", "4", "--skew", "single"]),
+];
+
+impl Preset {
+    /// The subcommands the preset runs, with their arguments
+    fn phases(self) -> Vec<Phase> {
+        match self {
+            Preset::VtableVsGeneric => vec![
+                ("", "gen-all-cases", &["--types", "1..64:x2", "--fns", "1..16:x2"]),
+                ("", "compile-all-cases", &["--opt-level", "3"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::VtableVsEnum => vec![
+                ("", "gen-all-cases", &["--types", "1..64:x2", "--fns", "1..16:x2",
+                                        "--static-via", "enum"]),
+                ("", "compile-all-cases", &["--opt-level", "3"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::CompileTimeScaling => vec![
+                ("", "gen-all-cases", &["--types", "1..256:x2", "--fns", "4"]),
+                ("", "compile-all-cases", &["--opt-level", "3", "--measure-compile",
+                                            "--repeat", "3"]),
+                ("", "run-all-cases", &[]),
+            ],
+            Preset::SizeEmbedded => vec![
+                ("", "gen-all-cases", &["--types", "1..32:x2", "--fns", "1..8:x2"]),
+                ("", "compile-all-cases", &["--opt-level", "z", "--lto", "fat"]),
+                ("", "run-all-cases", &[]),
+            ],
+            Preset::MuslVsGlibc => vec![
+                ("", "gen-all-cases", MUSL_GRID),
+                ("", "compile-all-cases", &["--opt-level", "3",
+                                            "--target", "x86_64-unknown-linux-gnu"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+                ("musl", "gen-all-cases", MUSL_GRID),
+                ("musl", "compile-all-cases", &["--opt-level", "3",
+                                                "--target", "x86_64-unknown-linux-musl",
+                                                "--linking", "static"]),
+                ("musl", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::SerdeStyle => vec![
+                ("", "gen-all-cases", &["--types", "1..4", "--fns", "16..1024:x4",
+                                        "--generic-method"]),
+                ("", "compile-all-cases", &["--opt-level", "3", "--measure-compile",
+                                            "--repeat", "3"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::EventCallbacks => vec![
+                ("", "gen-all-cases", &["--types", "1..32:x2", "--fns", "1..8:x2",
+                                        "--static-via", "enum"]),
+                ("", "compile-all-cases", &["--opt-level", "3"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+                ("fn", "gen-all-cases", &["--types", "1..32:x2", "--fns", "1..8:x2",
+                                          "--static-via", "enum",
+                                          "--dynamic-via", "closure"]),
+                ("fn", "compile-all-cases", &["--opt-level", "3"]),
+                ("fn", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::Ecs => vec![
+                ("", "gen-all-cases", &["--types", "1..8:x2", "--fns", "1..8:x2",
+                                        "--components", "64"]),
+                ("", "compile-all-cases", &["--opt-level", "3"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::Interpreter => vec![
+                ("", "gen-all-cases", &["--types", "2..64:x2", "--fns", "4",
+                                        "--dynamic-via", "jump-table"]),
+                ("", "compile-all-cases", &["--opt-level", "3"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+                ("fn-table", "gen-all-cases", &["--types", "2..64:x2", "--fns", "4",
+                                                "--dynamic-via", "fn-table"]),
+                ("fn-table", "compile-all-cases", &["--opt-level", "3"]),
+                ("fn-table", "run-all-cases", &["--repeat", "5"]),
+                ("dyn", "gen-all-cases", &["--types", "2..64:x2", "--fns", "4"]),
+                ("dyn", "compile-all-cases", &["--opt-level", "3"]),
+                ("dyn", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::StateMachine => vec![
+                ("", "gen-all-cases", &["--types", "2..256:x2", "--fns", "1",
+                                        "--state-machine"]),
+                ("", "compile-all-cases", &["--opt-level", "3", "--measure-compile",
+                                            "--repeat", "3"]),
+                ("", "run-all-cases", &["--repeat", "5"]),
+            ],
+            Preset::PgoBySkew => PGO_SKEWS.iter()
+                .flat_map(|&(plain, pgo, gen)| vec![
+                    (plain, "gen-all-cases", gen),
+                    (plain, "compile-all-cases", &["--opt-level", "3"][..]),
+                    (plain, "run-all-cases", &["--repeat", "5"][..]),
+                    (pgo, "gen-all-cases", gen),
+                    (pgo, "compile-all-cases", &["--opt-level", "3", "--pgo"][..]),
+                    (pgo, "run-all-cases", &["--repeat", "5"][..]),
+                ])
+                .collect(),
+        }
+    }
+
+    /// The phases as `dispatch-test` command lines
+    pub fn commands(self) -> Vec<String> {
+        self.phases().iter()
+            .map(|(run, phase, args)| {
+                let run_name = if run.is_empty() {
+                    String::new()
+                } else {
+                    format!(" --run-name <run>-{}", run)
+                };
+                format!("dispatch-test{} {} {}", run_name, phase, args.join(" "))
+                    .trim_end().to_string()
+            })
+            .collect()
+    }
+
+    fn cmds(self) -> Vec<Cmd> {
+        self.phases().iter()
+            .map(|(_, phase, args)| {
+                let argv = iter::once("dispatch-test").chain(iter::once(*phase))
+                    .chain(args.iter().copied());
+                Options::try_parse_from(argv)
+                    .expect("presets are valid command lines")
+                    .cmd
+            })
+            .collect()
+    }
+}
+
+/// Runs each phase of the preset into the run directory, or a sibling
+/// of it, then compares the runs if there's more than one
+pub fn run(outdir: &Path, preset: Preset) -> Result<()> {
+    let label = outdir.file_name().expect("run dirs have names").to_string_lossy();
+    let mut siblings: Vec<PathBuf> = vec![];
+    for ((run, _, _), (command, cmd)) in preset.phases().iter()
+        .zip(preset.commands().iter().zip(preset.cmds()))
+    {
+        let dir = if run.is_empty() {
+            outdir.to_owned()
+        } else {
+            outdir.with_file_name(format!("{}-{}", label, run))
+        };
+        if !run.is_empty() && !siblings.contains(&dir) {
+            crate::fresh_derived_run(&dir, outdir)?;
+            siblings.push(dir.clone());
+        }
+        println!("preset {}: {}", preset, command.replace("<run>", &label));
+        crate::run_cmd(cmd, &dir)?;
+    }
+
+    println!();
+    if siblings.is_empty() {
+        println!("preset {} finished; see `dispatch-test report --geomean`", preset);
+    } else if preset == Preset::Interpreter {
+        println!("preset {} finished", preset);
+        println!();
+        dispatch_costs(outdir, &siblings)?;
+    } else if preset == Preset::PgoBySkew {
+        println!("preset {} finished", preset);
+        println!();
+        pgo_recovery(outdir, &siblings)?;
+    } else {
+        println!("preset {} finished", preset);
+        for sibling in &siblings {
+            println!();
+            compare(outdir, sibling)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Each case's binary sizes and run times in two runs, side by side
+fn compare(a: &Path, b: &Path) -> Result<()> {
+    let name = |dir: &Path| {
+        dir.file_name().expect("run dirs have names").to_string_lossy().into_owned()
+    };
+    let (a_name, b_name) = (name(a), name(b));
+    let b_cases = results::load(b)?;
+
+    println!("{:<10}{:<10}{:>14}{:>14}{:>10}{:>10}", "case", "variant",
+             "size", "size", "run-ms", "run-ms");
+    println!("{:<20}{:>14}{:>14}{:>10}{:>10}", "", a_name, b_name, a_name, b_name);
+    for case in results::load(a)? {
+        let other = b_cases.iter()
+            .find(|c| (c.num_types, c.num_fns) == (case.num_types, case.num_fns));
+        let other = match other {
+            Some(other) => other,
+            None => continue,
+        };
+        for dispatch in case.variants() {
+            let size = |c: &results::CaseResults| {
+                c.compile.as_ref().and_then(|c| c.variant(dispatch))
+                    .map_or("-".to_string(), |c| c.bin_size.to_string())
+            };
+            let run_ms = |c: &results::CaseResults| {
+                c.run.as_ref().and_then(|r| r.variant(dispatch))
+                    .map_or("-".to_string(), |r| r.run_time_ms.to_string())
+            };
+            println!("{:<10}{:<10}{:>14}{:>14}{:>10}{:>10}",
+                     format!("{}-{}", case.num_types, case.num_fns), dispatch.to_string(),
+                     size(&case), size(other), run_ms(&case), run_ms(other));
+        }
+    }
+
+    Ok(())
+}
+
+/// Each case's time per call in nanoseconds: the static program's in
+/// the first run, as direct calls, then each run's dynamic program's
+fn dispatch_costs(first: &Path, others: &[PathBuf]) -> Result<()> {
+    let runs: Vec<&Path> = iter::once(first).chain(others.iter().map(|p| p.as_path())).collect();
+    let loaded = runs.iter().map(|dir| results::load(dir)).collect::<Result<Vec<_>>>()?;
+
+    print!("{:<10}{:>12}", "case", "direct");
+    for dir in &runs {
+        print!("{:>16}", dir.file_name().expect("run dirs have names").to_string_lossy());
+    }
+    println!();
+    for case in &loaded[0] {
+        let config = CaseConfig {
+            outdir: first.to_owned(),
+            num_types: case.num_types,
+            num_fns: case.num_fns,
+        };
+        let gen_opts = manifest::find_case(&config)?.gen_opts;
+        let calls = crate::test_loops(&gen_opts) as f64
+            * (case.num_types * case.num_fns.max(1)) as f64;
+        let ns = |c: Option<&results::CaseResults>, dispatch: Dispatch| {
+            let run = c.and_then(|c| c.run.as_ref()).and_then(|r| r.variant(dispatch));
+            let ms = run.map(|r| r.run_time_stats.as_ref()
+                             .map_or(r.run_time_ms as f64, |s| s.mean));
+            match ms {
+                Some(ms) if calls > 0.0 => format!("{:.2}", ms * 1e6 / calls),
+                _ => "-".to_string(),
+            }
+        };
+        print!("{:<10}{:>12}", format!("{}-{}", case.num_types, case.num_fns),
+               ns(Some(case), Dispatch::Static));
+        for cases in &loaded {
+            let other = cases.iter()
+                .find(|c| (c.num_types, c.num_fns) == (case.num_types, case.num_fns));
+            print!("{:>16}", ns(other, Dispatch::Dynamic));
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// For each pair of runs without and with --pgo, in the order of
+/// PGO_SKEWS, each case's dynamic/static run-time ratio in both and the
+/// share of the penalty PGO took away
+fn pgo_recovery(first: &Path, others: &[PathBuf]) -> Result<()> {
+    let runs: Vec<&Path> = iter::once(first).chain(others.iter().map(|p| p.as_path())).collect();
+
+    println!("{:<8}{:<10}{:>10}{:>10}{:>12}", "skew", "case", "penalty", "with-pgo",
+             "recovered");
+    for pair in runs.chunks(2) {
+        let (plain, pgo) = (results::load(pair[0])?, results::load(pair[1])?);
+        for case in &plain {
+            let config = CaseConfig {
+                outdir: pair[0].to_owned(),
+                num_types: case.num_types,
+                num_fns: case.num_fns,
+            };
+            let skew = manifest::find_case(&config)?.gen_opts.skew;
+            let other = pgo.iter()
+                .find(|c| (c.num_types, c.num_fns) == (case.num_types, case.num_fns));
+            let penalty = |c: Option<&results::CaseResults>| {
+                let run = c?.run.as_ref()?;
+                let ms = |d| {
+                    let r = run.variant(d)?;
+                    Some(r.run_time_stats.as_ref().map_or(r.run_time_ms as f64, |s| s.mean))
+                };
+                let (dynamic, stat) = (ms(Dispatch::Dynamic)?, ms(Dispatch::Static)?);
+                (stat > 0.0).then(|| dynamic / stat)
+            };
+            let (before, after) = (penalty(Some(case)), penalty(other));
+            let cell = |r: Option<f64>| r.map_or("-".to_string(), |r| format!("{:.3}", r));
+            let recovered = match (before, after) {
+                (Some(b), Some(a)) if b > 1.0 => format!("{:.0}%", 100.0 * (b - a) / (b - 1.0)),
+                _ => "-".to_string(),
+            };
+            println!("{:<8}{:<10}{:>10}{:>10}{:>12}", skew.to_string(),
+                     format!("{}-{}", case.num_types, case.num_fns),
+                     cell(before), cell(after), recovered);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Preset;
+    use crate::Cmd;
+    use clap::ValueEnum;
+
+    #[test]
+    fn presets_are_valid_command_lines() {
+        for preset in Preset::value_variants() {
+            for cmd in preset.cmds() {
+                match cmd {
+                    Cmd::GenAllCases { opts, .. } => opts.validate().unwrap(),
+                    Cmd::CompileAllCases { opts, .. } => opts.validate().unwrap(),
+                    Cmd::RunAllCases { .. } => {}
+                    other => panic!("unexpected phase {:?}", other),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Running cases compiled for another Linux architecture under
+//! qemu-user. Emulated run times say little about real hardware, so
+//! when qemu's instruction counting plugin is available the
+//! instruction count is measured too and reported as the headline.
+
+use anyhow::{Result, bail};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use crate::checksum;
+use crate::results::VariantRunResults;
+
+pub struct Qemu {
+    plugin: Option<PathBuf>,
+}
+
+static PLUGIN_DIRS: &[&str] = &[
+    "/usr/lib/qemu/plugins",
+    "/usr/lib64/qemu/plugins",
+    "/usr/local/lib/qemu/plugins",
+    "/usr/libexec/qemu/plugins",
+];
+
+impl Qemu {
+    pub fn new() -> Qemu {
+        let dirs = env::var_os("QEMU_PLUGIN_DIR")
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(PLUGIN_DIRS.iter().map(PathBuf::from));
+        let plugin = dirs.map(|d| d.join("libinsn.so")).find(|p| p.exists());
+
+        if plugin.is_none() {
+            println!("qemu's libinsn.so plugin was not found (set \
+                      QEMU_PLUGIN_DIR); reporting emulated time only");
+        }
+
+        Qemu { plugin }
+    }
+
+    /// Runs a binary under qemu, reading the sum it prints if
+    /// `read_checksum`
+    pub fn run_case(&self, bin: &Path, target: Option<&str>,
+                    read_checksum: bool) -> Result<VariantRunResults> {
+        let arch = match target {
+            Some(target) => target_arch(target),
+            None => env::consts::ARCH,
+        };
+        let qemu = format!("qemu-{}", qemu_arch(arch));
+        let log = bin.with_extension("insns.log");
+
+        let mut cmd = Command::new(&qemu);
+        if let Some(sysroot) = target.and_then(sysroot) {
+            cmd.arg("-L").arg(sysroot);
+        }
+        if let Some(ref plugin) = self.plugin {
+            cmd.arg("-plugin").arg(plugin)
+                .args(["-d", "plugin", "-D"]).arg(&log);
+        }
+
+        let start = Instant::now();
+
+        let stdout = if read_checksum { Stdio::piped() } else { Stdio::null() };
+        let output = match cmd.arg(bin).stdout(stdout).stderr(Stdio::inherit()).output() {
+            Ok(output) => output,
+            Err(e) => bail!("running {} failed: {}; is qemu-user installed?",
+                            qemu, e),
+        };
+
+        if !output.status.success() {
+            bail!("running case under {} failed", qemu);
+        }
+
+        let end = Instant::now();
+
+        let instructions = match self.plugin {
+            Some(_) => {
+                let count = parse_insns(&fs::read_to_string(&log)?);
+                fs::remove_file(&log)?;
+                if count.is_none() {
+                    bail!("no instruction count in qemu's plugin output");
+                }
+                count
+            }
+            None => None,
+        };
+
+        let sum = if read_checksum {
+            Some(checksum::read(bin, &String::from_utf8_lossy(&output.stdout))?)
+        } else {
+            None
+        };
+
+        Ok(VariantRunResults {
+            run_time_ms: (end - start).as_millis() as u64,
+            run_time_stats: None,
+            launch_time_stats: None,
+            instructions,
+            checksum: sum,
+        })
+    }
+}
+
+/// The architecture of a target triple, spelled like
+/// `std::env::consts::ARCH`
+pub fn target_arch(target: &str) -> &str {
+    let arch = target.split('-').next().unwrap_or(target);
+    match arch {
+        "i586" | "i686" => "x86",
+        a if a.starts_with("arm") || a.starts_with("thumb") => "arm",
+        a if a.starts_with("riscv64") => "riscv64",
+        a => a,
+    }
+}
+
+fn qemu_arch(arch: &str) -> &str {
+    match arch {
+        "x86" => "i386",
+        "powerpc" => "ppc",
+        "powerpc64" => "ppc64",
+        "powerpc64le" => "ppc64le",
+        a => a,
+    }
+}
+
+/// Where a cross toolchain installs the target's libc, for dynamically
+/// linked binaries. `QEMU_LD_PREFIX` overrides it, as it does for qemu.
+fn sysroot(target: &str) -> Option<PathBuf> {
+    if let Some(prefix) = env::var_os("QEMU_LD_PREFIX") {
+        return Some(PathBuf::from(prefix));
+    }
+
+    // aarch64-unknown-linux-gnu is installed as /usr/aarch64-linux-gnu
+    let mut parts: Vec<&str> = target.split('-').collect();
+    if parts.len() == 4 {
+        parts.remove(1);
+    }
+    if target_arch(target) == "arm" {
+        parts[0] = "arm";
+    }
+    let dir = Path::new("/usr").join(parts.join("-"));
+
+    Some(dir).filter(|d| d.is_dir())
+}
+
+fn parse_insns(log: &str) -> Option<u64> {
+    log.lines()
+        .rev()
+        .filter(|line| line.contains("insns"))
+        .find_map(|line| line.rsplit(':').next()?.trim().parse().ok())
+}
@@ -0,0 +1,131 @@
+//! Picking the fastest build of a case that fits a size and compile
+//! time budget, from every run measured in the outdir.
+
+use anyhow::{Error, Result, anyhow, bail};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::replay::CaseId;
+use crate::report;
+
+/// A size like `500K`, `2MB` or `123456`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bytes(pub u64);
+
+/// A duration like `10s`, `1500ms`, `2m` or `1h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Millis(pub u64);
+
+impl FromStr for Bytes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Bytes> {
+        let s = s.trim().to_ascii_uppercase();
+        let s = s.strip_suffix('B').unwrap_or(&s);
+        let (number, scale) = match s.char_indices().last() {
+            Some((i, 'K')) => (&s[..i], 1 << 10),
+            Some((i, 'M')) => (&s[..i], 1 << 20),
+            Some((i, 'G')) => (&s[..i], 1 << 30),
+            _ => (s, 1),
+        };
+        let number: f64 = number.trim().parse()
+            .map_err(|_| anyhow!("expected a size like 500K or 2MB"))?;
+
+        Ok(Bytes((number * scale as f64) as u64))
+    }
+}
+
+impl FromStr for Millis {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Millis> {
+        let s = s.trim();
+        let (number, scale) = if let Some(n) = s.strip_suffix("ms") {
+            (n, 1)
+        } else if let Some(n) = s.strip_suffix('s') {
+            (n, 1000)
+        } else if let Some(n) = s.strip_suffix('m') {
+            (n, 60_000)
+        } else if let Some(n) = s.strip_suffix('h') {
+            (n, 3_600_000)
+        } else {
+            bail!("expected a duration like 10s or 1500ms");
+        };
+        let number: f64 = number.trim().parse()
+            .map_err(|_| anyhow!("expected a duration like 10s or 1500ms"))?;
+
+        Ok(Millis((number * scale as f64) as u64))
+    }
+}
+
+pub struct Budget {
+    pub max_size: Option<Bytes>,
+    pub max_compile: Option<Millis>,
+}
+
+pub fn recommend(root: &Path, id: CaseId, budget: Budget) -> Result<()> {
+    let mut candidates = report::candidates(root, id)?;
+    candidates.sort_by_key(|c| (c.run_time_ms, c.bin_size));
+
+    let over_budget = |c: &report::Candidate| {
+        let mut reasons = vec![];
+        if let Some(Bytes(max)) = budget.max_size {
+            if c.bin_size > max {
+                reasons.push(format!("{} bytes is over {}", c.bin_size, max));
+            }
+        }
+        if let Some(Millis(max)) = budget.max_compile {
+            if c.compile_time_ms > max {
+                reasons.push(format!("{} ms to compile is over {}", c.compile_time_ms, max));
+            }
+        }
+        reasons
+    };
+
+    let (fits, misses): (Vec<_>, Vec<_>) = candidates.iter()
+        .partition(|c| over_budget(c).is_empty());
+
+    println!("case {} types / {} fns, {} of {} builds fit the budget",
+             id.num_types, id.num_fns, fits.len(), candidates.len());
+    match fits.first() {
+        Some(best) => {
+            println!("fastest: {} ({} ms, {} bytes, {} ms to compile)",
+                     best.label, best.run_time_ms, best.bin_size, best.compile_time_ms);
+        }
+        None => println!("fastest: nothing fits"),
+    }
+
+    println!();
+    println!("{:>12}{:>12}{:>12}  build", "run-time-ms", "bin-size", "compile-ms");
+    for c in &fits {
+        println!("{:>12}{:>12}{:>12}  {}", c.run_time_ms, c.bin_size,
+                 c.compile_time_ms, c.label);
+    }
+    for c in &misses {
+        println!("{:>12}{:>12}{:>12}  {}: {}", c.run_time_ms, c.bin_size,
+                 c.compile_time_ms, c.label, over_budget(c).join(", "));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bytes, Millis};
+
+    #[test]
+    fn sizes() {
+        assert_eq!("123".parse::<Bytes>().unwrap(), Bytes(123));
+        assert_eq!("500K".parse::<Bytes>().unwrap(), Bytes(500 * 1024));
+        assert_eq!("1.5mb".parse::<Bytes>().unwrap(), Bytes(3 << 19));
+        assert!("lots".parse::<Bytes>().is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!("10s".parse::<Millis>().unwrap(), Millis(10_000));
+        assert_eq!("1500ms".parse::<Millis>().unwrap(), Millis(1500));
+        assert_eq!("2m".parse::<Millis>().unwrap(), Millis(120_000));
+        assert!("10".parse::<Millis>().is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Refining a swept grid where the variants' relationship changes. After
+//! a run sweep, cases are added one at a time halfway between the pair
+//! of neighbouring cases, along either axis, whose dynamic/static run
+//! time ratios differ most, preferring pairs the variants cross between.
+//! Each new case is generated, compiled and run with its neighbour's
+//! options, and counts as a neighbour when picking the next.
+
+use anyhow::{Result, anyhow};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::report::{CaseAxis, Metric};
+use crate::{manifest, results, AxisFilter, CaseConfig, Runner};
+
+pub fn refine(outdir: &Path, filter: &AxisFilter, cases: u32, runner: &Runner,
+              repeat: u32, retries: u32) -> Result<()> {
+    let mut added = 0;
+    for _ in 0..cases {
+        let mut points = vec![];
+        for case in results::load(outdir)? {
+            if !filter.matches(case.num_types, case.num_fns) {
+                continue;
+            }
+            match Metric::RunTime.values(&case) {
+                Some((s, d)) if s > 0 && d > 0 => {
+                    points.push(((case.num_types, case.num_fns), d as f64 / s as f64));
+                }
+                _ => {}
+            }
+        }
+        let gap = match widest_gap(&points) {
+            Some(gap) => gap,
+            None => break,
+        };
+
+        println!("refining between {}-{} and {}-{}, at ratios {:.2} and {:.2}",
+                 gap.from.0, gap.from.1, gap.to.0, gap.to.1, gap.ratios.0, gap.ratios.1);
+        let neighbour = CaseConfig {
+            outdir: outdir.to_owned(),
+            num_types: gap.from.0,
+            num_fns: gap.from.1,
+        };
+        let gen_opts = manifest::find_case(&neighbour)?.gen_opts;
+        let compile_opts = results::find_case(&neighbour)?
+            .and_then(|c| c.compile)
+            .map(|c| c.opts)
+            .ok_or_else(|| anyhow!("case {} types / {} fns has no compile options",
+                                   gap.from.0, gap.from.1))?;
+        let config = CaseConfig {
+            outdir: outdir.to_owned(),
+            num_types: gap.midpoint.0,
+            num_fns: gap.midpoint.1,
+        };
+        crate::gen_one_case(config.clone(), gen_opts)?;
+        crate::compile_one_case(config.clone(), compile_opts)?;
+        crate::run_one_case(config, runner, repeat, retries)?;
+        added += 1;
+    }
+
+    println!();
+    println!("refined the grid with {} cases; see `dispatch-test crossover`", added);
+
+    Ok(())
+}
+
+/// A measured case and its dynamic/static run time ratio
+type Point = ((u32, u32), f64);
+
+struct Gap {
+    from: (u32, u32),
+    to: (u32, u32),
+    ratios: (f64, f64),
+    midpoint: (u32, u32),
+}
+
+/// The pair of neighbours, with room for a case between them, that a
+/// case between would tell the most about
+fn widest_gap(points: &[Point]) -> Option<Gap> {
+    let mut gaps = vec![];
+    for along in [CaseAxis::Types, CaseAxis::Fns].iter().copied() {
+        let pos = |case: (u32, u32)| match along {
+            CaseAxis::Types => case.0,
+            CaseAxis::Fns => case.1,
+        };
+        let across = |case: (u32, u32)| match along {
+            CaseAxis::Types => case.1,
+            CaseAxis::Fns => case.0,
+        };
+        let mut lines: BTreeMap<u32, Vec<Point>> = BTreeMap::new();
+        for &(case, ratio) in points {
+            lines.entry(across(case)).or_default().push((case, ratio));
+        }
+        for line in lines.values_mut() {
+            line.sort_by_key(|&(case, _)| pos(case));
+            for pair in line.windows(2) {
+                let ((from, r0), (to, r1)) = (pair[0], pair[1]);
+                if pos(to) - pos(from) < 2 {
+                    continue;
+                }
+                let mid = (pos(from) + pos(to)) / 2;
+                let midpoint = match along {
+                    CaseAxis::Types => (mid, from.1),
+                    CaseAxis::Fns => (from.0, mid),
+                };
+                gaps.push(Gap { from, to, ratios: (r0, r1), midpoint });
+            }
+        }
+    }
+
+    let crosses = |g: &Gap| (g.ratios.0 < 1.0) != (g.ratios.1 < 1.0);
+    let change = |g: &Gap| (g.ratios.1.ln() - g.ratios.0.ln()).abs();
+    gaps.into_iter()
+        .filter(|g| change(g) > 0.0)
+        .max_by(|a, b| crosses(a).cmp(&crosses(b))
+                .then(change(a).total_cmp(&change(b))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::widest_gap;
+
+    #[test]
+    fn splits_crossings_then_the_steepest_change() {
+        // Along types at 1 fn, dynamic crosses static between 4 and 8;
+        // along fns at 2 types, the ratio halves between 1 and 4
+        let points = [((2, 1), 0.9), ((4, 1), 0.95), ((8, 1), 1.2),
+                      ((2, 4), 0.45), ((16, 1), 1.2)];
+        let gap = widest_gap(&points).unwrap();
+        assert_eq!((gap.from, gap.to, gap.midpoint), ((4, 1), (8, 1), (6, 1)));
+
+        let points = [((2, 1), 0.9), ((2, 4), 0.45), ((4, 1), 0.95)];
+        assert_eq!(widest_gap(&points).unwrap().midpoint, (2, 2));
+
+        // Adjacent cases leave no room, and flat lines aren't worth it
+        assert!(widest_gap(&[((1, 1), 0.5), ((2, 1), 2.0)]).is_none());
+        assert!(widest_gap(&[((1, 1), 2.0), ((8, 1), 2.0)]).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+//! Running case binaries on another machine over ssh, for measuring on
+//! a quiet benchmarking box or a different microarchitecture. Binaries
+//! are copied into a scratch directory on the remote and timed there,
+//! so network latency doesn't count against the run.
+
+use anyhow::{Result, anyhow, bail};
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::container;
+
+/// The machine a case was run on, recorded alongside its run results
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub dest: String,
+    pub hostname: String,
+    pub os: String,
+    pub cpu: String,
+    pub cpus: u32,
+}
+
+pub struct Remote {
+    dest: String,
+    dir: String,
+    pub host: HostInfo,
+}
+
+static HOST_INFO_SCRIPT: &str = "\
+uname -n
+uname -srm
+sed -n 's/^model name[^:]*: //p' /proc/cpuinfo | head -n 1
+nproc";
+
+impl Remote {
+    /// Connects to `dest` (as given to ssh, e.g. `user@host`) and
+    /// creates a scratch directory for binaries
+    pub fn connect(dest: &str) -> Result<Remote> {
+        println!("connecting to {}", dest);
+
+        let info = ssh(dest, HOST_INFO_SCRIPT)?;
+        let mut lines = info.lines().map(str::trim);
+        let mut next = || lines.next().unwrap_or_default().to_string();
+        let host = HostInfo {
+            dest: dest.to_string(),
+            hostname: next(),
+            os: next(),
+            cpu: next(),
+            cpus: next().parse().unwrap_or(0),
+        };
+
+        println!("remote host: {} ({}, {}, {} cpus)",
+                 host.hostname, host.os, host.cpu, host.cpus);
+
+        let dir = ssh(dest, "mktemp -d -t dispatch-test.XXXXXX")?
+            .trim().to_string();
+
+        Ok(Remote { dest: dest.to_string(), dir, host })
+    }
+
+    /// Copies a binary to the remote and times it there, returning its
+    /// output too if `capture`
+    pub fn run_case(&self, bin: &Path, capture: bool) -> Result<(Duration, Option<String>)> {
+        let name = match bin.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => bail!("{} is not a file", bin.display()),
+        };
+
+        let status = Command::new("scp")
+            .args(["-q", "-o", "BatchMode=yes"])
+            .arg(bin)
+            .arg(format!("{}:{}/{}", self.dest, self.dir, name))
+            .status()?;
+
+        if !status.success() {
+            bail!("copying {} to {} failed", bin.display(), self.dest);
+        }
+
+        let script = format!("cd {} && sh -c '{}' ./{}; status=$?; rm {}; exit $status",
+                             self.dir, container::case_script(capture), name, name);
+        let out = ssh(&self.dest, &script)
+            .map_err(|e| anyhow!("running case on {} failed: {}", self.dest, e))?;
+
+        container::parse_case_output(&out, capture)
+            .ok_or_else(|| anyhow!("bad timing from {}: {:?}", self.dest, out))
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        let _ = ssh(&self.dest, &format!("rm -rf {}", self.dir));
+    }
+}
+
+fn ssh(dest: &str, script: &str) -> Result<String> {
+    let output = Command::new("ssh")
+        .args(["-o", "BatchMode=yes"])
+        .arg(dest)
+        .arg(script)
+        .output()?;
+
+    if !output.status.success() {
+        bail!("ssh to {} failed: {}",
+              dest, String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
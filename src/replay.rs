@@ -0,0 +1,141 @@
+//! Re-executing one stored case with the options it was measured with,
+//! to check a surprising number. The replay goes into its own run
+//! directory beside the original so the stored results are untouched.
+
+use anyhow::{Error, Result, anyhow};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::results::{self, CaseResults, RunResults, VariantCompileResults, VariantRunResults};
+use crate::{container, manifest, qemu, remote, sandbox, CaseConfig, Dispatch, Runner};
+
+/// A case named by its numbers of types and fns, as `TYPES-FNS` like
+/// the case's file names
+#[derive(Debug, Clone, Copy)]
+pub struct CaseId {
+    pub num_types: u32,
+    pub num_fns: u32,
+}
+
+impl FromStr for CaseId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CaseId> {
+        let (types, fns) = s.split_once('-')
+            .ok_or_else(|| anyhow!("expected TYPES-FNS, like 8-4"))?;
+
+        Ok(CaseId {
+            num_types: types.parse()?,
+            num_fns: fns.parse()?,
+        })
+    }
+}
+
+pub fn replay(outdir: &Path, id: CaseId) -> Result<()> {
+    let stored_config = CaseConfig {
+        outdir: outdir.to_owned(),
+        num_types: id.num_types,
+        num_fns: id.num_fns,
+    };
+    let case = manifest::find_case(&stored_config)?;
+    let stored = results::find_case(&stored_config)?
+        .filter(|s| s.compile.is_some())
+        .ok_or_else(|| anyhow!("case {} types / {} fns has no stored results \
+                                to replay", id.num_types, id.num_fns))?;
+    let compile = stored.compile.as_ref().expect("filtered on compile results");
+
+    let label = outdir.file_name().expect("run dirs have names").to_string_lossy();
+    let replay_dir = outdir.with_file_name(format!("{}-replay-{:04}-{:04}", label,
+                                                   id.num_types, id.num_fns));
+    crate::fresh_derived_run(&replay_dir, outdir)?;
+    let config = CaseConfig {
+        outdir: replay_dir.clone(),
+        num_types: id.num_types,
+        num_fns: id.num_fns,
+    };
+
+    crate::gen_one_case(config.clone(), case.gen_opts.clone())?;
+    crate::compile_one_case(config.clone(), compile.opts.clone())?;
+    if let Some(ref run) = stored.run {
+        crate::run_one_case(config.clone(), &stored_runner(run)?, stored_repeat(run), 0)?;
+    }
+
+    let fresh = results::find_case(&config)?
+        .ok_or_else(|| anyhow!("the replay recorded no results"))?;
+    println!();
+    println!("replayed in {}", replay_dir.display());
+    print_comparison(&stored, &fresh);
+
+    Ok(())
+}
+
+/// A runner like the one a stored run was measured with
+pub fn stored_runner(run: &RunResults) -> Result<Runner> {
+    Ok(if run.emulated {
+        Runner::Qemu(qemu::Qemu::new())
+    } else if let Some(ref host) = run.host {
+        Runner::Remote(remote::Remote::connect(&host.dest)?)
+    } else if let Some(ref image) = run.container {
+        Runner::Container(container::Container::new(image)?)
+    } else if run.sandboxed {
+        Runner::Sandbox(sandbox::Sandbox::new(None)?)
+    } else {
+        Runner::Local { mem_limit: None, cold_cache: run.cold_cache }
+    })
+}
+
+/// How many times a stored run ran each binary
+pub fn stored_repeat(run: &RunResults) -> u32 {
+    run.variant(Dispatch::Static)
+        .and_then(|r| r.run_time_stats.as_ref())
+        .map_or(1, |s| s.samples.len() as u32)
+}
+
+fn print_comparison(stored: &CaseResults, fresh: &CaseResults) {
+    let variants = stored.variants();
+    print!("{:<16}", "");
+    for dispatch in &variants {
+        print!("{:>12}{:>12}", dispatch.to_string(), "(replay)");
+    }
+    println!();
+    let row = |label: &str, values: Vec<Option<u64>>| {
+        if values.iter().all(Option::is_some) {
+            print!("{:<16}", label);
+            for value in values.into_iter().flatten() {
+                print!("{:>12}", value);
+            }
+            println!();
+        }
+    };
+    let compile_row = |label: &str, f: fn(&VariantCompileResults) -> u64| {
+        let value = |c: &CaseResults, d| Some(f(c.compile.as_ref()?.variant(d)?));
+        row(label, variants.iter()
+            .flat_map(|&d| [value(stored, d), value(fresh, d)])
+            .collect());
+    };
+    let run_row = |label: &str, f: fn(&VariantRunResults) -> Option<u64>| {
+        let value = |c: &CaseResults, d| f(c.run.as_ref()?.variant(d)?);
+        row(label, variants.iter()
+            .flat_map(|&d| [value(stored, d), value(fresh, d)])
+            .collect());
+    };
+
+    compile_row("compile-time-ms", |v| v.compile_time_ms);
+    compile_row("bin-size", |v| v.bin_size);
+    compile_row("method-count", |v| v.method_count as u64);
+    compile_row("fn-count", |v| v.fn_count as u64);
+    run_row("instructions", |v| v.instructions);
+    run_row("run-time-ms", |v| Some(v.run_time_ms));
+
+    // Checksums recorded by older versions are missing, so say nothing
+    if let (Some(s), Some(r)) = (stored.compile.as_ref(), fresh.compile.as_ref()) {
+        for &dispatch in &variants {
+            let digests = (s.variant(dispatch).and_then(|v| v.bin_sha256.as_ref()),
+                           r.variant(dispatch).and_then(|v| v.bin_sha256.as_ref()));
+            if let (Some(a), Some(b)) = digests {
+                let verdict = if a == b { "same as recorded" } else { "changed" };
+                println!("{} binary: {}", dispatch, verdict);
+            }
+        }
+    }
+}
@@ -0,0 +1,380 @@
+//! A table of each variant's ratios to a baseline variant over a run's
+//! cases, optionally rolled up along one axis or into a single
+//! geometric mean, for when the full grid is too much to read, or
+//! fitted as trends, for when only a sample of it was swept. Also the
+//! Pareto frontier of size and speed for one case across every run's
+//! options. A run compiled as a matrix is reported over each of its
+//! combinations, keyed by their flags.
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::replay::CaseId;
+use crate::results::{self, CaseResults, RESULTS_FILE};
+use crate::stats::Interval;
+use crate::{matrix, summary, trend, AxisFilter, Dispatch};
+
+/// One of a case's two parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CaseAxis {
+    Types,
+    Fns,
+}
+
+impl CaseAxis {
+    pub fn of(self, case: &CaseResults) -> u32 {
+        match self {
+            CaseAxis::Types => case.num_types,
+            CaseAxis::Fns => case.num_fns,
+        }
+    }
+
+    pub fn other(self) -> CaseAxis {
+        match self {
+            CaseAxis::Types => CaseAxis::Fns,
+            CaseAxis::Fns => CaseAxis::Types,
+        }
+    }
+}
+
+/// A measurement compared between the variants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    RunTime,
+    CompileTime,
+    BinSize,
+}
+
+impl Metric {
+    /// A variant's value, if the case was measured
+    pub fn value(self, case: &CaseResults, dispatch: Dispatch) -> Option<u64> {
+        match self {
+            Metric::RunTime => case.run.as_ref()
+                .and_then(|r| r.variant(dispatch)).map(|r| r.run_time_ms),
+            Metric::CompileTime => case.compile.as_ref()
+                .and_then(|c| c.variant(dispatch)).map(|c| c.compile_time_ms),
+            Metric::BinSize => case.compile.as_ref()
+                .and_then(|c| c.variant(dispatch)).map(|c| c.bin_size),
+        }
+    }
+
+    /// The static and dynamic values, if the case was measured
+    pub fn values(self, case: &CaseResults) -> Option<(u64, u64)> {
+        Some((self.value(case, Dispatch::Static)?, self.value(case, Dispatch::Dynamic)?))
+    }
+
+    pub fn interval(self, case: &CaseResults, num: Dispatch, den: Dispatch) -> Option<Interval> {
+        match self {
+            Metric::RunTime => case.run.as_ref()
+                .and_then(|r| r.run_time_ratio(num, den)),
+            Metric::CompileTime => case.compile.as_ref()
+                .and_then(|c| c.compile_time_ratio(num, den)),
+            Metric::BinSize => None,
+        }
+    }
+}
+
+pub struct ReportConfig {
+    /// Roll cases up along this axis, over the other one
+    pub by: Option<CaseAxis>,
+    /// Finish with the geometric mean over all the cases
+    pub geomean: bool,
+    /// Instead list the size and speed frontier for this case
+    pub pareto: Option<CaseId>,
+    /// Instead fit each ratio's trend over the cases
+    pub trend: bool,
+    /// The variant the others' ratios are taken against
+    pub baseline: Dispatch,
+}
+
+pub static METRICS: [Metric; 3] = [Metric::RunTime, Metric::CompileTime, Metric::BinSize];
+
+/// One column of the table: a metric's ratio of a variant to the
+/// baseline
+#[derive(Clone, Copy)]
+struct Column {
+    metric: Metric,
+    variant: Dispatch,
+    baseline: Dispatch,
+}
+
+fn columns(baseline: Dispatch, variants: &[Dispatch]) -> Vec<Column> {
+    METRICS.iter()
+        .flat_map(|&metric| {
+            variants.iter()
+                .filter(move |&&v| v != baseline)
+                .map(move |&variant| Column { metric, variant, baseline })
+        })
+        .collect()
+}
+
+/// A case's ratios, one for each column
+struct Ratios {
+    /// The compile options' values, if the run was compiled as a matrix
+    flags: Option<String>,
+    num_types: u32,
+    num_fns: u32,
+    ratios: Vec<Option<f64>>,
+    /// Confidence intervals on the ratios, from repeated measurements
+    intervals: Vec<Option<Interval>>,
+}
+
+pub fn report(outdir: &Path, filter: &AxisFilter, config: ReportConfig) -> Result<()> {
+    if let Some(case) = config.pareto {
+        let root = outdir.parent().expect("run dirs have parents");
+        return pareto(root, case);
+    }
+
+    let runs = matrix::runs(outdir)?;
+    let mut loaded: Vec<(Option<String>, CaseResults)> = vec![];
+    for (flags, dir) in &runs {
+        if !dir.join(RESULTS_FILE).exists() {
+            continue;
+        }
+        loaded.extend(results::load(dir)?
+                      .into_iter()
+                      .filter(|c| filter.matches(c.num_types, c.num_fns))
+                      .map(|c| (flags.clone(), c)));
+    }
+    // A column per variant any of the cases has
+    let variants: BTreeSet<Dispatch> = loaded.iter().flat_map(|(_, c)| c.variants()).collect();
+    let variants: Vec<Dispatch> = variants.into_iter().collect();
+    let columns = columns(config.baseline, &variants);
+    let mut cases: Vec<Ratios> = loaded.into_iter()
+        .map(|(flags, c)| ratios(&c, &columns, flags))
+        .collect();
+    if cases.is_empty() {
+        bail!("no measured cases in {} match", outdir.display());
+    }
+    // A matrix's combinations of each case go together, in the order
+    // they were compiled
+    cases.sort_by_key(|c| (c.num_types, c.num_fns));
+    let flags_width = runs.iter()
+        .filter_map(|(flags, _)| flags.as_ref().map(|f| f.len() + 2))
+        .max()
+        .unwrap_or(0);
+    let flags_cell = |flags: &Option<String>| {
+        format!("{:<width$}", flags.as_deref().unwrap_or(""), width = flags_width)
+    };
+    let combinations: Vec<&Option<String>> = runs.iter().map(|(flags, _)| flags).collect();
+
+    if config.trend {
+        for flags in combinations {
+            let group: Vec<&Ratios> = cases.iter().filter(|c| &c.flags == flags).collect();
+            if !group.is_empty() {
+                if let Some(flags) = flags {
+                    println!("flags: {}", flags);
+                }
+                trends(&group, &columns);
+            }
+        }
+        return Ok(());
+    }
+
+    match config.by {
+        None => {
+            println!("{}{:>6}{:>6}{}", flags_cell(&None), "types", "fns", header(&columns));
+            for case in &cases {
+                let cells: String = case.ratios.iter().zip(&case.intervals)
+                    .map(|(ratio, interval)| cell(*ratio, *interval))
+                    .collect();
+                println!("{}{:>6}{:>6}{}", flags_cell(&case.flags),
+                         case.num_types, case.num_fns, cells);
+            }
+        }
+        Some(axis) => {
+            let mut groups: BTreeMap<(u32, usize), Vec<&Ratios>> = BTreeMap::new();
+            for case in &cases {
+                let value = match axis {
+                    CaseAxis::Types => case.num_types,
+                    CaseAxis::Fns => case.num_fns,
+                };
+                let combination = combinations.iter()
+                    .position(|&f| f == &case.flags)
+                    .expect("cases come from the runs");
+                groups.entry((value, combination)).or_default().push(case);
+            }
+            println!("{}{:>6}{:>6}{}", flags_cell(&None), axis.to_string(), "cases",
+                     header(&columns));
+            for ((value, combination), group) in &groups {
+                println!("{}{:>6}{:>6}{}", flags_cell(combinations[*combination]), value,
+                         group.len(), geomean_cells(group, columns.len()));
+            }
+        }
+    }
+
+    if config.geomean {
+        for flags in combinations {
+            let group: Vec<&Ratios> = cases.iter().filter(|c| &c.flags == flags).collect();
+            if !group.is_empty() {
+                println!("{}{:>12}{}", flags_cell(flags), "geomean",
+                         geomean_cells(&group, columns.len()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// One variant of a case as built by one run
+pub struct Candidate {
+    pub label: String,
+    pub bin_size: u64,
+    pub compile_time_ms: u64,
+    pub run_time_ms: u64,
+}
+
+/// Each run in the outdir with results, by name, with its results for a
+/// case if it has any
+pub fn every_run(root: &Path, id: CaseId) -> Result<Vec<(String, Option<CaseResults>)>> {
+    let mut run_dirs: Vec<_> = fs::read_dir(root)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.join(RESULTS_FILE).exists())
+        .collect();
+    run_dirs.sort();
+
+    let mut runs = vec![];
+    for run_dir in run_dirs {
+        let run = run_dir.file_name().expect("run dirs have names")
+            .to_string_lossy().into_owned();
+        let case = results::load(&run_dir)?.into_iter().find(|c| {
+            (c.num_types, c.num_fns) == (id.num_types, id.num_fns)
+        });
+        runs.push((run, case));
+    }
+
+    Ok(runs)
+}
+
+/// Every run's measured static and dynamic builds of a case, labelled
+/// with the run and its compile flags
+pub fn candidates(root: &Path, id: CaseId) -> Result<Vec<Candidate>> {
+    let mut candidates = vec![];
+    for (run, case) in every_run(root, id)? {
+        let (compile, ran) = match case {
+            Some(CaseResults { compile: Some(c), run: Some(r), .. }) => (c, r),
+            _ => continue,
+        };
+        for dispatch in Dispatch::ALL {
+            let (built, ran) = match (compile.variant(dispatch), ran.variant(dispatch)) {
+                (Some(built), Some(ran)) => (built, ran),
+                _ => continue,
+            };
+            let opts = compile.opts.for_variant(dispatch);
+            let flags = summary::compile_flags(&opts).join(" ");
+            candidates.push(Candidate {
+                label: format!("{} {} ({})", run, dispatch, flags),
+                bin_size: built.bin_size,
+                compile_time_ms: built.compile_time_ms,
+                run_time_ms: ran.run_time_ms,
+            });
+        }
+    }
+    if candidates.is_empty() {
+        bail!("no run in {} has measured case {} types / {} fns",
+              root.display(), id.num_types, id.num_fns);
+    }
+
+    Ok(candidates)
+}
+
+/// Lists every run's static and dynamic builds of a case by size,
+/// marking those another build beats on both size and run time
+fn pareto(root: &Path, id: CaseId) -> Result<()> {
+    let mut candidates = candidates(root, id)?;
+    candidates.sort_by_key(|c| (c.bin_size, c.run_time_ms));
+
+    println!("case {} types / {} fns, by binary size", id.num_types, id.num_fns);
+    println!("{:>12}{:>12}  build", "bin-size", "run-time-ms");
+    for c in &candidates {
+        let dominator = candidates.iter().find(|o| {
+            o.bin_size <= c.bin_size && o.run_time_ms <= c.run_time_ms
+                && (o.bin_size, o.run_time_ms) != (c.bin_size, c.run_time_ms)
+        });
+        let mark = match dominator {
+            Some(o) => format!("dominated by {}", o.label),
+            None => "frontier".to_string(),
+        };
+        println!("{:>12}{:>12}  {}: {}", c.bin_size, c.run_time_ms, c.label, mark);
+    }
+
+    Ok(())
+}
+
+fn ratios(case: &CaseResults, columns: &[Column], flags: Option<String>) -> Ratios {
+    let ratio = |c: &Column| {
+        let base = c.metric.value(case, c.baseline)?;
+        let value = c.metric.value(case, c.variant)?;
+        if base == 0 { None } else { Some(value as f64 / base as f64) }
+    };
+
+    Ratios {
+        flags,
+        num_types: case.num_types,
+        num_fns: case.num_fns,
+        ratios: columns.iter().map(ratio).collect(),
+        intervals: columns.iter()
+            .map(|c| c.metric.interval(case, c.variant, c.baseline))
+            .collect(),
+    }
+}
+
+/// Each column's trend over the cases, for sweeps of a sample of the grid
+fn trends(cases: &[&Ratios], columns: &[Column]) {
+    println!("trends over {} cases, with 95% bootstrap intervals:", cases.len());
+    println!("{:<30}{:>8}{:>30}{:>30}{:>30}",
+             "", "cases", "at 1 type, 1 fn", "x per doubling of types", "x per doubling of fns");
+    for (i, c) in columns.iter().enumerate() {
+        let points: Vec<trend::Point> = cases.iter()
+            .filter_map(|case| Some(((case.num_types, case.num_fns), case.ratios[i]?)))
+            .collect();
+        let label = format!("{}/{} {}", c.variant, c.baseline, c.metric);
+        match trend::fit(&points) {
+            Some(t) => {
+                println!("{:<30}{:>8}{}{}{}", label, t.cases,
+                         cell(None, Some(t.at_one)),
+                         cell(None, t.per_types_doubling),
+                         cell(None, t.per_fns_doubling));
+            }
+            None => println!("{:<30}{:>8}{}", label, points.len(), cell(None, None)),
+        }
+    }
+}
+
+fn header(columns: &[Column]) -> String {
+    columns.iter()
+        .map(|c| format!("{:>30}", format!("{}/{} {}", c.variant, c.baseline, c.metric)))
+        .collect()
+}
+
+fn cell(ratio: Option<f64>, interval: Option<Interval>) -> String {
+    match (ratio, interval) {
+        (_, Some(interval)) => format!("{:>30}", interval.to_string()),
+        (Some(ratio), None) => format!("{:>30.3}", ratio),
+        (None, None) => format!("{:>30}", "-"),
+    }
+}
+
+/// The geometric mean of each column's ratios over the cases that
+/// have it. Ratios are averaged geometrically so that 2x and 0.5x
+/// cancel out.
+fn geomean_cells(cases: &[&Ratios], columns: usize) -> String {
+    (0..columns)
+        .map(|i| {
+            let logs: Vec<f64> = cases.iter()
+                .filter_map(|c| c.ratios[i])
+                .filter(|r| *r > 0.0)
+                .map(f64::ln)
+                .collect();
+            let mean = if logs.is_empty() {
+                None
+            } else {
+                Some((logs.iter().sum::<f64>() / logs.len() as f64).exp())
+            };
+            cell(mean, None)
+        })
+        .collect()
+}
@@ -0,0 +1,145 @@
+//! A shell script per compiled case that writes its programs,
+//! builds them as they were measured and times a run of each, needing
+//! nothing but the compiler. It's one file, so it can be attached to a
+//! rustc issue as it is.
+
+use anyhow::{Result, bail};
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::results;
+use crate::summary::{compile_flags, gen_flags};
+use crate::{cmdline, cpp, manifest, pgo, variant_path, CaseConfig, CompileOpts,
+            Dispatch, Lang};
+
+/// Ends each source's here-document; no generated line is this
+static EOF_MARKER: &str = "DISPATCH_TEST_EOF";
+
+/// The toolchain's llvm-profdata, from the llvm-tools component, for
+/// --pgo builds
+static PROFDATA: &str = "$(rustc --print sysroot)/lib/rustlib/\
+                         $(rustc -vV | sed -n 's/^host: //p')/bin/llvm-profdata";
+
+fn repro_path(config: &CaseConfig) -> PathBuf {
+    config.outdir.join(format!("repro-{:04}-{:04}.sh",
+                               config.num_types, config.num_fns))
+}
+
+/// Writes the case's script beside its files
+pub fn write(config: &CaseConfig) -> Result<()> {
+    let path = repro_path(config);
+    fs::write(&path, render(config)?)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+
+    Ok(())
+}
+
+pub fn render(config: &CaseConfig) -> Result<String> {
+    let case = manifest::find_case(config)?;
+    let compile = match results::find_case(config)?.and_then(|r| r.compile) {
+        Some(compile) => compile,
+        None => bail!("case {} types / {} fns hasn't been compiled",
+                      config.num_types, config.num_fns),
+    };
+    let opts = &compile.opts;
+    let lang = case.gen_opts.lang;
+    let variants = case.gen_opts.variants();
+
+    let mut out = String::new();
+    writeln!(out, "#!/bin/bash")?;
+    writeln!(out, "# dispatch-test {} case: {} types / {} fns",
+             env!("CARGO_PKG_VERSION"), config.num_types, config.num_fns)?;
+    for (cmd, flags) in [("gen-one-case", gen_flags(&case.gen_opts)),
+                         ("compile-one-case", compile_flags(opts))] {
+        let mut line = format!("#   {} {} {}", cmd, config.num_types, config.num_fns);
+        for flag in flags {
+            line.push(' ');
+            line.push_str(&flag);
+        }
+        writeln!(out, "{}", line)?;
+    }
+    if let Some(ref image) = opts.container {
+        writeln!(out, "# Measured with the compiler in {}; this uses yours.", image)?;
+    }
+    if let Some(ref target) = opts.target {
+        writeln!(out, "# Built for {}; run the binaries on such a machine.", target)?;
+    }
+    writeln!(out, "set -e")?;
+    writeln!(out, "cd \"$(mktemp -d)\"")?;
+    writeln!(out, "echo \"building in $PWD\"")?;
+    for assignment in cmdline::tool_env() {
+        writeln!(out, "export {}", assignment)?;
+    }
+
+    for &dispatch in &variants {
+        let src = variant_path(config, dispatch, lang.src_ext());
+        let bin = variant_path(config, dispatch, "bin");
+        let text = fs::read_to_string(&src)?;
+        let (src, bin) = (file_name(&src), file_name(&bin));
+
+        writeln!(out)?;
+        writeln!(out, "cat > {} <<'{}'", src.display(), EOF_MARKER)?;
+        out.push_str(&text);
+        if !text.ends_with('\n') {
+            out.push('\n');
+        }
+        writeln!(out, "{}", EOF_MARKER)?;
+        writeln!(out, "{}", build_command(&src, &bin, lang,
+                                          &opts.for_variant(dispatch))?)?;
+    }
+
+    writeln!(out)?;
+    for &dispatch in &variants {
+        let bin = file_name(&variant_path(config, dispatch, "bin"));
+        writeln!(out, "echo {}", dispatch)?;
+        writeln!(out, "time ./{}", bin.display())?;
+    }
+
+    Ok(out)
+}
+
+/// The measured build of one variant, without what only matters to
+/// measuring it: incremental state, split linking and path remapping.
+/// Under --pgo, preceded by the build and run that train its profile.
+fn build_command(src: &Path, bin: &Path, lang: Lang,
+                 opts: &CompileOpts) -> Result<String> {
+    let mut opts = opts.clone();
+    opts.incremental = false;
+    opts.split_link = false;
+    opts.deterministic = false;
+
+    Ok(match lang {
+        Lang::Rust if opts.pgo => {
+            let mut train = opts.clone();
+            train.pgo = false;
+            let raw = src.with_extension("profraw");
+            let instrumented = src.with_extension("pgo.bin");
+            let mut args = crate::rustc_args(src, &instrumented, "link", &train);
+            let mut arg = OsString::from("-Cprofile-generate=");
+            arg.push(&raw);
+            args.push(arg);
+            let merge: Vec<OsString> = vec!["-o".into(), pgo::profile_path(src).into(),
+                                            raw.into()];
+            let use_args: Vec<OsString> = crate::rustc_args(src, bin, "link", &opts);
+            [cmdline::command("rustc", &args),
+             format!("./{} > /dev/null", instrumented.display()),
+             format!("\"{}\" {}", PROFDATA, cmdline::command("merge", &merge)),
+             cmdline::command("rustc", &use_args)].join("\n")
+        }
+        Lang::Rust => {
+            let args: Vec<OsString> = crate::rustc_args(src, bin, "link", &opts);
+            cmdline::command("rustc", &args)
+        }
+        Lang::Cpp => {
+            let args = cpp::cxx_args(src, bin, false, &opts)?;
+            cmdline::command(&opts.cxx, &args)
+        }
+    })
+}
+
+fn file_name(path: &Path) -> PathBuf {
+    PathBuf::from(path.file_name().expect("case paths have names"))
+}
@@ -0,0 +1,380 @@
+//! Measurements from the compile and run phases, kept in the run
+//! directory next to the manifest so they outlive the terminal output
+//! and can be archived and compared later.
+
+use anyhow::{Result, anyhow};
+use atomic_blobject::AtomBlob;
+use clap::ValueEnum;
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use crate::{CaseConfig, CompileOpts, Dispatch};
+use crate::error::Phase;
+use crate::remote::HostInfo;
+use crate::mono::MonoItems;
+use crate::stats::{self, Interval, Stats};
+use crate::symbols::SymbolLoss;
+
+pub static RESULTS_FILE: &str = "results.json";
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Results {
+    pub cases: Vec<CaseResults>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CaseResults {
+    pub num_types: u32,
+    pub num_fns: u32,
+    #[serde(default)]
+    pub compile: Option<CompileResults>,
+    #[serde(default)]
+    pub run: Option<RunResults>,
+    /// Set when the case last went over `--mem-limit`
+    #[serde(default)]
+    pub mem_limit_exceeded: Option<MemLimitOutcome>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MemLimitOutcome {
+    pub phase: Phase,
+    pub limit: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CompileResults {
+    pub opts: CompileOpts,
+    /// The dispatch-test command line that compiled the case
+    #[serde(default)]
+    pub command: Option<String>,
+    /// The jobserver tokens shared by parallel compiles, if limited
+    #[serde(default)]
+    pub jobs: Option<usize>,
+    /// Each variant's build
+    #[serde(flatten)]
+    pub variants: Variants<VariantCompileResults>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VariantCompileResults {
+    /// The median when measured with `--measure-compile`
+    pub compile_time_ms: u64,
+    #[serde(default)]
+    pub compile_time_stats: Option<Stats>,
+    /// The build's time split at linking, under `--split-link`
+    #[serde(default)]
+    pub codegen_time_ms: Option<u64>,
+    #[serde(default)]
+    pub link_time_ms: Option<u64>,
+    /// A rebuild reusing warm incremental state, under `--incremental`
+    #[serde(default)]
+    pub rebuild_time_ms: Option<u64>,
+    #[serde(default)]
+    pub rebuild_time_stats: Option<Stats>,
+    /// The compiler and linker command lines, to reproduce the build
+    #[serde(default)]
+    pub commands: Vec<String>,
+    pub bin_size: u64,
+    pub method_count: usize,
+    pub fn_count: usize,
+    #[serde(default)]
+    pub symbol_loss: Option<SymbolLoss>,
+    /// What rustc monomorphized, under `--mono-items`
+    #[serde(default)]
+    pub mono_items: Option<MonoItems>,
+    /// Whether a second build was identical, under `--deterministic`
+    #[serde(default)]
+    pub deterministic: Option<bool>,
+    /// The SHA-256 of the binary, and of its assembly under `--asm`
+    #[serde(default)]
+    pub bin_sha256: Option<String>,
+    #[serde(default)]
+    pub asm_sha256: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunResults {
+    /// Each variant's run
+    #[serde(flatten)]
+    pub variants: Variants<VariantRunResults>,
+    /// Set when the case was run on another machine with `--remote`
+    #[serde(default)]
+    pub host: Option<HostInfo>,
+    /// Set when the case was run inside an image with `--container`
+    #[serde(default)]
+    pub container: Option<String>,
+    /// Set when the case was run under qemu with `--runner qemu`, in
+    /// which case the run times are not representative of hardware
+    #[serde(default)]
+    pub emulated: bool,
+    /// Set when the case was run under bubblewrap with `--sandbox`
+    #[serde(default)]
+    pub sandboxed: bool,
+    /// Set when each binary was dropped from the page cache before
+    /// every launch with `--cold-cache`
+    #[serde(default)]
+    pub cold_cache: bool,
+    /// The binaries built without the target cpu and features, under
+    /// `--compare-target`
+    #[serde(default)]
+    pub baseline: Option<Variants<VariantRunResults>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VariantRunResults {
+    /// The median when run with `--repeat`
+    pub run_time_ms: u64,
+    #[serde(default)]
+    pub run_time_stats: Option<Stats>,
+    /// Instructions retired, counted by qemu's instruction plugin
+    #[serde(default)]
+    pub instructions: Option<u64>,
+    /// Each launch's time in microseconds, for a `--cold-start` case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch_time_stats: Option<Stats>,
+    /// The sum a `--checksum` case printed on its first launch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>,
+}
+
+/// Results for each of a case's variants, keyed by how it dispatches.
+/// They're flattened into the case's other results, where `static` and
+/// `dynamic` always were, so keys that aren't variants are skipped.
+#[derive(Clone, Serialize)]
+pub struct Variants<T>(BTreeMap<Dispatch, T>);
+
+impl<T> Variants<T> {
+    /// A variant's results, if the case has that variant
+    pub fn get(&self, dispatch: Dispatch) -> Option<&T> {
+        self.0.get(&dispatch)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Dispatch, &T)> {
+        self.0.iter().map(|(&d, r)| (d, r))
+    }
+}
+
+impl<T> FromIterator<(Dispatch, T)> for Variants<T> {
+    fn from_iter<I: IntoIterator<Item = (Dispatch, T)>>(iter: I) -> Variants<T> {
+        Variants(iter.into_iter().collect())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Variants<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Variants<T>, D::Error> {
+        struct VariantsVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for VariantsVisitor<T> {
+            type Value = Variants<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("results keyed by variant")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Variants<T>, A::Error> {
+                let mut variants = BTreeMap::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    match Dispatch::from_str(&key, false) {
+                        Ok(dispatch) => {
+                            variants.insert(dispatch, map.next_value()?);
+                        }
+                        Err(_) => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                if variants.is_empty() {
+                    return Err(de::Error::custom("no variants' results"));
+                }
+                Ok(Variants(variants))
+            }
+        }
+
+        deserializer.deserialize_map(VariantsVisitor(PhantomData))
+    }
+}
+
+impl CaseResults {
+    /// The variants the case has compile or run results for
+    pub fn variants(&self) -> Vec<Dispatch> {
+        let compiled = self.compile.iter().flat_map(|c| c.variants.iter().map(|(d, _)| d));
+        let ran = self.run.iter().flat_map(|r| r.variants.iter().map(|(d, _)| d));
+        let variants: BTreeSet<Dispatch> = compiled.chain(ran).collect();
+        variants.into_iter().collect()
+    }
+}
+
+impl CompileResults {
+    /// A variant's build, if the case has that variant
+    pub fn variant(&self, dispatch: Dispatch) -> Option<&VariantCompileResults> {
+        self.variants.get(dispatch)
+    }
+
+    /// The ratio of two variants' compile times with its confidence
+    /// interval, under `--measure-compile`
+    pub fn compile_time_ratio(&self, num: Dispatch, den: Dispatch) -> Option<Interval> {
+        ratio(&self.variant(num)?.compile_time_stats, &self.variant(den)?.compile_time_stats)
+    }
+}
+
+impl RunResults {
+    /// A variant's run, if the case has that variant
+    pub fn variant(&self, dispatch: Dispatch) -> Option<&VariantRunResults> {
+        self.variants.get(dispatch)
+    }
+
+    pub fn run_time_ratio(&self, num: Dispatch, den: Dispatch) -> Option<Interval> {
+        self.variants.run_time_ratio(num, den)
+    }
+}
+
+impl Variants<VariantRunResults> {
+    /// The ratio of two variants' run times with its confidence
+    /// interval, when runs were repeated. A `--cold-start` case's are
+    /// its launches'.
+    pub fn run_time_ratio(&self, num: Dispatch, den: Dispatch) -> Option<Interval> {
+        let (num, den) = (self.get(num)?, self.get(den)?);
+        match num.launch_time_stats {
+            Some(_) => ratio(&num.launch_time_stats, &den.launch_time_stats),
+            None => ratio(&num.run_time_stats, &den.run_time_stats),
+        }
+    }
+}
+
+/// The ratio of two variants' repeated measurements, with a bootstrap
+/// confidence interval over their samples
+pub fn ratio(num: &Option<Stats>, den: &Option<Stats>) -> Option<Interval> {
+    match (num, den) {
+        (Some(n), Some(d)) => stats::bootstrap_ratio(&n.samples, &d.samples),
+        _ => None,
+    }
+}
+
+fn results_path(outdir: &Path) -> PathBuf {
+    outdir.join(RESULTS_FILE)
+}
+
+fn open(outdir: &Path) -> Result<AtomBlob<Results>> {
+    AtomBlob::new(results_path(outdir))
+        .map_err(|e| anyhow!("opening results: {}", e))
+}
+
+fn update_case(config: &CaseConfig, f: impl FnOnce(&mut CaseResults)) -> Result<()> {
+    let mut blob = open(&config.outdir)?;
+    let mut results = blob.get_mut()
+        .map_err(|e| anyhow!("locking results: {}", e))?;
+
+    let pos = results.cases.iter().position(|c| {
+        (c.num_types, c.num_fns) == (config.num_types, config.num_fns)
+    });
+    let pos = match pos {
+        Some(pos) => pos,
+        None => {
+            results.cases.push(CaseResults {
+                num_types: config.num_types,
+                num_fns: config.num_fns,
+                compile: None,
+                run: None,
+                mem_limit_exceeded: None,
+            });
+            results.cases.sort_by_key(|c| (c.num_types, c.num_fns));
+            results.cases.iter().position(|c| {
+                (c.num_types, c.num_fns) == (config.num_types, config.num_fns)
+            }).expect("just inserted")
+        }
+    };
+    f(&mut results.cases[pos]);
+
+    results.commit()
+        .map_err(|e| anyhow!("writing results: {}", e))?;
+
+    Ok(())
+}
+
+/// Records a compile, discarding any run results measured from the
+/// previous binaries
+pub fn record_compile(config: &CaseConfig, compile: CompileResults) -> Result<()> {
+    update_case(config, |c| {
+        c.compile = Some(compile);
+        c.run = None;
+        c.mem_limit_exceeded = None;
+    })
+}
+
+pub fn record_run(config: &CaseConfig, run: RunResults) -> Result<()> {
+    update_case(config, |c| {
+        c.run = Some(run);
+        c.mem_limit_exceeded = None;
+    })
+}
+
+pub fn record_mem_limit(config: &CaseConfig, outcome: MemLimitOutcome) -> Result<()> {
+    update_case(config, |c| c.mem_limit_exceeded = Some(outcome))
+}
+
+pub fn find_case(config: &CaseConfig) -> Result<Option<CaseResults>> {
+    if !results_path(&config.outdir).exists() {
+        return Ok(None);
+    }
+
+    let cases = load(&config.outdir)?;
+
+    Ok(cases.into_iter().find(|c| {
+        (c.num_types, c.num_fns) == (config.num_types, config.num_fns)
+    }))
+}
+
+pub fn load(outdir: &Path) -> Result<Vec<CaseResults>> {
+    let mut blob = open(outdir)?;
+    let results = blob.get()
+        .map_err(|e| anyhow!("reading results: {}", e))?;
+
+    Ok(results.cases.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Variants, VariantRunResults};
+    use crate::stats::Stats;
+    use crate::Dispatch;
+
+    #[test]
+    fn variants_beside_other_fields() {
+        #[derive(Deserialize)]
+        struct Run {
+            #[serde(flatten)]
+            variants: Variants<VariantRunResults>,
+            #[serde(default)]
+            emulated: bool,
+        }
+        // As written before variants were keyed, with a stored ratio
+        let run: Run = serde_json::from_str(r#"{
+            "static": { "run_time_ms": 10 },
+            "dynamic": { "run_time_ms": 20 },
+            "run_time_ratio": null,
+            "emulated": true
+        }"#).unwrap();
+        assert!(run.emulated);
+        assert_eq!(run.variants.iter().count(), 2);
+        assert_eq!(run.variants.get(Dispatch::Dynamic).unwrap().run_time_ms, 20);
+        assert!(run.variants.get(Dispatch::Concrete).is_none());
+
+        let repeated = |dispatch, samples: Vec<u64>| (dispatch, VariantRunResults {
+            run_time_ms: 0,
+            run_time_stats: Some(Stats::new(samples)),
+            instructions: None,
+            launch_time_stats: None,
+            checksum: None,
+        });
+        let runs: Variants<_> = vec![repeated(Dispatch::Static, vec![10, 10]),
+                                     repeated(Dispatch::Concrete, vec![5, 5])]
+            .into_iter().collect();
+        let ratio = runs.run_time_ratio(Dispatch::Static, Dispatch::Concrete).unwrap();
+        assert_eq!(ratio.estimate, 2.0);
+        assert!(runs.run_time_ratio(Dispatch::Dynamic, Dispatch::Static).is_none());
+    }
+}
@@ -0,0 +1,62 @@
+//! Retrying compiler and case runs that fail for reasons that have
+//! nothing to do with the case, like rustc being killed for memory, so
+//! an unattended sweep doesn't stop at the first one.
+
+use anyhow::Result;
+use std::thread;
+use std::time::Duration;
+
+use crate::interrupt;
+use crate::memlimit::MemLimitExceeded;
+
+/// The wait before the first retry, doubling for each one after
+static BACKOFF: Duration = Duration::from_secs(1);
+
+/// Calls `f` until it succeeds or has been retried `retries` times,
+/// returning the last error. Going over --mem-limit would only happen
+/// again, so isn't retried.
+pub fn retry<T>(retries: u32, f: impl FnMut() -> Result<T>) -> Result<T> {
+    retry_after(retries, BACKOFF, f)
+}
+
+fn retry_after<T>(retries: u32, backoff: Duration,
+                  mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < retries && !interrupt::interrupted()
+                && e.downcast_ref::<MemLimitExceeded>().is_none() => {
+                let delay = backoff * 2u32.pow(attempt);
+                attempt += 1;
+                eprintln!("{:#}; retry {} of {} in {:?}", e, attempt, retries, delay);
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::retry_after;
+    use anyhow::bail;
+    use std::time::Duration;
+
+    #[test]
+    fn retries_until_success_or_exhausted() {
+        let mut calls = 0;
+        let result = retry_after(3, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 { bail!("transient") } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: anyhow::Result<()> = retry_after(2, Duration::ZERO, || {
+            calls += 1;
+            bail!("permanent")
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+}
@@ -0,0 +1,66 @@
+//! Running case binaries under bubblewrap, for sweeps over templates
+//! that aren't trusted. The binary sees the filesystem read-only with a
+//! private /tmp, and has no network, no capabilities and no other
+//! processes. It is timed inside the sandbox so setting it up isn't
+//! measured.
+
+use anyhow::{Result, anyhow, bail};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::container;
+use crate::memlimit;
+
+pub struct Sandbox {
+    mem_limit: Option<u64>,
+}
+
+static BWRAP_ARGS: &[&str] = &[
+    "--ro-bind", "/", "/",
+    "--dev", "/dev",
+    "--proc", "/proc",
+    "--tmpfs", "/tmp",
+    "--unshare-all",
+    "--cap-drop", "ALL",
+    "--new-session",
+    "--die-with-parent",
+];
+
+impl Sandbox {
+    pub fn new(mem_limit: Option<u64>) -> Result<Sandbox> {
+        let found = Command::new("bwrap")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        if !found {
+            bail!("--sandbox needs bubblewrap (bwrap) installed");
+        }
+
+        Ok(Sandbox { mem_limit })
+    }
+
+    /// Runs a binary in the sandbox and returns how long it took, and
+    /// its output if `capture`
+    pub fn time(&self, bin: &Path, capture: bool) -> Result<(Duration, Option<String>)> {
+        let bin = fs::canonicalize(bin)?;
+        let mut cmd = Command::new("bwrap");
+        cmd.args(BWRAP_ARGS)
+            .args(["sh", "-c", container::case_script(capture)])
+            .arg(&bin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        let output = memlimit::output(&mut cmd, self.mem_limit)?;
+
+        if !output.status.success() {
+            bail!("{} failed in the sandbox with {}", bin.display(), output.status);
+        }
+
+        let out = String::from_utf8_lossy(&output.stdout);
+        container::parse_case_output(&out, capture)
+            .ok_or_else(|| anyhow!("bad timing from the sandbox: {:?}", out))
+    }
+}
@@ -0,0 +1,151 @@
+//! Ranking which parameters of a sweep drive the gap between the
+//! static and dynamic variants, by how much of the gap's variation
+//! each one explains on its own.
+
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::report::Metric;
+use crate::results::{self, CaseResults};
+use crate::{manifest, CompileOpts, Dispatch, GenOpts};
+
+/// A measured case with the options it was generated with
+struct Case {
+    results: CaseResults,
+    gen_opts: Option<GenOpts>,
+}
+
+type Param = (&'static str, fn(&Case) -> Option<String>);
+
+/// The parameters a case can vary in. Options only vary between cases
+/// across runs generated or compiled differently.
+static PARAMS: &[Param] = &[
+    ("types", |c| Some(c.results.num_types.to_string())),
+    ("fns", |c| Some(c.results.num_fns.to_string())),
+    ("structs", |c| Some(c.gen_opts.as_ref()?.structs.to_string())),
+    ("opt-level", |c| per_variant(&c.results, |o| o.opt_level.to_string())),
+    ("lto", |c| per_variant(&c.results, |o| format!("{:?}", o.lto))),
+];
+
+/// An option's value, or both variants' when --static-opts or
+/// --dynamic-opts set them apart
+fn per_variant(case: &CaseResults, value: fn(&CompileOpts) -> String) -> Option<String> {
+    let opts = &case.compile.as_ref()?.opts;
+    let s = value(&opts.for_variant(Dispatch::Static));
+    let d = value(&opts.for_variant(Dispatch::Dynamic));
+    Some(if s == d { s } else { format!("{}/{}", s, d) })
+}
+
+pub fn sensitivity(run_dirs: &[PathBuf]) -> Result<()> {
+    let mut cases = vec![];
+    for run_dir in run_dirs {
+        let generated = manifest::load(run_dir)?;
+        for results in results::load(run_dir)? {
+            let gen_opts = generated.iter()
+                .find(|g| (g.num_types, g.num_fns) == (results.num_types, results.num_fns))
+                .map(|g| g.gen_opts.clone());
+            cases.push(Case { results, gen_opts });
+        }
+    }
+    if cases.is_empty() {
+        bail!("no measured cases");
+    }
+
+    for metric in [Metric::RunTime, Metric::CompileTime, Metric::BinSize] {
+        // The gap as a log ratio, so 2x and 0.5x are equally far from
+        // parity
+        let gaps: Vec<(&Case, f64)> = cases.iter()
+            .filter_map(|c| {
+                let (s, d) = metric.values(&c.results)?;
+                if s == 0 || d == 0 {
+                    return None;
+                }
+                Some((c, (d as f64 / s as f64).ln()))
+            })
+            .collect();
+
+        println!("{} ({} cases)", metric, gaps.len());
+        let mut ranked: Vec<(&str, Option<f64>, usize)> = PARAMS.iter()
+            .map(|(name, param)| {
+                let groups = group(&gaps, *param);
+                (*name, explained(&groups), groups.len())
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.unwrap_or(-1.0).total_cmp(&a.1.unwrap_or(-1.0)));
+
+        for (rank, (name, share, values)) in ranked.iter().enumerate() {
+            match share {
+                _ if *values < 2 => {
+                    println!("  {}. {:<10} not varied", rank + 1, name);
+                }
+                Some(share) => {
+                    println!("  {}. {:<10} {:>5.1}% of the gap's variation ({} values)",
+                             rank + 1, name, share * 100.0, values);
+                }
+                None => {
+                    println!("  {}. {:<10} the gap doesn't vary", rank + 1, name);
+                }
+            }
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn group(gaps: &[(&Case, f64)],
+         param: fn(&Case) -> Option<String>) -> BTreeMap<String, Vec<f64>> {
+    let mut groups: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for (case, gap) in gaps {
+        if let Some(value) = param(case) {
+            groups.entry(value).or_default().push(*gap);
+        }
+    }
+    groups
+}
+
+/// The share of the total variance that lies between the groups (eta
+/// squared), or `None` when nothing varies
+fn explained(groups: &BTreeMap<String, Vec<f64>>) -> Option<f64> {
+    let all: Vec<f64> = groups.values().flatten().copied().collect();
+    if all.is_empty() {
+        return None;
+    }
+    let mean = all.iter().sum::<f64>() / all.len() as f64;
+    let total: f64 = all.iter().map(|x| (x - mean).powi(2)).sum();
+    if total == 0.0 {
+        return None;
+    }
+
+    let between: f64 = groups.values()
+        .map(|g| {
+            let group_mean = g.iter().sum::<f64>() / g.len() as f64;
+            g.len() as f64 * (group_mean - mean).powi(2)
+        })
+        .sum();
+
+    Some(between / total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::explained;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn explained_variance() {
+        let mut groups = BTreeMap::new();
+        groups.insert("a".to_string(), vec![1.0, 1.0]);
+        groups.insert("b".to_string(), vec![3.0, 3.0]);
+        assert_eq!(explained(&groups), Some(1.0));
+
+        groups.insert("b".to_string(), vec![1.0, 3.0]);
+        groups.insert("a".to_string(), vec![1.0, 3.0]);
+        assert_eq!(explained(&groups), Some(0.0));
+
+        groups.insert("b".to_string(), vec![2.0]);
+        groups.insert("a".to_string(), vec![2.0]);
+        assert_eq!(explained(&groups), None);
+    }
+}
@@ -0,0 +1,103 @@
+//! Which type appears at each of a case's call sites under `--skew`.
+//! Real programs rarely call every implementation equally often, and
+//! branch predictors and speculative devirtualization both do better
+//! when a few types dominate.
+
+use clap::ValueEnum;
+use std::iter;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Skew {
+    /// Every type once per fn
+    #[default]
+    Uniform,
+    /// Type k is called in proportion to 1/(k+1)
+    Zipf,
+    /// Every call site uses the first type
+    Single,
+}
+
+impl Skew {
+    pub fn is_uniform(&self) -> bool {
+        *self == Skew::Uniform
+    }
+}
+
+/// The type at each of a fn's `num_types` call sites in one pass of
+/// the loop, in type order
+pub fn call_types(num_types: u32, skew: Skew) -> Vec<u32> {
+    match skew {
+        Skew::Uniform => (0..num_types).collect(),
+        Skew::Single => vec![0; num_types as usize],
+        Skew::Zipf => {
+            let n = num_types as usize;
+            let weights: Vec<f64> = (0..n).map(|k| 1.0 / (k + 1) as f64).collect();
+            let total: f64 = weights.iter().sum();
+            let exact: Vec<f64> = weights.iter().map(|w| n as f64 * w / total).collect();
+            let mut counts: Vec<usize> = exact.iter().map(|e| e.floor() as usize).collect();
+
+            // The sites the floors leave over go to the largest
+            // remainders, lower types first on ties
+            let mut by_remainder: Vec<usize> = (0..n).collect();
+            by_remainder.sort_by(|&a, &b| {
+                (exact[b] - counts[b] as f64).total_cmp(&(exact[a] - counts[a] as f64))
+            });
+            let left = n - counts.iter().sum::<usize>();
+            for &k in by_remainder.iter().take(left) {
+                counts[k] += 1;
+            }
+
+            counts.iter().enumerate()
+                .flat_map(|(k, &count)| iter::repeat_n(k as u32, count))
+                .collect()
+        }
+    }
+}
+
+/// The type at each of one fn's or struct's `num_types` call sites
+/// under --site-types, cycling through `site_types` types starting at
+/// the site's own number, so together the sites still use every type.
+/// 0 is every type, skewed by `skew`.
+pub fn site_call_types(num_types: u32, site: u32, site_types: u32, skew: Skew) -> Vec<u32> {
+    if site_types == 0 || site_types >= num_types {
+        return call_types(num_types, skew);
+    }
+    let mut types: Vec<u32> = (0..num_types)
+        .map(|slot| (site + slot % site_types) % num_types)
+        .collect();
+    types.sort();
+    types
+}
+
+/// The types in order of how often `call_types` calls them, most often
+/// first, lower types first on ties
+pub fn by_frequency(num_types: u32, skew: Skew) -> Vec<u32> {
+    let sites = call_types(num_types, skew);
+    let mut types: Vec<u32> = (0..num_types).collect();
+    types.sort_by_key(|&t| std::cmp::Reverse(sites.iter().filter(|&&s| s == t).count()));
+    types
+}
+
+#[cfg(test)]
+mod test {
+    use super::{by_frequency, call_types, site_call_types, Skew};
+
+    #[test]
+    fn zipf_keeps_the_number_of_sites() {
+        for n in 0..20 {
+            assert_eq!(call_types(n, Skew::Zipf).len(), n as usize);
+        }
+        assert_eq!(call_types(3, Skew::Zipf), vec![0, 0, 1]);
+        assert_eq!(by_frequency(3, Skew::Zipf), vec![0, 1, 2]);
+        assert_eq!(call_types(3, Skew::Uniform), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn sites_see_site_types_types() {
+        assert_eq!(site_call_types(4, 0, 1, Skew::Uniform), vec![0, 0, 0, 0]);
+        assert_eq!(site_call_types(4, 3, 2, Skew::Uniform), vec![0, 0, 3, 3]);
+        assert_eq!(site_call_types(4, 1, 0, Skew::Uniform), vec![0, 1, 2, 3]);
+    }
+}
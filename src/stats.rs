@@ -0,0 +1,223 @@
+//! Summary statistics over repeated measurements.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stats {
+    pub samples: Vec<u64>,
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+impl Stats {
+    /// Panics if there are no samples
+    pub fn new(mut samples: Vec<u64>) -> Stats {
+        assert!(!samples.is_empty());
+        let unsorted = samples.clone();
+        samples.sort();
+
+        let n = samples.len();
+        let median = median(&samples);
+        let mean = samples.iter().sum::<u64>() as f64 / n as f64;
+        let variance = if n > 1 {
+            samples.iter()
+                .map(|&s| (s as f64 - mean).powi(2))
+                .sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+
+        Stats {
+            min: samples[0],
+            median,
+            max: samples[n - 1],
+            mean,
+            stddev: variance.sqrt(),
+            samples: unsorted,
+        }
+    }
+
+    /// The coefficient of variation, stddev relative to the mean, or
+    /// `None` when the mean is zero
+    pub fn cv(&self) -> Option<f64> {
+        if self.mean == 0.0 {
+            None
+        } else {
+            Some(self.stddev / self.mean)
+        }
+    }
+}
+
+/// The middle of sorted samples, or the mean of the middle two rounded
+/// down
+fn median(sorted: &[u64]) -> u64 {
+    let n = sorted.len();
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2
+    }
+}
+
+/// A point estimate with a confidence interval around it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Interval {
+    pub estimate: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+pub static BOOTSTRAP_RESAMPLES: usize = 2000;
+
+/// The ratio of the medians of two sets of samples, as the tables show
+/// them, with a 95% bootstrap confidence interval. Both sets are
+/// resampled with replacement from a fixed seed, so the same samples
+/// always give the same interval. `None` if either set is empty or the
+/// denominator's median is zero, in any resample too, as with run times
+/// that round to 0 ms.
+pub fn bootstrap_ratio(num: &[u64], den: &[u64]) -> Option<Interval> {
+    let middle = |s: &[u64]| {
+        let mut sorted = s.to_vec();
+        sorted.sort();
+        median(&sorted) as f64
+    };
+    if num.is_empty() || den.is_empty() || middle(den) == 0.0 {
+        return None;
+    }
+
+    let mut rng = XorShift::new(0);
+    let mut resample = |s: &[u64]| {
+        let picked: Vec<u64> = (0..s.len()).map(|_| s[rng.below(s.len())]).collect();
+        middle(&picked)
+    };
+    let mut ratios: Vec<f64> = (0..BOOTSTRAP_RESAMPLES)
+        .map(|_| {
+            let n = resample(num);
+            let d = resample(den);
+            if d == 0.0 { None } else { Some(n / d) }
+        })
+        .collect::<Option<_>>()?;
+
+    Some(Interval::around(middle(num) / middle(den), ratios))
+}
+
+impl Interval {
+    /// An estimate with the middle 95% of its resampled values
+    pub fn around(estimate: f64, mut resampled: Vec<f64>) -> Interval {
+        resampled.sort_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            resampled[((resampled.len() - 1) as f64 * p).round() as usize]
+        };
+        Interval {
+            estimate,
+            low: percentile(0.025),
+            high: percentile(0.975),
+        }
+    }
+}
+
+/// `n` of the items picked at random from `seed`, in their original
+/// order. The same seed always picks the same items.
+pub fn sample<T>(items: Vec<T>, n: usize, seed: u64) -> Vec<T> {
+    let mut rng = XorShift::new(seed);
+    let mut picks: Vec<usize> = (0..items.len()).collect();
+    let n = n.min(items.len());
+    for i in 0..n {
+        let j = i + rng.below(items.len() - i);
+        picks.swap(i, j);
+    }
+    picks.truncate(n);
+    picks.sort();
+    items.into_iter()
+        .enumerate()
+        .filter(|(i, _)| picks.binary_search(i).is_ok())
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// A small deterministic generator, which is all resampling needs
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> XorShift {
+        // Spread the seed's bits, never leaving the all-zero state,
+        // which xorshift can't leave
+        XorShift((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:.3} [{:.3}, {:.3}]", self.estimate, self.low, self.high)
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "min {} median {} max {} stddev {:.1} (n={})",
+               self.min, self.median, self.max, self.stddev,
+               self.samples.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bootstrap_ratio, sample, Stats};
+
+    #[test]
+    fn odd_and_even_medians() {
+        assert_eq!(Stats::new(vec![3, 1, 2]).median, 2);
+        assert_eq!(Stats::new(vec![4, 1, 3, 2]).median, 2);
+    }
+
+    #[test]
+    fn spread() {
+        let stats = Stats::new(vec![2, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!((stats.min, stats.max), (2, 9));
+        assert_eq!(stats.mean, 5.0);
+        assert!((stats.stddev - 2.138).abs() < 0.001);
+        assert_eq!(Stats::new(vec![7]).stddev, 0.0);
+    }
+
+    #[test]
+    fn coefficient_of_variation() {
+        let stats = Stats::new(vec![2, 4, 4, 4, 5, 5, 7, 9]);
+        assert!((stats.cv().unwrap() - 0.4276).abs() < 0.001);
+        assert_eq!(Stats::new(vec![0, 0]).cv(), None);
+    }
+
+    #[test]
+    fn bootstrap_intervals_contain_the_estimate() {
+        let ratio = bootstrap_ratio(&[20, 22, 19, 21, 23], &[10, 11, 10, 9, 10]).unwrap();
+        assert!((ratio.estimate - 2.1).abs() < 0.001);
+        assert!(ratio.low < ratio.estimate && ratio.estimate < ratio.high);
+
+        // Medians, not means, so the outlier doesn't move it
+        let skewed = bootstrap_ratio(&[10, 10, 100], &[5, 5, 5]).unwrap();
+        assert_eq!(skewed.estimate, 2.0);
+
+        let exact = bootstrap_ratio(&[6, 6], &[3, 3]).unwrap();
+        assert_eq!((exact.low, exact.estimate, exact.high), (2.0, 2.0, 2.0));
+        assert!(bootstrap_ratio(&[1], &[0]).is_none());
+        assert!(bootstrap_ratio(&[1, 1, 0, 1], &[0, 0, 1, 0]).is_none());
+    }
+
+    #[test]
+    fn samples_are_reproducible_and_ordered() {
+        let grid: Vec<u32> = (0..100).collect();
+        let picked = sample(grid.clone(), 10, 7);
+        assert_eq!(picked.len(), 10);
+        assert!(picked.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(picked, sample(grid.clone(), 10, 7));
+        assert_ne!(picked, sample(grid.clone(), 10, 8));
+        assert_eq!(sample(grid.clone(), 200, 7), grid);
+    }
+}
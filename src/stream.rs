@@ -0,0 +1,107 @@
+//! Streaming a record of each case as a sweep finishes it, under
+//! `--stream`, so a long sweep can be watched and partly analyzed by
+//! other tools while it's still running. Records go to stdout unless
+//! `--stream-file` names a file. Streamed to stdout, they have it to
+//! themselves: everything else the process and the tools it runs print
+//! there goes to stderr instead.
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use crate::error::Phase;
+use crate::results::{self, CaseResults};
+use crate::{interrupt, CaseConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One JSON object per line
+    Jsonl,
+    /// One YAML document per case, each starting with `---`
+    Yaml,
+}
+
+struct Stream {
+    format: Format,
+    /// Appended to, so each phase of a sweep adds to the same file, or
+    /// the process's original stdout
+    file: File,
+}
+
+static STREAM: OnceLock<Mutex<Stream>> = OnceLock::new();
+
+#[derive(Serialize)]
+struct Record {
+    phase: Phase,
+    num_types: u32,
+    num_fns: u32,
+    /// Why the case failed, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The case's results so far, after compiling or running it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results: Option<CaseResults>,
+}
+
+/// Streams records for the rest of the process
+pub fn init(format: Format, path: Option<&Path>) -> Result<()> {
+    let file = match path {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| anyhow!("can't open {}: {}", path.display(), e))?,
+        None => take_stdout()?,
+    };
+    let _ = STREAM.set(Mutex::new(Stream { format, file }));
+
+    Ok(())
+}
+
+/// Keeps stdout for the records, pointing the process's fd 1 at stderr
+/// so progress lines and child processes' output go there
+fn take_stdout() -> Result<File> {
+    io::stdout().flush()?;
+    let fd = unsafe { libc::fcntl(libc::STDOUT_FILENO, libc::F_DUPFD_CLOEXEC, 3) };
+    if fd == -1 || unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } == -1 {
+        return Err(anyhow!("can't move progress off stdout: {}",
+                           io::Error::last_os_error()));
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Streams a record of a case a sweep has finished a phase of, passing
+/// its result through. A case cut short by Ctrl-C isn't finished.
+pub fn case(config: &CaseConfig, phase: Phase, result: Result<()>) -> Result<()> {
+    let stream = match STREAM.get() {
+        Some(stream) => stream,
+        None => return result,
+    };
+    if result.is_err() && interrupt::interrupted() {
+        return result;
+    }
+
+    let results = match phase {
+        Phase::Generation => None,
+        _ => results::find_case(config)?,
+    };
+    let record = Record {
+        phase,
+        num_types: config.num_types,
+        num_fns: config.num_fns,
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        results,
+    };
+
+    let mut stream = stream.lock().expect("stream lock poisoned");
+    let text = match stream.format {
+        Format::Jsonl => format!("{}\n", serde_json::to_string(&record)?),
+        Format::Yaml => format!("---\n{}", serde_yaml::to_string(&record)?),
+    };
+    // One write per record, so parallel workers' records don't interleave
+    stream.file.write_all(text.as_bytes())?;
+
+    result
+}
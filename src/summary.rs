@@ -0,0 +1,310 @@
+//! A small summary written beside each case's files, with the
+//! parameters, flags and latest measurements, so a run directory makes
+//! sense when browsed long after the terminal output is gone.
+
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::manifest::{self, CaseStatus};
+use crate::results::{self, CompileResults, MemLimitOutcome, RunResults, VariantCompileResults};
+use crate::{CaseConfig, CompileOpts, Dispatch, GenOpts, Lang};
+
+#[derive(Serialize)]
+struct Summary {
+    num_types: u32,
+    num_fns: u32,
+    status: CaseStatus,
+    gen_opts: GenOpts,
+    compile: Option<CompileResults>,
+    run: Option<RunResults>,
+    mem_limit_exceeded: Option<MemLimitOutcome>,
+}
+
+fn summary_paths(config: &CaseConfig) -> (PathBuf, PathBuf) {
+    let stem = format!("summary-{:04}-{:04}", config.num_types, config.num_fns);
+    (config.outdir.join(format!("{}.txt", stem)),
+     config.outdir.join(format!("{}.json", stem)))
+}
+
+/// Rewrites the case's summary from the manifest and results
+pub fn write(config: &CaseConfig) -> Result<()> {
+    let case = manifest::find_case(config)?;
+    let results = results::find_case(config)?;
+    let (compile, run, mem_limit_exceeded) = match results {
+        Some(r) => (r.compile, r.run, r.mem_limit_exceeded),
+        None => (None, None, None),
+    };
+    // Measurements of a case since regenerated are stale
+    let compile = compile.filter(|_| case.status >= CaseStatus::Compiled);
+    let run = run.filter(|_| case.status >= CaseStatus::Ran);
+
+    let summary = Summary {
+        num_types: config.num_types,
+        num_fns: config.num_fns,
+        status: case.status,
+        gen_opts: case.gen_opts,
+        compile,
+        run,
+        mem_limit_exceeded,
+    };
+
+    let (txt_path, json_path) = summary_paths(config);
+    fs::write(txt_path, render(&summary))?;
+    fs::write(json_path, serde_json::to_string_pretty(&summary)?)?;
+
+    Ok(())
+}
+
+fn render(s: &Summary) -> String {
+    let mut out = String::new();
+    let mut line = |label: &str, value: &dyn std::fmt::Display| {
+        let _ = writeln!(out, "{:<16}{}", label, value);
+    };
+
+    line("case", &format!("{} types / {} fns", s.num_types, s.num_fns));
+    line("status", &format!("{:?}", s.status));
+    line("generated with", &flags_or_default(gen_flags(&s.gen_opts)));
+    if let Some(ref over) = s.mem_limit_exceeded {
+        line("over mem limit", &format!("{} bytes, during {}", over.limit, over.phase));
+    }
+
+    if let Some(ref c) = s.compile {
+        line("compiled with", &flags_or_default(compile_flags(&c.opts)));
+        for (num, den) in Dispatch::RATIOS {
+            if let Some(ratio) = c.compile_time_ratio(num, den) {
+                line("compile ratio", &format!("{} ({}/{}, 95% CI)", ratio, num, den));
+            }
+        }
+        for (dispatch, v) in c.variants.iter() {
+            for command in &v.commands {
+                line(&format!("{} build", dispatch), command);
+            }
+        }
+    }
+    if let Some(ref r) = s.run {
+        if let Some(ref host) = r.host {
+            line("ran on", &format!("{} ({})", host.hostname, host.dest));
+        }
+        if let Some(ref image) = r.container {
+            line("ran in", image);
+        }
+        if r.sandboxed {
+            line("ran in", &"a bubblewrap sandbox");
+        }
+        if r.emulated {
+            line("ran under", &"qemu (times are emulated)");
+        }
+        if r.cold_cache {
+            line("ran with", &"the binaries evicted from the page cache");
+        }
+        for (num, den) in Dispatch::RATIOS {
+            if let Some(ratio) = r.run_time_ratio(num, den) {
+                line("run-time ratio", &format!("{} ({}/{}, 95% CI)", ratio, num, den));
+            }
+        }
+    }
+
+    let _ = writeln!(out);
+    if s.compile.is_none() && s.run.is_none() {
+        let _ = writeln!(out, "(not measured yet)");
+        return out;
+    }
+    let variants = s.gen_opts.variants();
+    let _ = write!(out, "{:<16}", "");
+    for dispatch in &variants {
+        let _ = write!(out, "{:>12}", dispatch.to_string());
+    }
+    let _ = writeln!(out);
+    let mut row = |label: &str, value: &dyn Fn(Dispatch) -> Option<u64>| {
+        let values: Option<Vec<u64>> = variants.iter().map(|&d| value(d)).collect();
+        if let Some(values) = values {
+            let _ = write!(out, "{:<16}", label);
+            for v in values {
+                let _ = write!(out, "{:>12}", v);
+            }
+            let _ = writeln!(out);
+        }
+    };
+    if let Some(ref c) = s.compile {
+        let mut row = |label, f: fn(&VariantCompileResults) -> Option<u64>| {
+            row(label, &|d| f(c.variant(d)?))
+        };
+        row("compile-time-ms", |v| Some(v.compile_time_ms));
+        row("codegen-time-ms", |v| v.codegen_time_ms);
+        row("link-time-ms", |v| v.link_time_ms);
+        row("rebuild-time-ms", |v| v.rebuild_time_ms);
+        row("bin-size", |v| Some(v.bin_size));
+        row("method-count", |v| Some(v.method_count as u64));
+        row("fn-count", |v| Some(v.fn_count as u64));
+        row("mono-fns", |v| v.mono_items.as_ref().map(|m| m.generated as u64));
+    }
+    if let Some(ref r) = s.run {
+        row("instructions", &|d| r.variant(d)?.instructions);
+        row("run-time-ms", &|d| Some(r.variant(d)?.run_time_ms));
+        row("launch-time-us", &|d| Some(r.variant(d)?.launch_time_stats.as_ref()?.median));
+        row("baseline-run-ms", &|d| Some(r.baseline.as_ref()?.get(d)?.run_time_ms));
+    }
+    out
+}
+
+fn flags_or_default(flags: Vec<String>) -> String {
+    if flags.is_empty() {
+        "default options".to_string()
+    } else {
+        flags.join(" ")
+    }
+}
+
+pub fn gen_flags(opts: &GenOpts) -> Vec<String> {
+    let mut flags = vec![];
+    let switches = [
+        (opts.no_inline, "--no-inline"),
+        (opts.no_dedup, "--no-dedup"),
+        (opts.predictable, "--predictable"),
+        (opts.rustfmt, "--rustfmt"),
+        (opts.annotate, "--annotate"),
+        (opts.lang == Lang::Cpp, "--lang cpp"),
+        (opts.keep_symbols, "--keep-symbols"),
+        (opts.holder, "--holder"),
+        (opts.assoc_type, "--assoc-type"),
+        (opts.generic_method, "--generic-method"),
+        (opts.object_safety_stress, "--object-safety-stress"),
+        (opts.const_generic, "--const-generic"),
+        (opts.specialization, "--specialization"),
+        (opts.visitor, "--visitor"),
+        (opts.state_machine, "--state-machine"),
+        (opts.cold_start, "--cold-start"),
+        (opts.vary_args, "--vary-args"),
+        (opts.checksum, "--checksum"),
+        (opts.concrete, "--concrete"),
+    ];
+    for (on, flag) in switches.iter() {
+        if *on {
+            flags.push(flag.to_string());
+        }
+    }
+    if opts.structs > 0 {
+        flags.push(format!("--structs {}", opts.structs));
+    }
+    if !opts.dynamic_via.is_vtable() {
+        flags.push(format!("--dynamic-via {}", opts.dynamic_via));
+    }
+    if !opts.call_shape.is_inline_loop() {
+        flags.push(format!("--call-shape {}", opts.call_shape));
+    }
+    if !opts.blackbox.is_std() {
+        flags.push(format!("--blackbox {}", opts.blackbox));
+    }
+    if !opts.static_via.is_generics() {
+        flags.push(format!("--static-via {}", opts.static_via));
+    }
+    if !opts.branch_order.is_likely_first() {
+        flags.push(format!("--branch-order {}", opts.branch_order));
+    }
+    if !opts.skew.is_uniform() {
+        flags.push(format!("--skew {}", opts.skew));
+    }
+    if opts.site_types > 0 {
+        flags.push(format!("--site-types {}", opts.site_types));
+    }
+    if opts.trait_depth > 0 {
+        flags.push(format!("--trait-depth {}", opts.trait_depth));
+    }
+    if opts.upcast {
+        flags.push("--upcast".to_string());
+    }
+    if opts.threads > 0 {
+        flags.push(format!("--threads {}", opts.threads));
+    }
+    if opts.tasks > 0 {
+        flags.push(format!("--tasks {}", opts.tasks));
+    }
+    if let Some(path) = opts.errors {
+        flags.push(format!("--errors {}", path));
+    }
+    if opts.components > 0 {
+        flags.push(format!("--components {}", opts.components));
+    }
+    flags
+}
+
+pub fn compile_flags(opts: &CompileOpts) -> Vec<String> {
+    let mut flags = vec![format!("--opt-level {}", opts.opt_level)];
+    if opts.asm {
+        flags.push("--asm".to_string());
+    }
+    if opts.measure_compile {
+        flags.push(format!("--measure-compile --repeat {}", opts.repeat));
+    }
+    if opts.drop_caches {
+        flags.push("--drop-caches".to_string());
+    }
+    if opts.incremental {
+        flags.push("--incremental".to_string());
+    }
+    if opts.split_link {
+        flags.push("--split-link".to_string());
+    }
+    if opts.mono_items {
+        flags.push("--mono-items".to_string());
+    }
+    if opts.pgo {
+        flags.push("--pgo".to_string());
+    }
+    if let Some(lto) = opts.lto {
+        flags.push(format!("--lto {}", lto));
+    }
+    flags.push(format!("--codegen-backend {}", opts.codegen_backend));
+    if opts.no_pie {
+        flags.push("--no-pie".to_string());
+    } else if let Some(model) = opts.relocation_model {
+        flags.push(format!("--relocation-model {}", model));
+    }
+    if let Some(n) = opts.codegen_units {
+        flags.push(format!("--codegen-units {}", n));
+    }
+    if let Some(linking) = opts.linking {
+        flags.push(format!("--linking {}", linking));
+    }
+    if let Some(ref target) = opts.target {
+        flags.push(format!("--target {}", target));
+    }
+    if let Some(ref cpu) = opts.target_cpu {
+        flags.push(format!("--target-cpu {}", cpu));
+    }
+    for feature in &opts.target_feature {
+        flags.push(format!("--target-feature {}", feature));
+    }
+    if opts.compare_target {
+        flags.push("--compare-target".to_string());
+    }
+    if opts.compare_mitigations {
+        flags.push("--compare-mitigations".to_string());
+    } else if opts.mitigations {
+        flags.push("--mitigations".to_string());
+    }
+    if let Some(ref image) = opts.container {
+        flags.push(format!("--container {}", image));
+    }
+    if opts.deterministic {
+        flags.push("--deterministic".to_string());
+    }
+    if let Some(limit) = opts.mem_limit {
+        flags.push(format!("--mem-limit {}", limit.0));
+    }
+    if opts.retries > 0 {
+        flags.push(format!("--retries {}", opts.retries));
+    }
+    if opts.cxx != "c++" {
+        flags.push(format!("--cxx {}", opts.cxx));
+    }
+    if let Some(ref overrides) = opts.static_opts {
+        flags.push(format!("--static-opts={}", overrides));
+    }
+    if let Some(ref overrides) = opts.dynamic_opts {
+        flags.push(format!("--dynamic-opts={}", overrides));
+    }
+    flags
+}
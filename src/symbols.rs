@@ -0,0 +1,257 @@
+//! Accounting for the generated methods and fns in a case's binaries:
+//! how many survived optimization, how many were merged with others,
+//! and which symbols only one of the binaries has, so the difference
+//! can be traced to the instantiations behind it.
+
+use anyhow::{Result, bail};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::CaseConfig;
+
+/// Symbols of the generated methods and fns in a binary
+pub struct SymbolCounts {
+    pub methods: usize,
+    pub fns: usize,
+    /// Distinct addresses of those symbols. Fewer addresses than
+    /// symbols means identical bodies were folded together.
+    pub method_addrs: usize,
+    pub fn_addrs: usize,
+}
+
+/// How far the symbols fall short of what the program defines
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SymbolLoss {
+    pub methods_eliminated: usize,
+    pub fns_eliminated: usize,
+    pub methods_merged: usize,
+    pub fns_merged: usize,
+}
+
+impl SymbolCounts {
+    /// Compares against the methods and fns (counting each generic
+    /// instantiation) the program defines. When they were all kept
+    /// with --keep-symbols none can have been removed, so missing
+    /// symbols were merged into another body without an alias.
+    pub fn loss(&self, methods: u32, fns: u32, kept: bool) -> SymbolLoss {
+        let methods_missing = (methods as usize).saturating_sub(self.methods);
+        let fns_missing = (fns as usize).saturating_sub(self.fns);
+        let methods_merged = self.methods - self.method_addrs;
+        let fns_merged = self.fns - self.fn_addrs;
+
+        if kept {
+            SymbolLoss {
+                methods_eliminated: 0,
+                fns_eliminated: 0,
+                methods_merged: methods_merged + methods_missing,
+                fns_merged: fns_merged + fns_missing,
+            }
+        } else {
+            SymbolLoss {
+                methods_eliminated: methods_missing,
+                fns_eliminated: fns_missing,
+                methods_merged,
+                fns_merged,
+            }
+        }
+    }
+}
+
+pub fn count(bin: &Path) -> Result<SymbolCounts> {
+    let output = Command::new("nm")
+        .arg(bin)
+        .output()?;
+
+    if !output.status.success() {
+        bail!("running nm failed");
+    }
+
+    let out_str = String::from_utf8_lossy(&output.stdout);
+    let mut methods = HashSet::new();
+    let mut fns = HashSet::new();
+    let mut method_addrs = HashSet::new();
+    let mut fn_addrs = HashSet::new();
+    for line in out_str.lines() {
+        let mut parts = line.splitn(3, ' ');
+        let (addr, name) = match (parts.next(), parts.nth(1)) {
+            (Some(addr), Some(name)) => (addr, name),
+            _ => continue,
+        };
+        if name.contains("do_io_m") {
+            methods.insert(name);
+            method_addrs.insert(addr);
+        } else if name.contains("do_io_f") {
+            fns.insert(name);
+            fn_addrs.insert(addr);
+        }
+    }
+
+    Ok(SymbolCounts {
+        methods: methods.len(),
+        fns: fns.len(),
+        method_addrs: method_addrs.len(),
+        fn_addrs: fn_addrs.len(),
+    })
+}
+
+pub struct SymbolDiff {
+    pub static_only: BTreeSet<String>,
+    pub dynamic_only: BTreeSet<String>,
+}
+
+/// Generated methods and fns whose bodies were folded into one
+struct Fold {
+    size: Option<u64>,
+    names: Vec<String>,
+}
+
+struct CodeSymbol {
+    addr: String,
+    size: Option<u64>,
+    name: String,
+}
+
+fn diff_path(config: &CaseConfig) -> PathBuf {
+    config.outdir.join(format!("symbols-diff-{:04}-{:04}.txt",
+                               config.num_types, config.num_fns))
+}
+
+/// Diffs the binaries' symbols and writes the report, with the bodies
+/// each binary folded together, beside them
+pub fn write_diff(config: &CaseConfig, static_bin: &Path,
+                  dynamic_bin: &Path) -> Result<SymbolDiff> {
+    let static_syms = code_symbols(static_bin)?;
+    let dynamic_syms = code_symbols(dynamic_bin)?;
+    let static_names = names(&static_syms);
+    let dynamic_names = names(&dynamic_syms);
+    let diff = SymbolDiff {
+        static_only: static_names.difference(&dynamic_names).cloned().collect(),
+        dynamic_only: dynamic_names.difference(&static_names).cloned().collect(),
+    };
+
+    let mut out = String::new();
+    writeln!(out, "symbols in only one binary of case {} types / {} fns",
+             config.num_types, config.num_fns)?;
+    write_section(&mut out, "static", &diff.static_only)?;
+    write_section(&mut out, "dynamic", &diff.dynamic_only)?;
+    write_folds(&mut out, "static", &folds(&static_syms))?;
+    write_folds(&mut out, "dynamic", &folds(&dynamic_syms))?;
+    fs::write(diff_path(config), out)?;
+
+    Ok(diff)
+}
+
+fn names(symbols: &[CodeSymbol]) -> BTreeSet<String> {
+    symbols.iter().map(|s| s.name.clone()).collect()
+}
+
+/// Generated symbols sharing an address. Bodies folded without leaving
+/// an alias behind can't be seen here, only in the symbol counts.
+fn folds(symbols: &[CodeSymbol]) -> Vec<Fold> {
+    let mut by_addr: BTreeMap<&str, Fold> = BTreeMap::new();
+    for symbol in symbols.iter().filter(|s| s.name.contains("do_io_")) {
+        let fold = by_addr.entry(&symbol.addr).or_insert(Fold {
+            size: symbol.size,
+            names: vec![],
+        });
+        fold.names.push(symbol.name.clone());
+    }
+
+    by_addr.into_values().filter(|f| f.names.len() > 1).collect()
+}
+
+fn write_folds(out: &mut String, variant: &str, folds: &[Fold]) -> Result<()> {
+    writeln!(out)?;
+    writeln!(out, "folded in {} ({} shared bodies):", variant, folds.len())?;
+
+    for fold in folds {
+        let size = fold.size.map_or("unknown size".to_string(),
+                                    |s| format!("{} bytes", s));
+        writeln!(out, "  {} symbols, {}", fold.names.len(), size)?;
+        for name in &fold.names {
+            writeln!(out, "    {}", name)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_section(out: &mut String, variant: &str,
+                 symbols: &BTreeSet<String>) -> Result<()> {
+    writeln!(out)?;
+    writeln!(out, "only in {} ({}):", variant, symbols.len())?;
+
+    for (name, instances) in group_instantiations(symbols) {
+        if instances.len() == 1 && instances[0] == name {
+            writeln!(out, "  {}", name)?;
+        } else {
+            writeln!(out, "  {} ({} instantiations)", name, instances.len())?;
+            for instance in instances {
+                writeln!(out, "    {}", instance)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Groups generic instantiations, like `crate::do_io_f0::<crate::T1>`,
+/// under the name before their parameters
+fn group_instantiations(symbols: &BTreeSet<String>) -> BTreeMap<String, Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for symbol in symbols {
+        let name = match symbol.find('<') {
+            Some(i) => symbol[..i].trim_end_matches("::"),
+            None => symbol,
+        };
+        groups.entry(name.to_string()).or_default().push(symbol.clone());
+    }
+    groups
+}
+
+/// The demangled code symbols of a binary, with the crate name, which
+/// differs between the static and dynamic programs, replaced by `crate`
+fn code_symbols(bin: &Path) -> Result<Vec<CodeSymbol>> {
+    let output = Command::new("nm")
+        .arg("--demangle")
+        .arg("--print-size")
+        .arg(bin)
+        .output()?;
+
+    if !output.status.success() {
+        bail!("running nm failed");
+    }
+
+    let crate_name = bin.file_stem()
+        .map(|s| s.to_string_lossy().replace('-', "_"))
+        .unwrap_or_default();
+    let crate_prefix = format!("{}::", crate_name);
+
+    let out_str = String::from_utf8_lossy(&output.stdout);
+    let symbols = out_str.lines().filter_map(|line| {
+        // Symbols without a size leave that column out
+        let parts: Vec<&str> = line.splitn(4, ' ').collect();
+        let (addr, size, kind, name) = match parts[..] {
+            [addr, size, kind, name] if kind.len() == 1 => {
+                (addr, u64::from_str_radix(size, 16).ok(), kind, name.to_string())
+            }
+            [addr, kind, ..] if kind.len() == 1 => {
+                (addr, None, kind, parts[2..].join(" "))
+            }
+            _ => return None,
+        };
+        if !matches!(kind, "t" | "T" | "w" | "W") {
+            return None;
+        }
+        Some(CodeSymbol {
+            addr: addr.to_string(),
+            size,
+            name: name.replace(&crate_prefix, "crate::"),
+        })
+    });
+
+    Ok(symbols.collect())
+}
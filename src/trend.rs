@@ -0,0 +1,155 @@
+//! Trends in a ratio over the grid, for sweeps that measured only a
+//! sample of it. The log of the ratio is fitted by least squares as a
+//! plane over the log of each axis, so the trend reads as the ratio at
+//! one type and one fn, and the factor it changes by each time the
+//! types or fns double. The cases are resampled from a fixed seed for
+//! the uncertainty on each, as `stats::bootstrap_ratio` does for runs.
+
+use crate::stats::{Interval, XorShift, BOOTSTRAP_RESAMPLES};
+
+/// A case, as types and fns, and its ratio
+pub type Point = ((u32, u32), f64);
+
+pub struct Trend {
+    /// How many cases were fitted
+    pub cases: usize,
+    /// The ratio at 1 type and 1 fn
+    pub at_one: Interval,
+    /// What the ratio is multiplied by each time the types double, or
+    /// `None` if the cases all have the same number
+    pub per_types_doubling: Option<Interval>,
+    /// Likewise for fns
+    pub per_fns_doubling: Option<Interval>,
+}
+
+/// The trend through the points, skipping any with no types, no fns
+/// or a zero ratio, which have no log. `None` if there aren't enough
+/// left to fit.
+pub fn fit(points: &[Point]) -> Option<Trend> {
+    let points: Vec<Point> = points.iter().copied()
+        .filter(|&((t, f), r)| t > 0 && f > 0 && r > 0.0)
+        .collect();
+    let varies = |axis: fn(&Point) -> u32| {
+        points.iter().any(|p| axis(p) != axis(&points[0]))
+    };
+    let terms = Terms {
+        types: !points.is_empty() && varies(|p| (p.0).0),
+        fns: !points.is_empty() && varies(|p| (p.0).1),
+    };
+    let estimate = terms.solve(&points)?;
+
+    let mut rng = XorShift::new(0);
+    let resamples: Vec<[f64; 3]> = (0..BOOTSTRAP_RESAMPLES)
+        .filter_map(|_| {
+            let resample: Vec<Point> = (0..points.len())
+                .map(|_| points[rng.below(points.len())])
+                .collect();
+            // Resamples that lost an axis's spread don't say anything
+            // about it
+            terms.solve(&resample)
+        })
+        .collect();
+    let interval = |i: usize| {
+        let mut resampled: Vec<f64> = resamples.iter().map(|c| c[i].exp()).collect();
+        if resampled.is_empty() {
+            resampled.push(estimate[i].exp());
+        }
+        Interval::around(estimate[i].exp(), resampled)
+    };
+
+    Some(Trend {
+        cases: points.len(),
+        at_one: interval(0),
+        per_types_doubling: if terms.types { Some(interval(1)) } else { None },
+        per_fns_doubling: if terms.fns { Some(interval(2)) } else { None },
+    })
+}
+
+/// Which axes the fit has a term for, besides the constant
+#[derive(Clone, Copy)]
+struct Terms {
+    types: bool,
+    fns: bool,
+}
+
+impl Terms {
+    /// The least-squares coefficients of `ln ratio = a + b log2 types +
+    /// c log2 fns`, leaving out the terms not fitted as zero. `None` if
+    /// the points don't pin them all down.
+    fn solve(self, points: &[Point]) -> Option<[f64; 3]> {
+        let used: Vec<usize> = [true, self.types, self.fns].iter()
+            .enumerate()
+            .filter(|&(_, &used)| used)
+            .map(|(i, _)| i)
+            .collect();
+        let k = used.len();
+        if points.len() < k {
+            return None;
+        }
+
+        // The normal equations, as an augmented matrix
+        let mut m = vec![vec![0.0; k + 1]; k];
+        for &((t, f), r) in points {
+            let all = [1.0, (t as f64).log2(), (f as f64).log2()];
+            let x: Vec<f64> = used.iter().map(|&i| all[i]).collect();
+            for row in 0..k {
+                for col in 0..k {
+                    m[row][col] += x[row] * x[col];
+                }
+                m[row][k] += x[row] * r.ln();
+            }
+        }
+
+        // Gaussian elimination with partial pivoting
+        for col in 0..k {
+            let pivot = (col..k).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+            if m[pivot][col].abs() < 1e-9 {
+                return None;
+            }
+            m.swap(col, pivot);
+            let pivot_row = m[col].clone();
+            for (row, values) in m.iter_mut().enumerate() {
+                if row != col {
+                    let factor = values[col] / pivot_row[col];
+                    for (value, p) in values.iter_mut().zip(&pivot_row).skip(col) {
+                        *value -= factor * p;
+                    }
+                }
+            }
+        }
+
+        let mut coefficients = [0.0; 3];
+        for (row, &i) in used.iter().enumerate() {
+            coefficients[i] = m[row][k] / m[row][row];
+        }
+        Some(coefficients)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fit;
+
+    #[test]
+    fn recovers_a_plane() {
+        // The ratio starts at 0.5 and doubles with the types, but
+        // doesn't care about fns
+        let points: Vec<_> = [(1, 1), (2, 8), (4, 2), (8, 4), (16, 1), (32, 16)].iter()
+            .map(|&(t, f)| ((t, f), 0.5 * t as f64))
+            .collect();
+        let trend = fit(&points).unwrap();
+        assert_eq!(trend.cases, 6);
+        assert!((trend.at_one.estimate - 0.5).abs() < 1e-9);
+        let types = trend.per_types_doubling.unwrap();
+        assert!((types.estimate - 2.0).abs() < 1e-9);
+        assert!((types.low - 2.0).abs() < 1e-9 && (types.high - 2.0).abs() < 1e-9);
+        assert!((trend.per_fns_doubling.unwrap().estimate - 1.0).abs() < 1e-9);
+
+        // A single row of fns has no fns trend, and zero fns no log
+        let row = [((1, 4), 1.0), ((4, 4), 3.0), ((8, 0), 2.0)];
+        let trend = fit(&row).unwrap();
+        assert_eq!(trend.cases, 2);
+        assert!(trend.per_fns_doubling.is_none());
+        assert!(fit(&[]).is_none());
+    }
+}
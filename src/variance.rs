@@ -0,0 +1,114 @@
+//! Run-to-run variance across repeated sweeps of the same cases, to
+//! show which metrics this machine measures consistently and how many
+//! repetitions the others need.
+
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::results::{self, CaseResults};
+use crate::Dispatch;
+use crate::stats::Stats;
+
+pub struct VarianceConfig {
+    pub run_dirs: Vec<PathBuf>,
+    /// The largest coefficient of variation, in percent, counted as stable
+    pub max_cv: f64,
+    /// The precision, in percent, to estimate the repetitions needed for
+    pub precision: f64,
+}
+
+type Metric = (&'static str, fn(&CaseResults, Dispatch) -> Option<u64>);
+
+static METRICS: &[Metric] = &[
+    ("compile-time-ms", |c, d| Some(c.compile.as_ref()?.variant(d)?.compile_time_ms)),
+    ("bin-size", |c, d| Some(c.compile.as_ref()?.variant(d)?.bin_size)),
+    ("instructions", |c, d| c.run.as_ref()?.variant(d)?.instructions),
+    ("run-time-ms", |c, d| Some(c.run.as_ref()?.variant(d)?.run_time_ms)),
+];
+
+pub fn variance(config: VarianceConfig) -> Result<()> {
+    if config.run_dirs.len() < 2 {
+        bail!("variance needs at least two runs of the same sweep");
+    }
+
+    // Each case's results in every run that has it
+    let mut cases: BTreeMap<(u32, u32), Vec<CaseResults>> = BTreeMap::new();
+    for run_dir in &config.run_dirs {
+        for case in results::load(run_dir)? {
+            cases.entry((case.num_types, case.num_fns)).or_default().push(case);
+        }
+    }
+    cases.retain(|_, runs| runs.len() == config.run_dirs.len());
+    if cases.is_empty() {
+        bail!("no case has results in all {} runs", config.run_dirs.len());
+    }
+
+    // Per metric, the worst of the variants' CVs for each case
+    let mut worst: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+
+    for ((num_types, num_fns), runs) in &cases {
+        println!("case {} types / {} fns ({} runs)", num_types, num_fns, runs.len());
+        let variants = runs[0].variants();
+        print!("{:<16}", "");
+        for dispatch in &variants {
+            print!("{:>12}", format!("{}-cv", dispatch));
+        }
+        println!();
+        for (label, metric) in METRICS {
+            let cvs: Option<Vec<f64>> = variants.iter()
+                .map(|&d| {
+                    let values = runs.iter().map(|c| metric(c, d)).collect::<Option<_>>()?;
+                    Some(Stats::new(values).cv().unwrap_or(0.0) * 100.0)
+                })
+                .collect();
+            let cvs = match cvs {
+                Some(cvs) => cvs,
+                None => continue,
+            };
+            print!("{:<16}", label);
+            for cv in &cvs {
+                print!("{:>11.1}%", cv);
+            }
+            println!();
+            worst.entry(label).or_default().push(cvs.iter().copied().fold(0.0, f64::max));
+        }
+        for dispatch in variants {
+            let digests: Option<Vec<_>> = runs.iter()
+                .map(|c| c.compile.as_ref()?.variant(dispatch)?.bin_sha256.as_ref())
+                .collect();
+            if digests.is_some_and(|d| d.windows(2).any(|w| w[0] != w[1])) {
+                println!("the {} binary differs between runs", dispatch);
+            }
+        }
+        println!();
+    }
+
+    println!("{:<16}{:>12}{:>12}{:>16}", "", "median-cv", "stable",
+             format!("reps for {}%", config.precision));
+    for (label, _) in METRICS {
+        let cvs = match worst.get(label) {
+            Some(cvs) => cvs,
+            None => continue,
+        };
+        let mut sorted = cvs.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[sorted.len() / 2];
+        let stable = cvs.iter().filter(|&&cv| cv <= config.max_cv).count();
+        println!("{:<16}{:>11.1}%{:>12}{:>16}", label, median,
+                 format!("{}/{}", stable, cvs.len()),
+                 repetitions(median, config.precision));
+    }
+    println!();
+    println!("Stable cases vary by at most {}% between runs. Repetitions are \
+              how many runs put the mean within {}% at 95% confidence.",
+             config.max_cv, config.precision);
+
+    Ok(())
+}
+
+/// Runs needed for a 95% confidence interval of the mean within
+/// `precision` percent, given a CV in percent
+fn repetitions(cv: f64, precision: f64) -> u64 {
+    ((1.96 * cv / precision).powi(2).ceil() as u64).max(1)
+}
@@ -0,0 +1,129 @@
+//! Compile options for one variant only, given to `--static-opts` and
+//! `--dynamic-opts`, for comparing builds set up differently, like
+//! static with fat LTO against dynamic without.
+
+use anyhow::{Error, Result, anyhow};
+use clap::Parser;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Backend, CompileOpts, Lto, OptLevel, RelocModel};
+
+/// The flags as given, separated by whitespace or commas, like
+/// `"--lto fat --opt-level 3"`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct VariantOpts {
+    flags: Vec<String>,
+    args: VariantArgs,
+}
+
+/// The compile options that can differ between the variants
+#[derive(Debug, Clone, Parser)]
+#[command(no_binary_name = true)]
+struct VariantArgs {
+    #[arg(long, value_enum)]
+    opt_level: Option<OptLevel>,
+    #[arg(long, value_enum, conflicts_with = "no_lto")]
+    lto: Option<Lto>,
+    /// Build this variant without the shared --lto
+    #[arg(long)]
+    no_lto: bool,
+    #[arg(long, value_enum)]
+    codegen_backend: Option<Backend>,
+    #[arg(long, value_enum)]
+    relocation_model: Option<RelocModel>,
+    #[arg(long, value_name = "CPU")]
+    target_cpu: Option<String>,
+    /// Replaces the shared --target-feature list when given
+    #[arg(long, value_name = "FEATURE")]
+    target_feature: Vec<String>,
+}
+
+impl VariantOpts {
+    /// Applies these flags over the options shared by both variants
+    pub(crate) fn apply(&self, opts: &mut CompileOpts) {
+        let args = &self.args;
+        if let Some(level) = args.opt_level {
+            opts.opt_level = level;
+        }
+        if args.no_lto {
+            opts.lto = None;
+        } else if args.lto.is_some() {
+            opts.lto = args.lto;
+        }
+        if let Some(backend) = args.codegen_backend {
+            opts.codegen_backend = backend;
+        }
+        if args.relocation_model.is_some() {
+            opts.relocation_model = args.relocation_model;
+            opts.no_pie = false;
+        }
+        if args.target_cpu.is_some() {
+            opts.target_cpu = args.target_cpu.clone();
+        }
+        if !args.target_feature.is_empty() {
+            opts.target_feature = args.target_feature.clone();
+        }
+    }
+}
+
+impl FromStr for VariantOpts {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<VariantOpts> {
+        let flags: Vec<String> = s.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|f| !f.is_empty())
+            .map(str::to_string)
+            .collect();
+        // Just clap's first line; its usage line would name no command
+        let args = VariantArgs::try_parse_from(&flags).map_err(|e| {
+            let message = e.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            anyhow!("{}", first.trim_start_matches("error: "))
+        })?;
+
+        Ok(VariantOpts { flags, args })
+    }
+}
+
+/// Comma-separated, so the flags stay one word on a command line
+impl fmt::Display for VariantOpts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.flags.join(","))
+    }
+}
+
+impl TryFrom<String> for VariantOpts {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<VariantOpts> {
+        s.parse()
+    }
+}
+
+impl From<VariantOpts> for String {
+    fn from(opts: VariantOpts) -> String {
+        opts.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VariantOpts;
+
+    #[test]
+    fn parses_spaced_and_comma_separated_flags() {
+        let opts: VariantOpts = "--lto fat  --opt-level 3".parse().unwrap();
+        assert_eq!(opts.to_string(), "--lto,fat,--opt-level,3");
+        let again: VariantOpts = opts.to_string().parse().unwrap();
+        assert_eq!(again.to_string(), opts.to_string());
+    }
+
+    #[test]
+    fn rejects_options_shared_by_both_variants() {
+        assert!("--measure-compile".parse::<VariantOpts>().is_err());
+        assert!("--lto fat --no-lto".parse::<VariantOpts>().is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Regenerating, compiling and running one small case whenever watched
+//! files change, for a tight loop while experimenting with the shape of
+//! the generated code.
+//!
+//! Each phase runs as a fresh `dispatch-test` process started from the
+//! path this one was started from. That executable is always watched,
+//! so rebuilding dispatch-test after editing the templates (say with
+//! `cargo watch -x build` in another terminal) reruns the case with the
+//! new templates.
+
+use anyhow::{Result, bail};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::{results, summary, CaseConfig, CompileOpts, Dispatch, GenOpts};
+
+static POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Editors and cargo write files in several steps
+static SETTLE_TIME: Duration = Duration::from_millis(300);
+
+pub struct WatchConfig {
+    pub outdir: PathBuf,
+    pub run_name: String,
+    pub num_types: u32,
+    pub num_fns: u32,
+    pub paths: Vec<PathBuf>,
+    pub gen_opts: GenOpts,
+    pub compile_opts: CompileOpts,
+}
+
+pub fn watch(config: WatchConfig) -> Result<()> {
+    let exe = env::current_exe()?;
+    let mut paths = config.paths.clone();
+    paths.push(exe.clone());
+
+    println!("watching {} for changes",
+             paths.iter().map(|p| p.display().to_string())
+             .collect::<Vec<_>>().join(", "));
+
+    let mut last_change = None;
+    loop {
+        let change = latest_mtime(&paths);
+        if change != last_change {
+            if last_change.is_some() {
+                thread::sleep(SETTLE_TIME);
+            }
+            last_change = latest_mtime(&paths);
+            if let Err(e) = iterate(&exe, &config) {
+                println!("error: {}", e);
+            }
+            println!("watching for changes");
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn iterate(exe: &Path, config: &WatchConfig) -> Result<()> {
+    let case_args = [config.num_types.to_string(), config.num_fns.to_string()];
+    let gen_flags = summary::gen_flags(&config.gen_opts).join(" ");
+    let compile_flags = summary::compile_flags(&config.compile_opts).join(" ");
+
+    let phases = [
+        ("gen-one-case", gen_flags),
+        ("compile-one-case", compile_flags),
+        ("run-one-case", String::new()),
+    ];
+    for (phase, flags) in phases.iter() {
+        let status = Command::new(exe)
+            .arg("--outdir").arg(&config.outdir)
+            .arg("--run-name").arg(&config.run_name)
+            .arg(phase)
+            .args(&case_args)
+            .args(flags.split_whitespace())
+            .status()?;
+
+        if !status.success() {
+            bail!("{} failed", phase);
+        }
+    }
+
+    let case = CaseConfig {
+        outdir: config.outdir.join(&config.run_name),
+        num_types: config.num_types,
+        num_fns: config.num_fns,
+    };
+    let run = results::find_case(&case)?.and_then(|c| c.run);
+    let times = run.as_ref().and_then(|r| {
+        Some((r.variant(Dispatch::Static)?.run_time_ms,
+              r.variant(Dispatch::Dynamic)?.run_time_ms))
+    });
+    if let Some((s, d)) = times {
+        println!("dynamic/static run time: {} / {} ms = {:.2}x",
+                 d, s, d as f64 / s.max(1) as f64);
+    }
+
+    Ok(())
+}
+
+/// The most recent modification time of anything under the paths.
+/// Missing paths are skipped so a file can be watched before it exists.
+fn latest_mtime(paths: &[PathBuf]) -> Option<SystemTime> {
+    paths.iter().filter_map(|p| mtime_under(p)).max()
+}
+
+fn mtime_under(path: &Path) -> Option<SystemTime> {
+    let meta = fs::metadata(path).ok()?;
+    let own = meta.modified().ok();
+    if !meta.is_dir() {
+        return own;
+    }
+
+    let entries = fs::read_dir(path).ok()?;
+    entries
+        .filter_map(|e| mtime_under(&e.ok()?.path()))
+        .chain(own)
+        .max()
+}
@@ -0,0 +1,205 @@
+//! Tests of the generated case sources.
+//!
+//! Golden files live in `tests/golden`. After an intended change to the
+//! generators, bump `GENERATOR_VERSION` in src/hash.rs and regenerate
+//! them with `UPDATE_GOLDEN=1 cargo test`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+fn dispatch_test(outdir: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_dispatch-test"));
+    cmd.arg("--outdir").arg(outdir);
+    cmd.arg("--run-name").arg("test");
+    // The generated cases use nightly features
+    cmd.env("RUSTC_BOOTSTRAP", "1");
+    cmd
+}
+
+fn run(cmd: &mut Command) {
+    let output = cmd.output().expect("spawning dispatch-test");
+    assert!(output.status.success(),
+            "{:?} failed:\n{}\n{}", cmd,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr));
+}
+
+fn gen_one_case(num_types: u32, num_fns: u32, flags: &[&str]) -> (TempDir, PathBuf) {
+    let dir = TempDir::new().unwrap();
+    run(dispatch_test(dir.path())
+        .arg("gen-one-case")
+        .arg(num_types.to_string())
+        .arg(num_fns.to_string())
+        .args(flags));
+    let run_dir = dir.path().join("test");
+    (dir, run_dir)
+}
+
+fn hash_line(text: &str) -> Option<&str> {
+    text.lines().find(|l| l.starts_with("// dispatch-test-hash: "))
+}
+
+fn check_golden(name: &str, num_types: u32, num_fns: u32, flags: &[&str]) {
+    let (_dir, run_dir) = gen_one_case(num_types, num_fns, flags);
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests").join("golden").join(name);
+
+    let ext = if flags.contains(&"cpp") { "cpp" } else { "rs" };
+
+    for variant in &["static", "dynamic"] {
+        let file = format!("{}-{:04}-{:04}.{}", variant, num_types, num_fns, ext);
+        let actual = fs::read_to_string(run_dir.join(&file)).unwrap();
+        let golden_path = golden_dir.join(&file);
+
+        let golden = fs::read_to_string(&golden_path);
+        // The hash line is the same only if GENERATOR_VERSION is
+        if let Ok(ref expected) = golden {
+            assert!(actual == *expected || hash_line(&actual) != hash_line(expected),
+                    "{} differs from {} under the same hash; bump GENERATOR_VERSION \
+                     in src/hash.rs", file, golden_path.display());
+        }
+
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(&golden_dir).unwrap();
+            fs::write(&golden_path, &actual).unwrap();
+            continue;
+        }
+
+        let expected = golden
+            .unwrap_or_else(|e| panic!("reading {}: {}", golden_path.display(), e));
+        assert!(actual == expected,
+                "{} differs from {}; rerun with UPDATE_GOLDEN=1 if intended",
+                file, golden_path.display());
+    }
+}
+
+#[test]
+fn golden_default() {
+    check_golden("default", 2, 3, &[]);
+}
+
+#[test]
+fn golden_all_gen_flags() {
+    check_golden("all-gen-flags", 2, 3,
+                 &["--no-inline", "--no-dedup", "--predictable"]);
+}
+
+#[test]
+fn golden_no_types() {
+    check_golden("no-types", 0, 2, &[]);
+}
+
+#[test]
+fn golden_no_fns() {
+    check_golden("no-fns", 2, 0, &[]);
+}
+
+#[test]
+fn golden_cpp() {
+    check_golden("cpp", 2, 3, &["--lang", "cpp"]);
+}
+
+/// Type-checks a generated source the way the sweeps build it, failing
+/// with rustc's errors
+fn check_types(src: &Path, out_dir: &Path, flags: &[&str]) {
+    let output = Command::new("rustc")
+        .arg(src)
+        .args(["--emit=metadata", "--cap-lints", "allow", "-o"])
+        .arg(out_dir.join("check.rmeta"))
+        // The -Z paths need nightly features
+        .env("RUSTC_BOOTSTRAP", "1")
+        .output()
+        .expect("spawning rustc");
+    assert!(output.status.success(),
+            "{} with {:?} doesn't type-check:\n{}",
+            src.display(), flags, String::from_utf8_lossy(&output.stderr));
+}
+
+/// Every combination of a small grid and the gen flags type-checks
+#[test]
+fn grid_type_checks() {
+    let flag_sets: &[&[&str]] = &[
+        &[],
+        &["--no-inline"],
+        &["--no-dedup"],
+        &["--predictable"],
+        &["--keep-symbols"],
+        &["--no-inline", "--no-dedup", "--predictable"],
+        &["--assoc-type"],
+        &["--generic-method"],
+        &["--assoc-type", "--generic-method", "--keep-symbols"],
+        &["--object-safety-stress", "--assoc-type", "--holder"],
+        &["--const-generic", "--keep-symbols", "--predictable"],
+        &["--specialization", "--generic-method", "--holder"],
+        &["--dynamic-via", "jump-table", "--object-safety-stress", "--keep-symbols"],
+        &["--dynamic-via", "branch-tree", "--branch-order", "uniform"],
+        &["--skew", "zipf", "--predictable", "--structs", "1"],
+        &["--skew", "single", "--dynamic-via", "branch-tree"],
+        &["--site-types", "1", "--predictable", "--structs", "2"],
+        &["--trait-depth", "2", "--upcast", "--assoc-type", "--keep-symbols"],
+        &["--visitor", "--object-safety-stress", "--keep-symbols"],
+        &["--cold-start", "--predictable", "--skew", "zipf"],
+        &["--threads", "2", "--assoc-type", "--annotate", "--keep-symbols"],
+        &["--tasks", "3", "--holder", "--annotate"],
+        &["--tasks", "2", "--dynamic-via", "jump-table", "--predictable"],
+        &["--errors", "hot", "--keep-symbols", "--trait-depth", "1", "--upcast"],
+        &["--errors", "rare", "--annotate", "--no-inline"],
+        &["--static-via", "enum", "--dynamic-via", "closure", "--keep-symbols", "--annotate"],
+        &["--static-via", "enum", "--dynamic-via", "closure", "--tasks", "2", "--generic-method"],
+        &["--components", "4", "--object-safety-stress", "--keep-symbols", "--annotate"],
+        &["--components", "4", "--assoc-type", "--tasks", "2"],
+        &["--dynamic-via", "fn-table", "--object-safety-stress", "--keep-symbols"],
+        &["--state-machine", "--generic-method", "--annotate", "--keep-symbols"],
+        &["--call-shape", "outlined", "--structs", "2", "--annotate"],
+        &["--call-shape", "indirect-through-array", "--visitor", "--tasks", "2"],
+        &["--vary-args", "--structs", "2", "--annotate", "--keep-symbols"],
+        &["--vary-args", "--dynamic-via", "jump-table", "--no-inline"],
+        &["--checksum", "--errors", "hot", "--annotate", "--keep-symbols"],
+        &["--checksum", "--state-machine", "--assoc-type", "--no-dedup"],
+        &["--blackbox", "asm", "--vary-args", "--holder", "--annotate"],
+        &["--blackbox", "volatile", "--object-safety-stress", "--tasks", "2"],
+        &["--blackbox", "none", "--errors", "hot", "--keep-symbols"],
+        &["--concrete", "--checksum", "--errors", "hot", "--keep-symbols", "--annotate"],
+        &["--concrete", "--vary-args", "--static-via", "enum", "--no-inline"],
+        &["--concrete", "--tasks", "2", "--call-shape", "outlined", "--skew", "zipf"],
+    ];
+
+    for flags in flag_sets {
+        let dir = TempDir::new().unwrap();
+        run(dispatch_test(dir.path())
+            .arg("gen-all-cases")
+            .args(["--types", "0..3", "--fns", "0..3"])
+            .args(*flags));
+
+        let mut count = 0;
+        for entry in fs::read_dir(dir.path().join("test")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "rs") {
+                continue;
+            }
+            check_types(&path, dir.path(), flags);
+            count += 1;
+        }
+        let programs = if flags.contains(&"--concrete") { 3 } else { 2 };
+        assert_eq!(count, 4 * 4 * programs);
+    }
+}
+
+/// A tiny case makes it through every phase
+#[test]
+fn smoke_compile_and_run() {
+    let dir = TempDir::new().unwrap();
+    run(dispatch_test(dir.path()).args(["gen-all-cases", "--types", "2", "--fns", "1,2"]));
+    run(dispatch_test(dir.path()).arg("compile-all-cases"));
+    run(dispatch_test(dir.path()).arg("run-all-cases"));
+
+    for fns in 1..=2 {
+        for variant in &["static", "dynamic"] {
+            let bin = format!("{}-0002-{:04}.bin", variant, fns);
+            assert!(dir.path().join("test").join(bin).exists());
+        }
+    }
+}
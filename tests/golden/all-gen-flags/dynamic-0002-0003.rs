@@ -0,0 +1,73 @@
+// types = 2, fns = 3
+// dispatch-test-hash: 9e8ddad94b01528f
+
+
+#![feature(test)]
+
+use std::hint::black_box;
+
+trait Io {
+    fn do_io_m(&self);
+}
+
+struct T0(u8);
+impl Io for T0 {
+    #[inline(never)]
+    fn do_io_m(&self) {
+        black_box(self);
+        if true {
+            black_box(&0);
+        }
+    }
+}
+
+struct T1(u8);
+impl Io for T1 {
+    #[inline(never)]
+    fn do_io_m(&self) {
+        black_box(self);
+        if true {
+            black_box(&1);
+        }
+    }
+}
+
+#[inline(never)]
+fn do_io_f0(v: &dyn Io) {
+    v.do_io_m();
+    if true {
+        black_box(&0);
+    }
+}
+
+#[inline(never)]
+fn do_io_f1(v: &dyn Io) {
+    v.do_io_m();
+    if true {
+        black_box(&1);
+    }
+}
+
+#[inline(never)]
+fn do_io_f2(v: &dyn Io) {
+    v.do_io_m();
+    if true {
+        black_box(&2);
+    }
+}
+
+fn main() {
+    static V0: &T0 = &T0(0_u8);
+    static V1: &T1 = &T1(0_u8);
+
+    for _ in 0..100000 {
+        do_io_f0(V0);
+        do_io_f1(V0);
+        do_io_f2(V0);
+
+        do_io_f0(V1);
+        do_io_f1(V1);
+        do_io_f2(V1);
+
+    }
+}
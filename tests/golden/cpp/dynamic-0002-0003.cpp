@@ -0,0 +1,73 @@
+// types = 2, fns = 3
+// dispatch-test-hash: bb624d4b927307c3
+
+#include <cstdint>
+
+template <typename T>
+void black_box(T const& v) { asm volatile("" : : "r"(&v) : "memory"); }
+
+struct Io { virtual void do_io_m() const = 0; };
+
+
+struct T0 final : Io {
+    uint8_t v;
+    void do_io_m() const override {
+        black_box(this);
+        if (false) {
+            black_box(0);
+        }
+    }
+};
+
+
+struct T1 final : Io {
+    uint8_t v;
+    void do_io_m() const override {
+        black_box(this);
+        if (false) {
+            black_box(1);
+        }
+    }
+};
+
+
+void do_io_f0(Io const& v) {
+    v.do_io_m();
+    if (false) {
+        black_box(0);
+    }
+}
+
+
+void do_io_f1(Io const& v) {
+    v.do_io_m();
+    if (false) {
+        black_box(1);
+    }
+}
+
+
+void do_io_f2(Io const& v) {
+    v.do_io_m();
+    if (false) {
+        black_box(2);
+    }
+}
+
+
+int main() {
+    static T0 V0;
+    static T1 V1;
+
+    for (long i = 0; i < 100000; i++) {
+        do_io_f0(V0);
+        do_io_f0(V1);
+
+        do_io_f1(V0);
+        do_io_f1(V1);
+
+        do_io_f2(V0);
+        do_io_f2(V1);
+
+    }
+}
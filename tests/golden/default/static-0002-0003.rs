@@ -0,0 +1,69 @@
+// types = 2, fns = 3
+// dispatch-test-hash: 0f7273f9360c0eab
+
+
+#![feature(test)]
+
+use std::hint::black_box;
+
+trait Io {
+    fn do_io_m(&self);
+}
+
+struct T0(u8);
+impl Io for T0 {
+    fn do_io_m(&self) {
+        black_box(self);
+        if false {
+            black_box(&0);
+        }
+    }
+}
+
+struct T1(u8);
+impl Io for T1 {
+    fn do_io_m(&self) {
+        black_box(self);
+        if false {
+            black_box(&1);
+        }
+    }
+}
+
+fn do_io_f0<T: Io>(v: &T) {
+    v.do_io_m();
+    if false {
+        black_box(&0);
+    }
+}
+
+fn do_io_f1<T: Io>(v: &T) {
+    v.do_io_m();
+    if false {
+        black_box(&1);
+    }
+}
+
+fn do_io_f2<T: Io>(v: &T) {
+    v.do_io_m();
+    if false {
+        black_box(&2);
+    }
+}
+
+fn main() {
+    static V0: &T0 = &T0(0_u8);
+    static V1: &T1 = &T1(0_u8);
+
+    for _ in 0..100000 {
+        do_io_f0(V0);
+        do_io_f0(V1);
+
+        do_io_f1(V0);
+        do_io_f1(V1);
+
+        do_io_f2(V0);
+        do_io_f2(V1);
+
+    }
+}
@@ -0,0 +1,42 @@
+// types = 2, fns = 0
+// dispatch-test-hash: cfdb624315ac4983
+
+#![allow(unused)]
+
+#![feature(test)]
+
+use std::hint::black_box;
+
+trait Io {
+    fn do_io_m(&self);
+}
+
+struct T0(u8);
+impl Io for T0 {
+    fn do_io_m(&self) {
+        black_box(self);
+        if false {
+            black_box(&0);
+        }
+    }
+}
+
+struct T1(u8);
+impl Io for T1 {
+    fn do_io_m(&self) {
+        black_box(self);
+        if false {
+            black_box(&1);
+        }
+    }
+}
+
+fn main() {
+    static V0: &T0 = &T0(0_u8);
+    static V1: &T1 = &T1(0_u8);
+
+    for _ in 0..100000 {
+        V0.do_io_m();
+        V1.do_io_m();
+    }
+}
@@ -0,0 +1,33 @@
+// types = 0, fns = 2
+// dispatch-test-hash: d2deb2e34b453de2
+
+#![allow(unused)]
+
+#![feature(test)]
+
+use std::hint::black_box;
+
+trait Io {
+    fn do_io_m(&self);
+}
+
+fn do_io_f0<T: Io>(v: &T) {
+    v.do_io_m();
+    if false {
+        black_box(&0);
+    }
+}
+
+fn do_io_f1<T: Io>(v: &T) {
+    v.do_io_m();
+    if false {
+        black_box(&1);
+    }
+}
+
+fn main() {
+
+    for _ in 0..100000 {
+        black_box(());
+    }
+}